@@ -104,3 +104,139 @@ impl BigWigFile {
         })
     }
 }
+
+pub struct BigWigWriter {
+    handle: *mut bigWigFile_t,
+    chroms: Vec<CString>,
+    current_chrom: Option<usize>,
+}
+
+impl Drop for BigWigWriter {
+    fn drop(&mut self) {
+        // Closing a BigWig file opened for write flushes the last data block and writes the
+        // zoom levels and the index
+        unsafe {
+            bwClose(self.handle);
+        }
+    }
+}
+
+impl BigWigWriter {
+    /// Create a new BigWig file with the given chromosome list and the maximum number of zoom
+    /// levels
+    pub fn create<P: AsRef<Path>>(
+        path: P,
+        chroms: &[(String, usize)],
+        max_zoom_levels: u32,
+    ) -> Result<Self, std::io::Error> {
+        let io_error = |what: &str| std::io::Error::other(what.to_string());
+        let chrom_names = chroms
+            .iter()
+            .map(|(name, _)| CString::new(name.as_str()))
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|_| io_error("Invalid chromosome name"))?;
+        let mut name_ptrs: Vec<_> = chrom_names
+            .iter()
+            .map(|name| name.as_ptr() as *mut _)
+            .collect();
+        let mut lengths: Vec<u32> = chroms.iter().map(|(_, size)| *size as u32).collect();
+
+        let handle = unsafe {
+            let path_buf = CString::new(path.as_ref().as_os_str().as_bytes()).unwrap();
+            let mod_buf = CString::new("w").unwrap();
+            bwOpen(
+                path_buf.as_ptr() as *mut _,
+                None,
+                mod_buf.as_ptr() as *mut _,
+            )
+        };
+
+        if handle.is_null() {
+            return Err(io_error("Cannot create BW file"));
+        }
+
+        // Until the header is written, the handle isn't a valid BigWig file yet, thus it must be
+        // released without being finalized on failure
+        let discard = move |what: &str| {
+            unsafe {
+                (*handle).isWrite = 0;
+                bwClose(handle);
+            }
+            Err(io_error(what))
+        };
+
+        unsafe {
+            if bwCreateHdr(handle, max_zoom_levels as i32) != 0 {
+                return discard("Cannot create BW header");
+            }
+            // The chrom list makes its own copy of the names and lengths
+            (*handle).cl = bwCreateChromList(
+                name_ptrs.as_mut_ptr(),
+                lengths.as_mut_ptr(),
+                chroms.len() as i64,
+            );
+            if (*handle).cl.is_null() {
+                return discard("Cannot create BW chromosome list");
+            }
+            if bwWriteHdr(handle) != 0 {
+                return discard("Cannot write BW header");
+            }
+        }
+
+        Ok(BigWigWriter {
+            handle,
+            chroms: chrom_names,
+            current_chrom: None,
+        })
+    }
+
+    /// Append intervals to the file. The intervals should be sorted and non-overlapping, and the
+    /// chromosomes should be written in the same order as the chromosome list.
+    pub fn add_intervals(
+        &mut self,
+        chrom: &str,
+        intervals: &[BigWigInterval],
+    ) -> Result<(), std::io::Error> {
+        if intervals.is_empty() {
+            return Ok(());
+        }
+        let chrom_idx = self
+            .chroms
+            .iter()
+            .position(|name| name.as_bytes() == chrom.as_bytes())
+            .ok_or_else(|| std::io::Error::other("Unknown chromosome"))?;
+        let mut begins: Vec<u32> = intervals.iter().map(|i| i.begin).collect();
+        let mut ends: Vec<u32> = intervals.iter().map(|i| i.end).collect();
+        let mut values: Vec<f32> = intervals.iter().map(|i| i.value).collect();
+        let n = intervals.len() as u32;
+
+        let rc = unsafe {
+            if self.current_chrom == Some(chrom_idx) {
+                bwAppendIntervals(
+                    self.handle,
+                    begins.as_mut_ptr(),
+                    ends.as_mut_ptr(),
+                    values.as_mut_ptr(),
+                    n,
+                )
+            } else {
+                let mut chrom_ptrs =
+                    vec![self.chroms[chrom_idx].as_ptr() as *mut _; intervals.len()];
+                bwAddIntervals(
+                    self.handle,
+                    chrom_ptrs.as_mut_ptr(),
+                    begins.as_mut_ptr(),
+                    ends.as_mut_ptr(),
+                    values.as_mut_ptr(),
+                    n,
+                )
+            }
+        };
+
+        if rc != 0 {
+            return Err(std::io::Error::other("Cannot write BW intervals"));
+        }
+        self.current_chrom = Some(chrom_idx);
+        Ok(())
+    }
+}
@@ -83,6 +83,38 @@ impl<T: Read + Write + Seek> DirectoryImpl<T> {
         self.entries.push(new_entry);
         Ok(())
     }
+    /// Replace the entry with the same name, the replaced object is left in the file but it's no
+    /// longer reachable. If there's no such entry, the new entry is appended to the directory.
+    fn replace_entry(&mut self, new_entry: Entry) -> Result<()> {
+        let idx = match self.entries.iter().position(|x| x.name == new_entry.name) {
+            Some(idx) => idx,
+            None => return self.append_directory(new_entry),
+        };
+        self.entries[idx] = new_entry;
+        // The size of the serialized entry doesn't change, so we can rewrite the entry list in
+        // place. But the pending data should be written to the file before we overwrite it.
+        self.stream.flush()?;
+        let file = self.stream.clone_underlying_file();
+        let mut stream =
+            Stream::open_for_update(file, (self.offset, Directory::<T>::INIT_BLOCK_SIZE))?;
+        for entry in self.entries.iter() {
+            stream.write(&[1, entry.kind as u8])?;
+            stream.write(&(entry.primary_offset - self.offset).to_le_bytes())?;
+            stream.write(&entry.primary_size.to_le_bytes())?;
+            stream.write(entry.name.as_bytes())?;
+            stream.write(&[0])?;
+        }
+        stream.write(&[0])?;
+        self.stream = stream;
+        Ok(())
+    }
+    fn add_entry(&mut self, new_entry: Entry, replace: bool) -> Result<()> {
+        if replace {
+            self.replace_entry(new_entry)
+        } else {
+            self.append_directory(new_entry)
+        }
+    }
 }
 
 pub struct Directory<T>(Arc<RwLock<DirectoryImpl<T>>>);
@@ -142,18 +174,30 @@ impl<T: Read + Write + Seek> Directory<T> {
     }
 
     pub fn create_blob(&mut self, name: &str, size: usize) -> Result<Blob<T>> {
+        self.create_blob_impl(name, size, false)
+    }
+
+    /// Create a blob that replaces the existing object with the same name
+    pub fn replace_blob(&mut self, name: &str, size: usize) -> Result<Blob<T>> {
+        self.create_blob_impl(name, size, true)
+    }
+
+    fn create_blob_impl(&mut self, name: &str, size: usize, replace: bool) -> Result<Blob<T>> {
         let mut inner = self
             .0
             .write()
             .map_err(|_| Error::new(ErrorKind::Other, "Lock Error"))?;
         let mut file = inner.stream.clone_underlying_file();
         let offset = file.reserve_block(size)?;
-        inner.append_directory(Entry {
-            kind: EntryKind::Blob,
-            primary_offset: offset,
-            primary_size: size as u64,
-            name: name.to_string(),
-        })?;
+        inner.add_entry(
+            Entry {
+                kind: EntryKind::Blob,
+                primary_offset: offset,
+                primary_size: size as u64,
+                name: name.to_string(),
+            },
+            replace,
+        )?;
         Ok(Blob::new(file, offset, size))
     }
 
@@ -169,6 +213,22 @@ impl<T: Read + Write + Seek> Directory<T> {
     }
 
     pub fn create_directory(&mut self, name: &str) -> Result<Directory<T>>
+    where
+        T: Send + 'static,
+    {
+        self.create_directory_impl(name, false)
+    }
+
+    /// Create a sub-directory that replaces the existing object with the same name. Similar to
+    /// `create_directory`, the entry is updated once the returned directory is dropped.
+    pub fn replace_directory(&mut self, name: &str) -> Result<Directory<T>>
+    where
+        T: Send + 'static,
+    {
+        self.create_directory_impl(name, true)
+    }
+
+    fn create_directory_impl(&mut self, name: &str, replace: bool) -> Result<Directory<T>>
     where
         T: Send + 'static,
     {
@@ -193,7 +253,7 @@ impl<T: Read + Write + Seek> Directory<T> {
                     primary_size,
                     name,
                 };
-                inner.append_directory(entry).unwrap();
+                inner.add_entry(entry, replace).unwrap();
             }))?
         };
         let stream = Stream::create(file, Self::INIT_BLOCK_SIZE)?;
@@ -205,23 +265,56 @@ impl<T: Read + Write + Seek> Directory<T> {
         }))))
     }
     pub fn create_stream(&mut self, name: &str, frame_size: usize) -> Result<Stream<T>> {
+        self.create_stream_impl(name, frame_size, false)
+    }
+
+    /// Create a stream that replaces the existing object with the same name
+    pub fn replace_stream(&mut self, name: &str, frame_size: usize) -> Result<Stream<T>> {
+        self.create_stream_impl(name, frame_size, true)
+    }
+
+    fn create_stream_impl(
+        &mut self,
+        name: &str,
+        frame_size: usize,
+        replace: bool,
+    ) -> Result<Stream<T>> {
         let mut inner = self
             .0
             .write()
             .map_err(|_| Error::new(ErrorKind::Other, "Lock Error"))?;
         let file = inner.stream.clone_underlying_file();
         let stream = Stream::create(file, frame_size)?;
-        inner.append_directory(Entry {
-            kind: EntryKind::Stream,
-            primary_offset: stream.get_frame_offset().unwrap(),
-            primary_size: stream.get_frame_size().unwrap() as u64,
-            name: name.to_string(),
-        })?;
+        inner.add_entry(
+            Entry {
+                kind: EntryKind::Stream,
+                primary_offset: stream.get_frame_offset().unwrap(),
+                primary_size: stream.get_frame_size().unwrap() as u64,
+                name: name.to_string(),
+            },
+            replace,
+        )?;
         Ok(stream)
     }
     pub fn open_directory_for_update(&self, name: &str) -> Result<Directory<T>> {
         self.open_directory_impl(name, Self::open_directory_rw_impl)
     }
+
+    /// Grow the size of the sub-directory so that it covers the end of the file. This is needed
+    /// once the objects in an updated sub-directory are replaced, since the new objects are
+    /// appended to the end of the file, and a mapped directory only sees the data in its range.
+    pub fn extend_directory_to_end(&mut self, name: &str) -> Result<()> {
+        let mut inner = self.0.write().map_err(|_| Error::other("Lock Error"))?;
+        let mut entry = inner
+            .entries
+            .iter()
+            .find(|e| e.name == name && e.kind == EntryKind::SubDir)
+            .cloned()
+            .ok_or_else(|| Error::other("Directory not found"))?;
+        let file_size = inner.stream.clone_underlying_file().size()?;
+        entry.primary_size = entry.primary_size.max(file_size - entry.primary_offset);
+        inner.replace_entry(entry)
+    }
 }
 
 #[cfg(all(feature = "mapped_io", not(target_arch = "wasm32")))]
@@ -588,6 +681,60 @@ mod test {
         Ok(())
     }
     #[test]
+    fn test_replace_entry() -> std::result::Result<(), Box<dyn std::error::Error>> {
+        let buf = {
+            let cursor = Cursor::new(vec![]);
+            let mut dir = Directory::make_root(cursor)?;
+            for i in 0..10 {
+                let stream_name = format!("test_stream.{}", i);
+                let mut test_stream = dir.create_stream(stream_name.as_str(), 32)?;
+                test_stream.write(b"old data")?;
+            }
+            dir.create_blob("test_blob", 16)?;
+            dir.flush()?;
+            dir.clone_underlying_file()?.clone_inner()?.into_inner()
+        };
+        let buf = {
+            let backend = Cursor::new(buf);
+            let mut root = Directory::open_root_for_update(backend, 0)?;
+            {
+                let mut stream = root.replace_stream("test_stream.5", 32)?;
+                stream.write(b"new data")?;
+            }
+            root.replace_blob("test_blob", 64)?;
+            {
+                let mut sub_dir = root.replace_directory("test_stream.7")?;
+                sub_dir.create_stream("nested", 32)?.write(b"nested data")?;
+            }
+            root.create_stream("test_stream.10", 32)?
+                .write(b"appended data")?;
+            root.flush()?;
+            root.clone_underlying_file()?.clone_inner()?.into_inner()
+        };
+
+        let backend = Cursor::new(buf);
+        let root = Directory::open_root(backend, 0)?;
+        assert_eq!(root.entries().len(), 12);
+        let read_stream = |dir: &Directory<_>, name: &str| -> Result<Vec<u8>> {
+            let mut stream = dir.open_stream(name)?;
+            let mut data = vec![0; 32];
+            let size = stream.read(&mut data)?;
+            data.truncate(size);
+            while data.last() == Some(&0) {
+                data.pop();
+            }
+            Ok(data)
+        };
+        assert_eq!(read_stream(&root, "test_stream.4")?, b"old data");
+        assert_eq!(read_stream(&root, "test_stream.5")?, b"new data");
+        assert_eq!(read_stream(&root, "test_stream.10")?, b"appended data");
+        assert_eq!(root.open_blob("test_blob")?.size(), 64);
+        assert_eq!(root.entry_kind("test_stream.7"), Some(EntryKind::SubDir));
+        let sub_dir = root.open_directory("test_stream.7")?;
+        assert_eq!(read_stream(&sub_dir, "nested")?, b"nested data");
+        Ok(())
+    }
+    #[test]
     fn test_stream_cluster() -> Result<()> {
         let buf = {
             let cursor = Cursor::new(vec![]);
@@ -36,4 +36,17 @@ impl<'a> Alignment<'a> {
     pub fn ref_id(&self) -> i32 {
         self.hts_obj().core.tid
     }
+
+    pub fn is_reverse_strand(&self) -> bool {
+        self.flag() & 0x10 != 0
+    }
+
+    pub fn is_proper_pair(&self) -> bool {
+        self.flag() & 0x1 != 0 && self.flag() & 0x2 != 0
+    }
+
+    /// The observed template length (TLEN), positive for the leftmost mate of a pair
+    pub fn insert_size(&self) -> i64 {
+        self.hts_obj().core.isize_
+    }
 }
@@ -10,4 +10,5 @@ mod seq_ext;
 
 pub use alignment_impl::{Alignment, AlignmentIter, AlignmentReader};
 pub use bamfile::BamFile;
+pub use cigar_ext::{Cigar, CigarOps};
 pub use nucleotide::Nucleotide;
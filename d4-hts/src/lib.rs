@@ -5,18 +5,83 @@ use std::collections::BinaryHeap;
 pub mod alignment;
 pub use alignment::*;
 
+/// Describes how an alignment contributes to the depth profile
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Default)]
+pub enum DepthMode {
+    /// Each read covers its whole reference span, including deletions and ref-skips
+    #[default]
+    ReadSpan,
+    /// Each read only covers its aligned blocks, the selected CIGAR gap operations are excluded
+    ExcludeGaps { deletion: bool, ref_skip: bool },
+    /// Each properly paired read pair covers its whole fragment, determined by the insert size.
+    /// Reads that are not properly paired are ignored.
+    Fragment,
+}
+
+impl DepthMode {
+    /// Compute the closed reference intervals covered by the read, returns None if the read
+    /// should not be counted
+    fn covered_segments(&self, read: &Alignment<'_>) -> Option<Vec<(usize, usize)>> {
+        let begin = read.ref_begin();
+        match self {
+            DepthMode::ReadSpan => Some(vec![(begin, read.ref_end())]),
+            DepthMode::ExcludeGaps { deletion, ref_skip } => {
+                let mut segments: Vec<(usize, usize)> = vec![];
+                let mut pos = begin;
+                for cigar in read.cigar().filter(|c| c.in_reference() && c.len > 0) {
+                    let len = cigar.len as usize;
+                    let excluded = match cigar.op {
+                        CigarOps::Delete => *deletion,
+                        CigarOps::Skip => *ref_skip,
+                        _ => false,
+                    };
+                    if !excluded {
+                        match segments.last_mut() {
+                            Some(last) if last.1 + 1 == pos => last.1 = pos + len - 1,
+                            _ => segments.push((pos, pos + len - 1)),
+                        }
+                    }
+                    pos += len;
+                }
+                Some(segments).filter(|s| !s.is_empty())
+            }
+            DepthMode::Fragment => {
+                let insert_size = read.insert_size();
+                if read.is_proper_pair() && insert_size > 0 {
+                    Some(vec![(begin, begin + insert_size as usize - 1)])
+                } else {
+                    None
+                }
+            }
+        }
+    }
+}
+
+/// A loaded read that hasn't been pushed yet: (chrom id, begin, covered segments)
+type PendingRead = (i32, usize, Vec<(usize, usize)>);
+
 pub struct DepthIter<'a, R: AlignmentReader<'a>> {
     iter: AlignmentIter<'a, R>,
     cur_chrom: i32,
     cur_pos: usize,
     heap: BinaryHeap<Reverse<usize>>,
-    next_read: Option<(i32, usize, usize)>,
+    pending: BinaryHeap<Reverse<(usize, usize)>>,
+    next_read: Option<PendingRead>,
+    mode: DepthMode,
     #[allow(clippy::type_complexity)]
     filter: Option<Box<dyn Fn(&Alignment<'_>) -> bool + 'a>>,
 }
 
 impl<'a, R: AlignmentReader<'a>> DepthIter<'a, R> {
     pub fn with_filter<F: Fn(&Alignment<'_>) -> bool + 'a>(reader: R, filter: F) -> Self {
+        Self::with_filter_and_mode(reader, filter, DepthMode::default())
+    }
+
+    pub fn with_filter_and_mode<F: Fn(&Alignment<'_>) -> bool + 'a>(
+        reader: R,
+        filter: F,
+        mode: DepthMode,
+    ) -> Self {
         let (chrom, pos) = reader.start();
         let iter = reader.into_alignment_iter();
 
@@ -26,6 +91,8 @@ impl<'a, R: AlignmentReader<'a>> DepthIter<'a, R> {
             cur_chrom: chrom as i32,
             cur_pos: pos as usize,
             heap: BinaryHeap::new(),
+            pending: BinaryHeap::new(),
+            mode,
             filter: Some(Box::new(filter)),
         };
 
@@ -37,37 +104,47 @@ impl<'a, R: AlignmentReader<'a>> DepthIter<'a, R> {
         self.next_read = loop {
             if let Some(Ok(read)) = self.iter.next() {
                 if self.filter.as_ref().map_or(true, |predict| predict(&read)) {
-                    break Some(read);
+                    if let Some(segments) = self.mode.covered_segments(&read) {
+                        break Some((read.ref_id(), read.ref_begin(), segments));
+                    }
                 }
             } else {
                 break None;
             }
-        }
-        .map(|read| (read.ref_id(), read.ref_begin(), read.ref_end()));
+        };
     }
 }
 
 impl<'a, R: AlignmentReader<'a>> Iterator for DepthIter<'a, R> {
     type Item = (i32, usize, u32);
     fn next(&mut self) -> Option<Self::Item> {
-        if self.next_read.is_none() && self.heap.is_empty() {
+        if self.next_read.is_none() && self.heap.is_empty() && self.pending.is_empty() {
             return None;
         }
 
-
-        while let Some((tid, left, right)) = self.next_read {
-            if tid != self.cur_chrom {
-                if self.heap.is_empty() {
-                    self.cur_chrom = tid;
+        while let Some((tid, left, _)) = self.next_read.as_ref() {
+            if *tid != self.cur_chrom {
+                if self.heap.is_empty() && self.pending.is_empty() {
+                    self.cur_chrom = *tid;
                     self.cur_pos = 0;
                 }
                 break;
             }
+            if *left > self.cur_pos {
+                break;
+            }
+            if let Some((_, _, segments)) = self.next_read.take() {
+                self.pending.extend(segments.into_iter().map(Reverse));
+            }
+            self.load_next();
+        }
+
+        while let Some(&Reverse((left, right))) = self.pending.peek() {
             if left > self.cur_pos {
                 break;
             }
+            self.pending.pop();
             self.heap.push(Reverse(right));
-            self.load_next();
         }
 
         while self.heap.peek().map_or(false, |x| x.0 < self.cur_pos) {
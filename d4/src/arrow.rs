@@ -0,0 +1,326 @@
+/*! Export the D4 tracks as Apache Arrow record batches, in either Arrow IPC or Parquet format */
+
+use std::{
+    fs::File,
+    io::{Error, Result, Write},
+    path::Path,
+    sync::Arc,
+};
+
+use arrow_array::{
+    types::Int32Type, ArrayRef, DictionaryArray, Float64Array, Int32Array, RecordBatch,
+    StringArray, UInt32Array,
+};
+use arrow_ipc::writer::FileWriter;
+use arrow_schema::{DataType, Field, Schema, SchemaRef};
+use parquet::arrow::ArrowWriter;
+use rayon::prelude::*;
+
+use crate::{
+    d4file::{DataScanner, MultiTrackPartitionReader},
+    find_tracks_in_file, D4MatrixReader, D4TrackReader, MultiTrackReader, ValueType,
+};
+
+/// The name of the value column for the track that doesn't have a name, i.e. the track at the
+/// root of a single track file
+const DEFAULT_TRACK_NAME: &str = "value";
+
+/// The maximum size of the partition, each partition becomes one record batch
+const PARTITION_SIZE: usize = 10_000_000;
+
+/// How the raw values of a track are converted to the value column
+#[derive(Clone, Copy)]
+enum ValueColumn {
+    Integer,
+    FixedPoint(f64),
+    Float(f64),
+}
+
+impl ValueColumn {
+    fn new(value_type: ValueType, denominator: f64) -> Self {
+        match value_type {
+            ValueType::Float => Self::Float(denominator),
+            ValueType::Integer if denominator != 1.0 => Self::FixedPoint(denominator),
+            ValueType::Integer => Self::Integer,
+        }
+    }
+
+    fn data_type(&self) -> DataType {
+        match self {
+            Self::Integer => DataType::Int32,
+            _ => DataType::Float64,
+        }
+    }
+
+    fn make_array(&self, values: Vec<i32>) -> ArrayRef {
+        match *self {
+            Self::Integer => Arc::new(Int32Array::from(values)),
+            Self::FixedPoint(denominator) => Arc::new(Float64Array::from_iter_values(
+                values.into_iter().map(|v| v as f64 / denominator),
+            )),
+            Self::Float(denominator) => Arc::new(Float64Array::from_iter_values(
+                values
+                    .into_iter()
+                    .map(|v| ValueType::Float.decode(v) / denominator),
+            )),
+        }
+    }
+}
+
+/// Collects the intervals of one partition, the adjacent positions with the same values are
+/// merged into a single interval
+struct IntervalCollector {
+    range: (u32, u32),
+    skip_zeros: bool,
+    begin: Vec<u32>,
+    end: Vec<u32>,
+    values: Vec<Vec<i32>>,
+    row: Vec<i32>,
+}
+
+impl IntervalCollector {
+    /// Merge the first interval of the next partition into the last interval of this one, if
+    /// they are adjacent and have the same values
+    fn join(&mut self, next: &mut Self) {
+        let (Some(last_end), Some(&next_begin)) = (self.end.last_mut(), next.begin.first()) else {
+            return;
+        };
+        let same_values = self
+            .values
+            .iter()
+            .zip(next.values.iter())
+            .all(|(column, next_column)| column.last() == next_column.first());
+        if *last_end == next_begin && same_values {
+            *last_end = next.end[0];
+            next.begin.remove(0);
+            next.end.remove(0);
+            for column in next.values.iter_mut() {
+                column.remove(0);
+            }
+        }
+    }
+}
+
+impl<R: Iterator<Item = i32> + ExactSizeIterator> DataScanner<R> for IntervalCollector {
+    fn get_range(&self) -> (u32, u32) {
+        self.range
+    }
+
+    fn feed_row(&mut self, pos: u32, row: &mut R) -> bool {
+        self.feed_rows(pos, pos + 1, row)
+    }
+
+    fn feed_rows(&mut self, begin: u32, end: u32, row: &mut R) -> bool {
+        self.row.clear();
+        self.row.extend(row);
+        if begin >= end || (self.skip_zeros && self.row.iter().all(|&v| v == 0)) {
+            return true;
+        }
+        if let Some(last_end) = self.end.last_mut() {
+            let same_values = self
+                .values
+                .iter()
+                .zip(self.row.iter())
+                .all(|(column, value)| column.last() == Some(value));
+            if *last_end == begin && same_values {
+                *last_end = end;
+                return true;
+            }
+        }
+        self.begin.push(begin);
+        self.end.push(end);
+        for (column, &value) in self.values.iter_mut().zip(self.row.iter()) {
+            column.push(value);
+        }
+        true
+    }
+}
+
+/// Exports the intervals of one or more D4 tracks as Arrow record batches with the schema
+/// `(chrom, start, end, <track>...)`. Each partition of the tracks is scanned in parallel and
+/// becomes one record batch, thus the output is produced partition by partition rather than
+/// being loaded into memory at once. An interval that spans the partition boundaries is written
+/// to the batch of the partition it starts in.
+pub struct ArrowExporter {
+    reader: D4MatrixReader<crate::stab::SparseArrayReader<crate::stab::RangeRecord>>,
+    chroms: Arc<StringArray>,
+    columns: Vec<ValueColumn>,
+    schema: SchemaRef,
+    skip_zeros: bool,
+}
+
+impl ArrowExporter {
+    /// Create an exporter for the given tracks, each value column is named after its track
+    pub fn new<N: AsRef<str>, T: IntoIterator<Item = (N, D4TrackReader)>>(
+        tracks: T,
+    ) -> Result<Self> {
+        let mut fields = vec![
+            Field::new(
+                "chrom",
+                DataType::Dictionary(Box::new(DataType::Int32), Box::new(DataType::Utf8)),
+                false,
+            ),
+            Field::new("start", DataType::UInt32, false),
+            Field::new("end", DataType::UInt32, false),
+        ];
+        let mut columns = vec![];
+        let mut readers = vec![];
+        for (name, reader) in tracks {
+            let header = reader.header();
+            let column = ValueColumn::new(header.value_type(), header.get_denominator());
+            fields.push(Field::new(name.as_ref(), column.data_type(), false));
+            columns.push(column);
+            readers.push(reader);
+        }
+        let reader = D4MatrixReader::new(readers)?;
+        let chroms = StringArray::from_iter_values(
+            reader.chrom_regions().into_iter().map(|(name, _, _)| name),
+        );
+        Ok(Self {
+            reader,
+            chroms: Arc::new(chroms),
+            columns,
+            schema: Arc::new(Schema::new(fields)),
+            skip_zeros: false,
+        })
+    }
+
+    /// Open all the tracks in the file that matches the pattern
+    pub fn open<P: AsRef<Path>, Pat: FnMut(Option<&Path>) -> bool>(
+        path: P,
+        pattern: Pat,
+    ) -> Result<Self> {
+        let mut track_paths = vec![];
+        find_tracks_in_file(path.as_ref(), pattern, &mut track_paths)?;
+        let mut tracks = vec![];
+        for track_path in track_paths {
+            let name = track_path.file_name().map_or_else(
+                || DEFAULT_TRACK_NAME.to_string(),
+                |name| name.to_string_lossy().to_string(),
+            );
+            let reader = D4TrackReader::open_track_with_path(path.as_ref(), &track_path)?;
+            tracks.push((name, reader));
+        }
+        Self::new(tracks)
+    }
+
+    /// Do not export the intervals with all the values being zero
+    pub fn skip_zeros(&mut self, skip_zeros: bool) -> &mut Self {
+        self.skip_zeros = skip_zeros;
+        self
+    }
+
+    /// The schema of the record batches
+    pub fn schema(&self) -> SchemaRef {
+        self.schema.clone()
+    }
+
+    fn make_record_batch(
+        &self,
+        chrom_id: usize,
+        collector: IntervalCollector,
+    ) -> Result<RecordBatch> {
+        let keys = Int32Array::from(vec![chrom_id as i32; collector.begin.len()]);
+        let chrom = DictionaryArray::<Int32Type>::try_new(keys, self.chroms.clone())
+            .map_err(Error::other)?;
+        let mut arrays: Vec<ArrayRef> = vec![
+            Arc::new(chrom),
+            Arc::new(UInt32Array::from(collector.begin)),
+            Arc::new(UInt32Array::from(collector.end)),
+        ];
+        for (column, values) in self.columns.iter().zip(collector.values) {
+            arrays.push(column.make_array(values));
+        }
+        RecordBatch::try_new(self.schema.clone(), arrays).map_err(Error::other)
+    }
+
+    /// Scan the tracks and call the handle with the record batch of each partition, in the
+    /// order of the genome
+    pub fn for_each_batch<H: FnMut(RecordBatch) -> Result<()>>(
+        &mut self,
+        mut handle: H,
+    ) -> Result<()> {
+        let mut partitions = self.reader.split(Some(PARTITION_SIZE))?;
+        let chrom_ids: Vec<_> = partitions
+            .iter()
+            .map(|part| {
+                self.chroms
+                    .iter()
+                    .position(|name| name == Some(part.chrom()))
+                    .unwrap_or_default()
+            })
+            .collect();
+        let batch_size = rayon::current_num_threads();
+        // The last non-empty partition, which is held until we know whether its last interval
+        // continues in the following partitions
+        let mut pending: Option<(usize, IntervalCollector)> = None;
+        for (parts, chrom_ids) in partitions
+            .chunks_mut(batch_size)
+            .zip(chrom_ids.chunks(batch_size))
+        {
+            let collectors: Vec<_> = parts
+                .par_iter_mut()
+                .zip(chrom_ids.par_iter())
+                .map(|(part, &chrom_id)| {
+                    let mut collector = [IntervalCollector {
+                        range: (part.begin(), part.end()),
+                        skip_zeros: self.skip_zeros,
+                        begin: vec![],
+                        end: vec![],
+                        values: vec![vec![]; self.columns.len()],
+                        row: vec![],
+                    }];
+                    part.scan_partition(&mut collector);
+                    let [collector] = collector;
+                    (chrom_id, collector)
+                })
+                .collect();
+            for (chrom_id, mut collector) in collectors {
+                if let Some((last_chrom_id, last)) = pending.as_mut() {
+                    if *last_chrom_id == chrom_id {
+                        last.join(&mut collector);
+                    }
+                }
+                if collector.begin.is_empty() {
+                    continue;
+                }
+                if let Some((chrom_id, last)) = pending.replace((chrom_id, collector)) {
+                    handle(self.make_record_batch(chrom_id, last)?)?;
+                }
+            }
+        }
+        if let Some((chrom_id, last)) = pending {
+            handle(self.make_record_batch(chrom_id, last)?)?;
+        }
+        Ok(())
+    }
+
+    /// Write the intervals as an Arrow IPC file
+    pub fn write_ipc<W: Write>(&mut self, writer: W) -> Result<()> {
+        let mut writer = FileWriter::try_new(writer, &self.schema).map_err(Error::other)?;
+        self.for_each_batch(|batch| writer.write(&batch).map_err(Error::other))?;
+        writer.finish().map_err(Error::other)
+    }
+
+    /// Write the intervals as a Parquet file, each partition becomes a row group
+    pub fn write_parquet<W: Write + Send>(&mut self, writer: W) -> Result<()> {
+        let mut writer =
+            ArrowWriter::try_new(writer, self.schema.clone(), None).map_err(Error::other)?;
+        self.for_each_batch(|batch| {
+            writer.write(&batch).map_err(Error::other)?;
+            writer.flush().map_err(Error::other)
+        })?;
+        writer.close().map_err(Error::other)?;
+        Ok(())
+    }
+
+    /// Create the Arrow IPC file at the given path
+    pub fn create_ipc<P: AsRef<Path>>(&mut self, path: P) -> Result<()> {
+        self.write_ipc(File::create(path)?)
+    }
+
+    /// Create the Parquet file at the given path
+    pub fn create_parquet<P: AsRef<Path>>(&mut self, path: P) -> Result<()> {
+        self.write_parquet(File::create(path)?)
+    }
+}
@@ -1,12 +1,13 @@
 use super::D4FileBuilder;
 use std::{
-    io::{Result, Seek, SeekFrom},
+    io::{Error, Result, Seek, SeekFrom},
     path::{Path, PathBuf},
 };
 
 pub struct D4FileMerger {
     dest: PathBuf,
     sources: Vec<(String, PathBuf)>,
+    append: bool,
 }
 
 impl D4FileMerger {
@@ -14,9 +15,16 @@ impl D4FileMerger {
         Self {
             dest: target.as_ref().to_owned(),
             sources: Vec::new(),
+            append: false,
         }
     }
 
+    /// Add the inputs to an existing D4 file as new tracks, instead of creating a new file
+    pub fn append(mut self, append: bool) -> Self {
+        self.append = append;
+        self
+    }
+
     pub fn add_input<P: AsRef<Path>>(self, dest: P) -> Self {
         if let Some(tag) = dest.as_ref().file_stem().map(|x| x.to_string_lossy()) {
             self.add_input_with_tag(&dest, tag.as_ref())
@@ -32,8 +40,15 @@ impl D4FileMerger {
     }
 
     pub fn merge(self) -> Result<()> {
-        let mut root_dir = D4FileBuilder::write_d4_header(self.dest.as_path())?;
+        let mut root_dir = if self.append {
+            D4FileBuilder::open_container_for_update(self.dest.as_path())?
+        } else {
+            D4FileBuilder::write_d4_header(self.dest.as_path())?
+        };
         for (name, path) in self.sources {
+            if root_dir.entry_kind(&name).is_some() {
+                return Err(Error::other(format!("Track {} already exists", name)));
+            }
             let mut input = super::open_file_and_validate_header(path)?;
             let size = input.seek(SeekFrom::End(0))?;
             root_dir.copy_directory_from_file(&name, input, 8, size as usize - 8)?;
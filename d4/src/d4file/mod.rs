@@ -4,16 +4,22 @@ mod merger;
 #[cfg(all(feature = "mapped_io", not(target_arch = "wasm32")))]
 mod reader;
 
+#[cfg(all(feature = "mapped_io", not(target_arch = "wasm32")))]
+mod subset;
+
 #[cfg(all(feature = "mapped_io", not(target_arch = "wasm32")))]
 mod track;
 
+#[cfg(all(feature = "mapped_io", not(target_arch = "wasm32")))]
+mod updater;
+
 #[cfg(all(feature = "mapped_io", not(target_arch = "wasm32")))]
 mod writer;
 
 use std::{
-    fs::File,
+    fs::{File, OpenOptions},
     io::{Read, Result, Seek},
-    path::{Path, PathBuf},
+    path::{Component, Path, PathBuf},
 };
 
 use d4_framefile::{Directory, EntryKind};
@@ -23,9 +29,11 @@ mod mapped {
     use super::*;
     pub use merger::D4FileMerger;
     pub use reader::D4TrackReader;
+    pub use subset::D4FileSubset;
     pub use track::{
         D4MatrixReader, DataScanner, MultiTrackPartitionReader, MultiTrackReader,
     };
+    pub use updater::D4FileUpdater;
 
     pub use writer::{D4FileBuilder, D4FileWriter, D4FileWriterExt};
 }
@@ -53,6 +61,32 @@ fn open_file_and_validate_header<P: AsRef<Path>>(path: P) -> Result<File> {
     Ok(fp)
 }
 
+/// Open the root container of an existing D4 file for in-place update
+pub(crate) fn open_root_for_update<P: AsRef<Path>>(path: P) -> Result<Directory<File>> {
+    let mut fp = OpenOptions::new()
+        .read(true)
+        .write(true)
+        .create(false)
+        .truncate(false)
+        .open(path.as_ref())?;
+    validate_header(&mut fp)?;
+    Directory::open_root_for_update(fp, 8)
+}
+
+/// Open the root directory of the given track for in-place update
+pub(crate) fn open_track_root_for_update<P: AsRef<Path>>(
+    file_root: &Directory<File>,
+    track: P,
+) -> Result<Directory<File>> {
+    let mut track_root = file_root.clone();
+    for comp in track.as_ref().components() {
+        if let Component::Normal(name) = comp {
+            track_root = track_root.open_directory_for_update(&name.to_string_lossy())?;
+        }
+    }
+    Ok(track_root)
+}
+
 pub fn find_tracks<Pat: FnMut(Option<&Path>) -> bool, R: Read + Seek>(
     mut input: R,
     mut pattern: Pat,
@@ -0,0 +1,210 @@
+use super::{find_tracks_in_file, D4FileBuilder, D4FileWriter};
+use crate::{
+    ptab::PTablePartitionWriter, ssio::D4TrackReader, stab::SecondaryTablePartWriter, Chrom,
+    Dictionary,
+};
+use std::{
+    collections::HashMap,
+    fs::File,
+    io::Result,
+    path::{Path, PathBuf},
+};
+
+/// Copy selected tracks, chromosomes and regions of an existing D4 file into a new file.
+/// If regions are specified, all the values outside of the regions are zero and the output uses
+/// the sparse encoding, thus the size of the output is proportional to the size of the regions.
+pub struct D4FileSubset {
+    source: PathBuf,
+    dest: PathBuf,
+    tracks: Vec<PathBuf>,
+    chroms: Option<Vec<String>>,
+    regions: HashMap<String, Vec<(u32, u32)>>,
+    zero_fill: bool,
+}
+
+impl D4FileSubset {
+    pub fn new<S: AsRef<Path>, D: AsRef<Path>>(source: S, dest: D) -> Self {
+        Self {
+            source: source.as_ref().to_owned(),
+            dest: dest.as_ref().to_owned(),
+            tracks: vec![],
+            chroms: None,
+            regions: HashMap::new(),
+            zero_fill: false,
+        }
+    }
+
+    /// Add a track to the output, by default all the tracks are copied
+    pub fn add_track<P: AsRef<Path>>(mut self, track: P) -> Self {
+        self.tracks.push(track.as_ref().to_owned());
+        self
+    }
+
+    /// Only copy the given chromosomes, by default all the chromosomes are copied
+    pub fn select_chroms<I: IntoIterator<Item = S>, S: AsRef<str>>(mut self, chroms: I) -> Self {
+        self.chroms
+            .get_or_insert_with(Vec::new)
+            .extend(chroms.into_iter().map(|chr| chr.as_ref().to_string()));
+        self
+    }
+
+    /// Add a region to the output. Once a region is added, only the values inside the regions
+    /// are copied
+    pub fn add_region(mut self, chrom: &str, begin: u32, end: u32) -> Self {
+        if begin < end {
+            self.regions
+                .entry(chrom.to_string())
+                .or_default()
+                .push((begin, end));
+        }
+        self
+    }
+
+    /// Keep the chromosomes that don't overlap any region as all-zero chromosomes, so that the
+    /// output has the same reference genome as the source. By default these chromosomes are
+    /// dropped.
+    pub fn zero_fill(mut self, zero_fill: bool) -> Self {
+        self.zero_fill = zero_fill;
+        self
+    }
+
+    fn normalize_regions(&mut self) {
+        for regions in self.regions.values_mut() {
+            regions.sort_unstable();
+            let mut merged: Vec<(u32, u32)> = Vec::with_capacity(regions.len());
+            for &(begin, end) in regions.iter() {
+                match merged.last_mut() {
+                    Some(last) if last.1 >= begin => last.1 = last.1.max(end),
+                    _ => merged.push((begin, end)),
+                }
+            }
+            *regions = merged;
+        }
+    }
+
+    fn is_chrom_selected(&self, chrom: &Chrom) -> bool {
+        let selected = self
+            .chroms
+            .as_ref()
+            .is_none_or(|chroms| chroms.iter().any(|name| name == &chrom.name));
+        let has_region = self.regions.is_empty()
+            || self.zero_fill
+            || self.regions.contains_key(chrom.name.as_str());
+        selected && has_region
+    }
+
+    fn copy_track(
+        &self,
+        reader: &mut D4TrackReader<File>,
+        mut d4_writer: D4FileWriter,
+        default_value: Option<i32>,
+    ) -> Result<()> {
+        if default_value.is_some() {
+            d4_writer.enable_secondary_table_compression(5);
+        }
+
+        for (mut pt, mut st) in d4_writer.parallel_parts(None)? {
+            let (chrom, left, right) = pt.region();
+            let chrom = chrom.to_string();
+            let mut encoder = pt.make_encoder();
+
+            let ranges: Vec<_> = if self.regions.is_empty() {
+                vec![(left, right)]
+            } else {
+                self.regions
+                    .get(chrom.as_str())
+                    .into_iter()
+                    .flatten()
+                    .filter(|&&(begin, end)| begin < right && left < end)
+                    .map(|&(begin, end)| (begin.max(left), end.min(right)))
+                    .collect()
+            };
+
+            let mut write_interval = |begin: u32, end: u32, value: i32| -> Result<()> {
+                if let Some(default_value) = default_value {
+                    if value != default_value {
+                        st.encode_record(begin, end, value)?;
+                    }
+                } else {
+                    for pos in begin..end {
+                        if !encoder.encode(pos as usize, value) {
+                            st.encode(pos, value)?;
+                        }
+                    }
+                }
+                Ok(())
+            };
+
+            let mut last = left;
+            for (begin, end) in ranges {
+                write_interval(last, begin, 0)?;
+                let mut view = reader.get_view(&chrom, begin, end)?;
+                while view.tell().is_some() {
+                    let (begin, end, value) = view.read_next_interval()?;
+                    write_interval(begin, end, value)?;
+                }
+                last = end;
+            }
+            write_interval(last, right, 0)?;
+            st.flush()?;
+            st.finish()?;
+        }
+        Ok(())
+    }
+
+    pub fn subset(mut self) -> Result<()> {
+        self.normalize_regions();
+
+        let tracks = if self.tracks.is_empty() {
+            let mut tracks = vec![];
+            find_tracks_in_file(&self.source, |_| true, &mut tracks)?;
+            tracks
+        } else {
+            self.tracks.clone()
+        };
+
+        let sparse = !self.regions.is_empty();
+        let mut container = None;
+
+        for track in tracks.iter() {
+            let mut reader = D4TrackReader::from_reader(File::open(&self.source)?, track.to_str())?;
+            let header = reader.get_header();
+
+            let mut builder = D4FileBuilder::new(&self.dest);
+            builder.append_chrom(
+                header
+                    .chrom_list()
+                    .iter()
+                    .filter(|chrom| self.is_chrom_selected(chrom))
+                    .cloned(),
+            );
+            if sparse {
+                builder.set_dictionary(Dictionary::new_simple_range_dict(0, 1)?);
+            } else {
+                builder.set_dictionary(header.dictionary().clone());
+            }
+            if header.get_denominator() != 1.0 {
+                builder.set_denominator(header.get_denominator());
+            }
+            builder.set_value_type(header.value_type());
+            let default_value = if builder.dictionary().bit_width() == 0 {
+                Some(builder.dictionary().first_value())
+            } else {
+                None
+            };
+
+            let d4_writer = if tracks.len() == 1 && track.as_os_str().is_empty() {
+                builder.create()?
+            } else {
+                let container = match container.as_mut() {
+                    Some(container) => container,
+                    None => container.insert(D4FileBuilder::create_container(&self.dest)?),
+                };
+                builder.create_track(container, track.to_string_lossy().as_ref())?
+            };
+
+            self.copy_track(&mut reader, d4_writer, default_value)?;
+        }
+        Ok(())
+    }
+}
@@ -2,6 +2,7 @@ use smallvec::SmallVec;
 
 use super::D4TrackReader;
 use crate::{
+    header::ValueType,
     ptab::{
         BitArrayPartReader, BitArrayReader, DecodeBlockHandle, DecodeResult, Decoder,
         MatrixDecoder, PrimaryTablePartReader, PrimaryTableReader,
@@ -11,7 +12,7 @@ use crate::{
 };
 
 use std::{
-    cmp::Ordering,
+    cmp::{Ordering, Reverse},
     collections::BinaryHeap,
     io::{Error, ErrorKind, Result},
     iter::Once,
@@ -124,6 +125,10 @@ pub trait MultiTrackReader {
     type PartitionType: MultiTrackPartitionReader;
     /// Split a multi track reader into different partitions
     fn split(&mut self, size_limit: Option<usize>) -> Result<Vec<Self::PartitionType>>;
+    /// The type of the values in this reader
+    fn value_type(&self) -> ValueType {
+        ValueType::Integer
+    }
     /// Create a task on this reader
     fn run_tasks<RS, T>(&mut self, tasks: RS) -> Result<TaskOutputVec<T::Output>>
     where
@@ -183,6 +188,10 @@ impl<P: PrimaryTableReader, S: SecondaryTableReader> MultiTrackPartitionReader
                 let iter = self.secondary.seek_iter(part_left);
                 let mut last_right = part_left;
                 for (mut left, mut right, value) in iter {
+                    // The records after this range shouldn't be fed to the active handles
+                    if left >= part_right {
+                        break;
+                    }
                     left = left.max(part_left);
                     right = right.min(part_right).max(left);
                     for handle in active_handles.iter_mut() {
@@ -242,6 +251,9 @@ impl<P: PrimaryTableReader, S: SecondaryTableReader> MultiTrackReader for D4Trac
             .map(|(primary, secondary)| D4FilePartition { primary, secondary })
             .collect())
     }
+    fn value_type(&self) -> ValueType {
+        self.header().value_type()
+    }
 }
 
 pub struct D4MatrixReader<S: SecondaryTableReader> {
@@ -378,14 +390,15 @@ impl<S: SecondaryTableReader> D4MatrixReaderPartition<S> {
             iters.push(sec_tab.seek_iter(part_left));
         }
 
-        // Event(idx, start, value)
+        // Event(pos, is_begin, track_id, value), the heap is ordered by position, and the end of
+        // a record comes before the beginning of the next record at the same position
         let mut event_heap = BinaryHeap::new();
 
         for (track_id, reader) in iters.iter_mut().enumerate() {
             if let Some((begin, end, value)) = reader.next() {
                 if begin < part_right {
-                    event_heap.push((begin, value, track_id));
-                    event_heap.push((end, default_values[track_id], track_id))
+                    event_heap.push(Reverse((begin.max(part_left), true, track_id, value)));
+                    event_heap.push(Reverse((end, false, track_id, default_values[track_id])));
                 }
             }
         }
@@ -393,35 +406,36 @@ impl<S: SecondaryTableReader> D4MatrixReaderPartition<S> {
         let mut data_buf = MatrixRow::default();
         data_buf.extend(&default_values);
         let mut cur_pos = part_left;
-        let mut last_event_pos = part_left;
-
-        while let Some(event) = event_heap.pop() {
-            if event.1 == default_values[event.2] {
-                if let Some(next) = iters[event.2].next() {
-                    if next.0 < part_right {
-                        event_heap.push((next.0, next.2, event.2));
-                        event_heap.push((next.1, default_values[event.2], event.2));
-                    }
+
+        while let Some(Reverse((pos, is_begin, track_id, value))) = event_heap.pop() {
+            let pos = pos.min(part_right);
+            if cur_pos < pos {
+                for handle in active_handles.iter_mut() {
+                    data_buf.read_idx = 0;
+                    handle.feed_rows(cur_pos, pos, &mut data_buf);
                 }
+                cur_pos = pos;
             }
-            if last_event_pos != event.0 {
-                if cur_pos < last_event_pos {
-                    for handle in active_handles.iter_mut() {
-                        data_buf.read_idx = 0;
-                        handle.feed_rows(cur_pos, last_event_pos, &mut data_buf);
+            if cur_pos >= part_right {
+                break;
+            }
+
+            data_buf[track_id] = value;
+
+            if !is_begin {
+                if let Some((begin, end, value)) = iters[track_id].next() {
+                    if begin < part_right {
+                        event_heap.push(Reverse((begin.max(pos), true, track_id, value)));
+                        event_heap.push(Reverse((end, false, track_id, default_values[track_id])));
                     }
                 }
-                cur_pos = last_event_pos;
             }
-
-            data_buf[event.2] = event.1;
-            last_event_pos = event.0;
         }
 
-        if cur_pos < last_event_pos {
+        if cur_pos < part_right {
             for handle in active_handles.iter_mut() {
                 data_buf.read_idx = 0;
-                handle.feed_rows(cur_pos, last_event_pos, &mut data_buf);
+                handle.feed_rows(cur_pos, part_right, &mut data_buf);
             }
         }
     }
@@ -482,6 +496,9 @@ impl<S: SecondaryTableReader> D4MatrixReader<S> {
                     "Inconsistent reference genome in matrix",
                 ));
             }
+            if track.header().value_type() != tracks[0].header().value_type() {
+                return Err(Error::other("Inconsistent value type in matrix"));
+            }
         }
         Ok(Self { tracks })
     }
@@ -490,6 +507,10 @@ impl<S: SecondaryTableReader> D4MatrixReader<S> {
 impl<S: SecondaryTableReader> MultiTrackReader for D4MatrixReader<S> {
     type PartitionType = D4MatrixReaderPartition<S>;
 
+    fn value_type(&self) -> ValueType {
+        self.tracks[0].header().value_type()
+    }
+
     fn split(&mut self, size_limit: Option<usize>) -> Result<Vec<Self::PartitionType>> {
         let mut primary_table_decoders: Vec<Vec<_>> = vec![];
         let mut secondary_tables: Vec<Vec<_>> = vec![];
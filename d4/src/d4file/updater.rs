@@ -0,0 +1,377 @@
+use super::{find_tracks_in_file, open_root_for_update, open_track_root_for_update};
+use crate::{
+    index::INDEX_ROOT_NAME,
+    ptab::{
+        BitArrayWriter, PTablePartitionWriter, PrimaryTableWriter, COMPRESSED_PRIMARY_TABLE_NAME,
+        PRIMARY_TABLE_NAME,
+    },
+    stab::{
+        secondary_table_name, CompressionMethod, FloatRangeRecord, RangeRecord, Record,
+        RecordFormat, RunLengthRecord, SecondaryTablePartReader, SecondaryTablePartWriter,
+        SecondaryTableReader, SparseArraryMetadata, SparseArrayReader, SparseArrayWriter,
+        SECONDARY_TABLE_METADATA_NAME,
+    },
+    Chrom, Header,
+};
+use d4_framefile::Directory;
+use std::{
+    collections::HashMap,
+    fs::File,
+    io::{Error, Read, Result},
+    path::{Path, PathBuf},
+};
+
+/// Update an existing D4 file in place, without re-encoding the whole file.
+///
+/// - Appending chromosomes, e.g. decoy sequences, grows the primary table of each track and the
+///   values on the new chromosomes are zero.
+/// - Overwriting the values in a region patches the primary table in place.
+///
+/// In both cases only the affected partitions of the secondary table are rewritten, the replaced
+/// objects are left in the file as unreachable data. Tracks with data indexes can't be updated,
+/// since the indexes would be out of date.
+pub struct D4FileUpdater {
+    path: PathBuf,
+    tracks: Vec<PathBuf>,
+    new_chroms: Vec<Chrom>,
+    values: HashMap<String, Vec<(u32, u32, f64)>>,
+}
+
+/// The list of (begin, end, value) records of a secondary table
+type Records = Vec<(u32, u32, i32)>;
+
+/// Remove the regions from a sorted list of records, both lists should be sorted and
+/// non-overlapping
+fn remove_regions(records: Records, regions: &[(u32, u32)]) -> Records {
+    let mut ret = Vec::with_capacity(records.len());
+    let mut regions = regions.iter().peekable();
+    for (mut left, right, value) in records {
+        while let Some(&&(begin, end)) = regions.peek() {
+            if end <= left {
+                regions.next();
+                continue;
+            }
+            if begin < right {
+                if left < begin {
+                    ret.push((left, begin, value));
+                }
+                left = left.max(end);
+                if end <= right {
+                    regions.next();
+                    continue;
+                }
+            }
+            break;
+        }
+        if left < right {
+            ret.push((left, right, value));
+        }
+    }
+    ret
+}
+
+impl D4FileUpdater {
+    pub fn new<P: AsRef<Path>>(path: P) -> Self {
+        Self {
+            path: path.as_ref().to_owned(),
+            tracks: vec![],
+            new_chroms: vec![],
+            values: HashMap::new(),
+        }
+    }
+
+    /// Add a track to update, by default all the tracks in the file are updated
+    pub fn add_track<P: AsRef<Path>>(mut self, track: P) -> Self {
+        self.tracks.push(track.as_ref().to_owned());
+        self
+    }
+
+    /// Append chromosomes to the end of the chrom list
+    pub fn append_chrom<I: Iterator<Item = Chrom>>(mut self, chrom_it: I) -> Self {
+        self.new_chroms.extend(chrom_it);
+        self
+    }
+
+    /// Overwrite the values in the given region. The value is the actual value, which is
+    /// encoded with the denominator and the value type of the track
+    pub fn set_value(mut self, chrom: &str, begin: u32, end: u32, value: f64) -> Self {
+        if begin < end {
+            self.values
+                .entry(chrom.to_string())
+                .or_default()
+                .push((begin, end, value));
+        }
+        self
+    }
+
+    /// Load the records of the given partitions of the secondary table
+    fn load_secondary_table_records(
+        track_root: &mut Directory<File>,
+        header: &Header,
+        partitions: &[(&str, u32, u32)],
+    ) -> Result<Vec<Records>> {
+        let mut reader = SparseArrayReader::<RangeRecord>::create(track_root, header)?;
+        Ok(reader
+            .split(partitions)?
+            .into_iter()
+            .map(|part| part.iter().collect())
+            .collect())
+    }
+
+    /// Rewrite the given partitions of the secondary table, the other partitions are left
+    /// untouched. The partition list is only updated when there are new partitions appended.
+    fn update_secondary_table<R: Record>(
+        track_root: &mut Directory<File>,
+        compression: CompressionMethod,
+        new_partitions: Option<&[(&str, u32, u32)]>,
+        updated: Vec<(usize, Records)>,
+    ) -> Result<()> {
+        let mut s_table = SparseArrayWriter::<R>::open_for_update(track_root, compression)?;
+        if let Some(partitions) = new_partitions {
+            s_table.update_partitions(partitions)?;
+        }
+        for (idx, records) in updated {
+            let mut part = s_table.replace_partition(idx)?;
+            for (left, right, value) in records {
+                part.encode_record(left, right, value)?;
+            }
+            part.flush()?;
+            part.finish()?;
+        }
+        drop(s_table);
+        // The replaced partitions are appended to the end of the file, which is out of the
+        // original range of the secondary table
+        track_root.extend_directory_to_end(secondary_table_name(track_root))
+    }
+
+    fn update_track(&self, track_root: &mut Directory<File>) -> Result<()> {
+        if track_root.entry_kind(INDEX_ROOT_NAME).is_some() {
+            return Err(Error::other(
+                "Can not update a track with data index, as the index would be out of date",
+            ));
+        }
+        if track_root
+            .entry_kind(COMPRESSED_PRIMARY_TABLE_NAME)
+            .is_some()
+        {
+            return Err(Error::other(
+                "Can not update a track with compressed primary table",
+            ));
+        }
+
+        let old_header = Header::read(track_root.open_stream(Header::HEADER_STREAM_NAME)?)?;
+        let mut chrom_list = old_header.chrom_list().to_vec();
+        for chrom in self.new_chroms.iter() {
+            if chrom_list.iter().any(|c| c.name == chrom.name) {
+                return Err(Error::other(format!(
+                    "Chromosome {} already exists",
+                    chrom.name
+                )));
+            }
+            chrom_list.push(chrom.clone());
+        }
+        let header = Header {
+            chrom_list,
+            dictionary: old_header.dictionary.clone(),
+            denominator: old_header.denominator,
+            value_type: old_header.value_type,
+        };
+
+        let mut values = HashMap::new();
+        for (chrom, intervals) in self.values.iter() {
+            let size = match old_header.chrom_list().iter().find(|c| &c.name == chrom) {
+                Some(chrom) => chrom.size as u32,
+                None => return Err(Error::other(format!("Unknown chromosome {}", chrom))),
+            };
+            let mut intervals: Vec<_> = intervals
+                .iter()
+                .map(|&(begin, end, value)| (begin, end, header.encode_value(value)))
+                .collect();
+            intervals.sort_unstable_by_key(|&(begin, ..)| begin);
+            if intervals.windows(2).any(|w| w[0].1 > w[1].0) {
+                return Err(Error::other(format!("Overlapping regions on {}", chrom)));
+            }
+            if intervals.last().is_some_and(|&(_, end, _)| end > size) {
+                return Err(Error::other(format!(
+                    "Region out of the range of {}",
+                    chrom
+                )));
+            }
+            values.insert(chrom.as_str(), intervals);
+        }
+
+        // Grow the primary table for the new chromosomes. The data for the existing chromosomes
+        // is a prefix of the new table, since the new chromosomes are appended to the end.
+        let old_size = old_header.primary_table_size();
+        let new_size = header.primary_table_size();
+        if new_size != old_size {
+            let old_table = track_root.open_blob(PRIMARY_TABLE_NAME)?;
+            let old_data = old_table.mmap()?;
+            let mut new_table = track_root.replace_blob(PRIMARY_TABLE_NAME, new_size)?;
+            new_table.mmap_mut()?.as_mut()[..old_size].copy_from_slice(old_data.as_ref());
+        }
+
+        // The values that can't be encoded by the primary table
+        let mut new_records: HashMap<String, Records> = HashMap::new();
+        let mut p_table = BitArrayWriter::open_for_update(track_root, &header)?;
+        for mut part in p_table.split(&header, None)? {
+            let (chrom, _, size) = part.region();
+            let chrom = chrom.to_string();
+            let intervals = if self.new_chroms.iter().any(|c| c.name == chrom) {
+                vec![(0, size, 0)]
+            } else {
+                values.get(chrom.as_str()).cloned().unwrap_or_default()
+            };
+            let mut encoder = part.make_encoder();
+            for (left, right, value) in intervals {
+                if part.bit_width() > 0 {
+                    for pos in left..right {
+                        encoder.encode(pos as usize, value);
+                    }
+                }
+                if !part.can_encode(value) {
+                    new_records
+                        .entry(chrom.clone())
+                        .or_default()
+                        .push((left, right, value));
+                }
+            }
+        }
+        drop(p_table);
+
+        if !values.is_empty() || !new_records.is_empty() {
+            let stab_metadata = {
+                let stab_root = track_root.open_directory(secondary_table_name(track_root))?;
+                let mut content = vec![];
+                stab_root
+                    .open_stream(SECONDARY_TABLE_METADATA_NAME)?
+                    .read_to_end(&mut content)?;
+                let raw_metadata = String::from_utf8_lossy(&content);
+                serde_json::from_str::<SparseArraryMetadata>(raw_metadata.trim_end_matches('\0'))
+                    .map_err(|e| Error::other(e.to_string()))?
+            };
+            let streams: Vec<_> = stab_metadata.streams().collect();
+            let mut partitions: Vec<_> = streams
+                .iter()
+                .map(|stream| (stream.chr.as_str(), stream.range.0, stream.range.1))
+                .collect();
+
+            // Only the partitions overlapping the overwritten regions are rewritten
+            let affected: Vec<_> = (0..partitions.len())
+                .filter(|&idx| {
+                    let (chrom, begin, end) = partitions[idx];
+                    values.get(chrom).is_some_and(|intervals| {
+                        intervals.iter().any(|&(l, r, _)| l < end && begin < r)
+                    })
+                })
+                .collect();
+            let affected_partitions: Vec<_> = affected.iter().map(|&idx| partitions[idx]).collect();
+            let old_records =
+                Self::load_secondary_table_records(track_root, &old_header, &affected_partitions)?;
+
+            let mut updated = vec![];
+            for (&idx, records) in affected.iter().zip(old_records) {
+                let (chrom, begin, end) = partitions[idx];
+                let regions: Vec<_> = values[chrom].iter().map(|&(l, r, _)| (l, r)).collect();
+                let mut records = remove_regions(records, &regions);
+                for &(left, right, value) in new_records.get(chrom).into_iter().flatten() {
+                    if left < end && begin < right {
+                        records.push((left.max(begin), right.min(end), value));
+                    }
+                }
+                records.sort_unstable_by_key(|&(left, ..)| left);
+                updated.push((idx, records));
+            }
+
+            // The new chromosomes are appended as new partitions
+            for chrom in self.new_chroms.iter() {
+                if let Some(records) = new_records.remove(&chrom.name) {
+                    updated.push((partitions.len(), records));
+                    partitions.push((chrom.name.as_str(), 0, chrom.size as u32));
+                }
+            }
+
+            // The updated secondary table keeps the record format of the original one
+            let compression = stab_metadata.compression();
+            let new_partitions = Some(partitions.as_slice()).filter(|p| p.len() > streams.len());
+            match stab_metadata.record_format()? {
+                RecordFormat::Range
+                    if stab_metadata.record_format_name() == FloatRangeRecord::FORMAT_NAME =>
+                {
+                    Self::update_secondary_table::<FloatRangeRecord>(
+                        track_root,
+                        compression,
+                        new_partitions,
+                        updated,
+                    )?
+                }
+                RecordFormat::Range => Self::update_secondary_table::<RangeRecord>(
+                    track_root,
+                    compression,
+                    new_partitions,
+                    updated,
+                )?,
+                RecordFormat::RunLength => Self::update_secondary_table::<RunLengthRecord>(
+                    track_root,
+                    compression,
+                    new_partitions,
+                    updated,
+                )?,
+            }
+        }
+
+        if !self.new_chroms.is_empty() {
+            let mut metadata_stream = track_root.replace_stream(Header::HEADER_STREAM_NAME, 512)?;
+            metadata_stream.write(serde_json::to_string(&header).unwrap().as_bytes())?;
+        }
+
+        Ok(())
+    }
+
+    pub fn update(self) -> Result<()> {
+        let tracks = if self.tracks.is_empty() {
+            let mut tracks = vec![];
+            find_tracks_in_file(&self.path, |_| true, &mut tracks)?;
+            tracks
+        } else {
+            self.tracks.clone()
+        };
+
+        let file_root = open_root_for_update(&self.path)?;
+        for track in tracks {
+            let mut track_root = open_track_root_for_update(&file_root, &track)?;
+            self.update_track(&mut track_root)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::remove_regions;
+
+    #[test]
+    fn test_remove_regions() {
+        let records = vec![(0, 10, 1), (10, 20, 2), (30, 40, 3), (50, 60, 4)];
+        assert_eq!(remove_regions(records.clone(), &[]), records);
+        assert_eq!(
+            remove_regions(records.clone(), &[(5, 15)]),
+            vec![(0, 5, 1), (15, 20, 2), (30, 40, 3), (50, 60, 4)]
+        );
+        assert_eq!(
+            remove_regions(records.clone(), &[(32, 35), (36, 38), (40, 55)]),
+            vec![
+                (0, 10, 1),
+                (10, 20, 2),
+                (30, 32, 3),
+                (35, 36, 3),
+                (38, 40, 3),
+                (55, 60, 4)
+            ]
+        );
+        assert_eq!(
+            remove_regions(records, &[(0, 25), (25, 100)]),
+            Vec::<(u32, u32, i32)>::new()
+        );
+    }
+}
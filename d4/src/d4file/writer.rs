@@ -6,12 +6,40 @@ use std::path::{Path, PathBuf};
 
 use crate::chrom::Chrom;
 use crate::dict::Dictionary;
-use crate::header::{Denominator, Header};
-use crate::ptab::{BitArrayWriter, PTablePartitionWriter, PrimaryTableWriter};
-use crate::stab::{RangeRecord, SecondaryTableWriter, SparseArrayWriter};
+use crate::header::{Denominator, Header, ValueType};
+use crate::ptab::{BitArrayWriter, Encoder, PTablePartitionWriter, PrimaryTableWriter};
+use crate::stab::{
+    CompressionMethod, RangeRecord, SecondaryTablePartWriter, SecondaryTableWriter,
+    SparseArrayWriter,
+};
 
 use super::FILE_MAGIC_NUM;
 
+/// Encode the value of the bases in `begin..end` of a partition, `default_value` is the only
+/// value of a 0-bit primary table, which doesn't need to be written
+fn encode_range<P: PTablePartitionWriter, S: SecondaryTablePartWriter>(
+    p_part: &mut P,
+    s_part: &mut S,
+    default_value: Option<i32>,
+    begin: u32,
+    end: u32,
+    value: i32,
+) -> Result<()> {
+    match default_value {
+        Some(default_value) if default_value == value => {}
+        Some(_) => s_part.encode_record(begin, end, value)?,
+        None => {
+            let mut encoder = p_part.make_encoder();
+            for pos in begin..end {
+                if !encoder.encode(pos as usize, value) {
+                    s_part.encode(pos, value)?;
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
 /// Create a D4 file
 pub struct D4FileWriter<
     PT: PrimaryTableWriter = BitArrayWriter,
@@ -49,6 +77,66 @@ impl<PT: PrimaryTableWriter, ST: SecondaryTableWriter> D4FileWriter<PT, ST> {
         Ok(ret)
     }
 
+    /// Write the values of the intervals, e.g. the records of a bedGraph file, the bases that
+    /// are not covered by any interval are 0. The intervals on the chromosomes that are not in
+    /// the file are ignored. This function finishes all the partitions of the writer, thus it
+    /// should be the only way the values are written.
+    pub fn write_intervals<N, I>(&mut self, intervals: I) -> Result<()>
+    where
+        N: AsRef<str>,
+        I: IntoIterator<Item = (N, u32, u32, i32)>,
+    {
+        // With a 0-bit primary table, only the values other than the dictionary's only value
+        // are written to the secondary table
+        let default_value = if self.header.dictionary.bit_width() == 0 {
+            Some(self.header.dictionary.first_value())
+        } else {
+            None
+        };
+        // An unwritten base decodes to the value of code 0, so the gaps between the intervals
+        // are written explicitly if that value isn't 0
+        let fill_gaps = self.header.dictionary.first_value() != 0;
+        let mut partitions = self.parallel_parts(None)?;
+        let mut written: Vec<_> = partitions.iter().map(|(part, _)| part.region().1).collect();
+        let mut current = 0;
+        for (chr, mut begin, end, value) in intervals {
+            let chr = chr.as_ref();
+            while begin < end {
+                let (chrom, left, right) = partitions[current].0.region();
+                if chrom != chr || begin < left || right <= begin {
+                    match partitions.iter().position(|(part, _)| {
+                        let (chrom, left, right) = part.region();
+                        chrom == chr && left <= begin && begin < right
+                    }) {
+                        Some(idx) => {
+                            current = idx;
+                            continue;
+                        }
+                        None => break,
+                    }
+                }
+                let right = right.min(end);
+                let (p_part, s_part) = &mut partitions[current];
+                if fill_gaps && written[current] < begin {
+                    encode_range(p_part, s_part, default_value, written[current], begin, 0)?;
+                }
+                encode_range(p_part, s_part, default_value, begin, right, value)?;
+                written[current] = written[current].max(right);
+                begin = right;
+            }
+        }
+        for ((mut p_part, mut s_part), written) in partitions.into_iter().zip(written) {
+            let right = p_part.region().2;
+            if fill_gaps && written < right {
+                encode_range(&mut p_part, &mut s_part, default_value, written, right, 0)?;
+            }
+            s_part.flush()?;
+            s_part.finish()?;
+            p_part.finish()?;
+        }
+        Ok(())
+    }
+
     /// Enable the secondary table compression
     pub fn enable_secondary_table_compression(&mut self, level: u32) {
         self.s_table
@@ -56,6 +144,27 @@ impl<PT: PrimaryTableWriter, ST: SecondaryTableWriter> D4FileWriter<PT, ST> {
             .unwrap()
             .enable_deflate_encoding(level);
     }
+
+    /// Set the compression method of the secondary table
+    pub fn set_secondary_table_compression(&mut self, method: CompressionMethod) {
+        self.s_table.as_mut().unwrap().enable_compression(method);
+    }
+
+    /// Set the compression method of the primary table, e.g. the blocks of
+    /// [CompressedBitArrayWriter](crate::ptab::CompressedBitArrayWriter). This should be called
+    /// before the writer is split.
+    pub fn set_primary_table_compression(&mut self, method: CompressionMethod) -> Result<()> {
+        self.p_table.set_compression(method)
+    }
+
+    /// Finish writing the file, this should be called once all the partitions are finished.
+    /// Primary tables like the block compressed one are only written to the file by this
+    /// function.
+    pub fn finish(mut self) -> Result<()> {
+        // The secondary table directory locks the file until it's dropped
+        drop(self.s_table.take());
+        self.p_table.finish()
+    }
 }
 
 impl<PT: PrimaryTableWriter, ST: SecondaryTableWriter> Drop for D4FileWriter<PT, ST> {
@@ -72,6 +181,7 @@ pub struct D4FileBuilder {
     dict: Dictionary,
     chrom_filter: Box<dyn Fn(&str, usize) -> bool>,
     denominator: Denominator,
+    value_type: ValueType,
 }
 
 impl D4FileBuilder {
@@ -83,6 +193,7 @@ impl D4FileBuilder {
             dict: Dictionary::SimpleRange { low: 0, high: 64 },
             chrom_filter: Box::new(|_, _| true),
             denominator: Default::default(),
+            value_type: Default::default(),
         }
     }
 
@@ -91,6 +202,14 @@ impl D4FileBuilder {
         self
     }
 
+    /// Set the type of the values. In the float value mode, the values passed to the writer
+    /// should be encoded with `ValueType::encode`, and the dictionary should be either a float
+    /// dictionary or `0-1`, which encodes 0.0 only.
+    pub fn set_value_type(&mut self, value_type: ValueType) -> &mut Self {
+        self.value_type = value_type;
+        self
+    }
+
     /// Set a chromosome filter lambda, this will be used to determine if the chromosome should be
     /// in the output
     pub fn set_filter<T: Fn(&str, usize) -> bool + 'static>(&mut self, filter: T) -> &mut Self {
@@ -144,16 +263,81 @@ impl D4FileBuilder {
         Directory::make_root(target)
     }
 
+    /// Create an empty D4 container at the given path, tracks can be added to the container
+    /// with `create_track`
+    pub fn create_container<P: AsRef<Path>>(path: P) -> Result<Directory<File>> {
+        Self::write_d4_header(path)
+    }
+
+    /// Open the root container of an existing D4 file, so that new tracks can be added to the
+    /// file with `create_track`
+    pub fn open_container_for_update<P: AsRef<Path>>(path: P) -> Result<Directory<File>> {
+        super::open_root_for_update(path)
+    }
+
     /// Create the D4 file writer for this file
     pub fn create<PT: PrimaryTableWriter, ST: SecondaryTableWriter>(
         &mut self,
     ) -> Result<D4FileWriter<PT, ST>> {
-        let mut directory = Self::write_d4_header(self.path.as_path())?;
+        let directory = Self::write_d4_header(self.path.as_path())?;
+        self.create_in_directory(directory)
+    }
+
+    /// Create the D4 file writer for a new track named `track_name` in the given container. The
+    /// path of this builder is ignored. Note that the container is locked until the returned
+    /// writer is dropped, thus tracks in the same container must be written one after another.
+    pub fn create_track<PT: PrimaryTableWriter, ST: SecondaryTableWriter>(
+        &mut self,
+        container: &mut Directory<File>,
+        track_name: &str,
+    ) -> Result<D4FileWriter<PT, ST>> {
+        if container.entry_kind(track_name).is_some() {
+            return Err(std::io::Error::other(format!(
+                "Track {} already exists",
+                track_name
+            )));
+        }
+        let directory = container.create_directory(track_name)?;
+        self.create_in_directory(directory)
+    }
+
+    /// Create a multi-track D4 file at the path of this builder, the tracks are named after
+    /// `track_names` and share the configuration of this builder. As the tracks in a container
+    /// are written one after another, `write_track` is called with the index and the writer of
+    /// each track in order, e.g. to write the `i`-th value column of a multi-column bedGraph
+    /// file with [D4FileWriter::write_intervals].
+    pub fn create_multi_track<PT, ST, N, F>(
+        &mut self,
+        track_names: &[N],
+        mut write_track: F,
+    ) -> Result<()>
+    where
+        PT: PrimaryTableWriter,
+        ST: SecondaryTableWriter,
+        N: AsRef<str>,
+        F: FnMut(usize, D4FileWriter<PT, ST>) -> Result<()>,
+    {
+        let mut container = Self::create_container(self.path.as_path())?;
+        for (idx, track_name) in track_names.iter().enumerate() {
+            // The chromosome list is moved into the header of each track
+            let chrom_info = self.chrom_info.clone();
+            let writer = self.create_track(&mut container, track_name.as_ref())?;
+            self.chrom_info = chrom_info;
+            write_track(idx, writer)?;
+        }
+        Ok(())
+    }
+
+    fn create_in_directory<PT: PrimaryTableWriter, ST: SecondaryTableWriter>(
+        &mut self,
+        mut directory: Directory<File>,
+    ) -> Result<D4FileWriter<PT, ST>> {
         let mut metadata_stream = directory.create_stream(".metadata", 512)?;
         let header = Header {
             chrom_list: std::mem::take(&mut self.chrom_info),
             dictionary: self.dict.clone(),
             denominator: self.denominator,
+            value_type: self.value_type,
         };
 
         metadata_stream.write(serde_json::to_string(&header).unwrap().as_bytes())?;
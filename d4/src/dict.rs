@@ -6,7 +6,7 @@ use std::io::{BufRead, BufReader, Read, Result};
 use std::path::Path;
 
 #[cfg(feature = "depth_profiler")]
-use d4_hts::{BamFile, DepthIter, Alignment};
+use d4_hts::{BamFile, DepthIter, DepthMode, Alignment};
 #[cfg(feature = "depth_profiler")]
 use rand::Rng;
 #[cfg(feature = "depth_profiler")]
@@ -23,6 +23,13 @@ pub enum Dictionary {
         _v2i_map: HashMap<i32, u32>,
         i2v_map: Vec<i32>,
     },
+    /// The dictionary for the float value mode, the value of each code is a float and the
+    /// bit pattern of the float is what the primary table encodes
+    FloatDictionary {
+        #[serde(skip)]
+        _v2i_map: HashMap<i32, u32>,
+        i2v_map: Vec<f32>,
+    },
 }
 
 #[cfg(feature = "writer")]
@@ -54,8 +61,54 @@ impl Dictionary {
         F: Fn(&str, usize) -> bool, 
         RF: Fn(&Alignment) -> bool + Send + Sync + Copy
     {
+        Self::from_sample_bam_with_mode(path, filter, reference, read_filter, DepthMode::default())
+    }
 
-        let bam = BamFile::open(path.as_ref())?;
+    /// Same as `from_sample_bam`, but profiles the depth computed with the given depth mode
+    #[cfg(feature = "depth_profiler")]
+    pub fn from_sample_bam_with_mode<P, F, RF>(
+        path: P,
+        filter: F,
+        reference: Option<&str>,
+        read_filter: RF,
+        mode: DepthMode,
+    ) -> std::result::Result<Self, Box<dyn std::error::Error>>
+    where
+        P: AsRef<Path>,
+        F: Fn(&str, usize) -> bool,
+        RF: Fn(&Alignment) -> bool + Send + Sync + Copy,
+    {
+        Self::from_sample_bams(&[path], filter, reference, read_filter, mode)
+    }
+
+    /// Run the random sampling algorithm on a group of alignment files, determining the optimal
+    /// dictionary configuration shared by all of them. All the files should be aligned against
+    /// the same reference genome.
+    #[cfg(feature = "depth_profiler")]
+    pub fn from_sample_bams<P, F, RF>(
+        paths: &[P],
+        filter: F,
+        reference: Option<&str>,
+        read_filter: RF,
+        mode: DepthMode,
+    ) -> std::result::Result<Self, Box<dyn std::error::Error>>
+    where
+        P: AsRef<Path>,
+        F: Fn(&str, usize) -> bool,
+        RF: Fn(&Alignment) -> bool + Send + Sync + Copy,
+    {
+        let paths: Vec<&Path> = paths.iter().map(AsRef::as_ref).collect();
+        if paths.is_empty() {
+            return Err("At least one alignment file is required".into());
+        }
+        let bam = BamFile::open(paths[0])?;
+        for path in &paths[1..] {
+            if BamFile::open(path)?.chroms() != bam.chroms() {
+                return Err(
+                    format!("Inconsistent reference genome in file {}", path.display()).into(),
+                );
+            }
+        }
         let mut parts = vec![];
         let mut rng = rand::thread_rng();
         let mut total_size = 0;
@@ -69,7 +122,8 @@ impl Dictionary {
             while from < *size {
                 if rng.gen::<f64>() < 0.01 {
                     let to = (from + 100_000).min(*size);
-                    parts.push((chr.as_str(), from, to));
+                    let file_idx = rng.gen_range(0..paths.len());
+                    parts.push((file_idx, chr.as_str(), from, to));
                     sample_size += to - from;
                 }
                 from += 100_000;
@@ -77,12 +131,11 @@ impl Dictionary {
         }
         let mut histogram = HashMap::new();
         let mut range_count = HashMap::new();
-        let path = path.as_ref();
 
         let part_results: Vec<_> = parts
             .into_par_iter()
-            .map(|(chr, from, to)| {
-                let mut bam = BamFile::open(path).unwrap();
+            .map(|(file_idx, chr, from, to)| {
+                let mut bam = BamFile::open(paths[file_idx]).unwrap();
                 if let Some(reference) = reference {
                     bam.reference_path(reference);
                 }
@@ -90,7 +143,7 @@ impl Dictionary {
                 let mut previous_value = None;
                 let mut histogram = HashMap::new();
                 let mut range_count = HashMap::new();
-                for (_, _, dep) in DepthIter::with_filter(range, read_filter) {
+                for (_, _, dep) in DepthIter::with_filter_and_mode(range, read_filter, mode) {
                     match previous_value {
                         Some(d) if d == dep => continue,
                         Some(_) | None => {
@@ -105,14 +158,138 @@ impl Dictionary {
             .collect();
         for (part_hist, part_rc) in part_results {
             for (k, v) in part_hist.into_iter() {
-                *histogram.entry(k).or_insert(0) += v;
+                *histogram.entry(k as i32).or_insert(0) += v;
             }
             for (k, v) in part_rc.into_iter() {
-                *range_count.entry(k).or_insert(0) += v;
+                *range_count.entry(k as i32).or_insert(0) += v;
+            }
+        }
+        Ok(Self::from_value_profile(
+            histogram,
+            range_count,
+            total_size,
+            sample_size,
+            false,
+        )?)
+    }
+
+    /// Run the random sampling algorithm on a stream of (chrom, begin, end, value) intervals,
+    /// e.g. the records of a BigWig or bedGraph file, determining the optimal dictionary
+    /// configuration with the same cost model as `from_sample_bam`.
+    ///
+    /// Only the chromosomes listed in `chroms` are profiled, the intervals should be sorted within
+    /// each chromosome and the bases that are not covered by any interval have value 0.
+    pub fn from_sample_intervals<C, N, I>(chroms: &[(C, usize)], intervals: I) -> Result<Self>
+    where
+        C: AsRef<str>,
+        N: AsRef<str>,
+        I: IntoIterator<Item = (N, u32, u32, i32)>,
+    {
+        const WINDOW_SIZE: usize = 100_000;
+        // Profile 1% of the windows, but at least a few of them for small genomes
+        let total_size: usize = chroms.iter().map(|(_, size)| size).sum();
+        let num_windows = total_size.div_ceil(WINDOW_SIZE);
+        let stride = (num_windows / 10).clamp(1, 100);
+
+        let mut windows: HashMap<&str, Vec<(u32, u32)>> = HashMap::new();
+        let mut window_idx = 0;
+        let mut sample_size = 0;
+        for (chrom, size) in chroms {
+            let chrom_windows = windows.entry(chrom.as_ref()).or_default();
+            for from in (0..*size).step_by(WINDOW_SIZE) {
+                if window_idx % stride == 0 {
+                    let to = (from + WINDOW_SIZE).min(*size);
+                    chrom_windows.push((from as u32, to as u32));
+                    sample_size += to - from;
+                }
+                window_idx += 1;
+            }
+        }
+
+        let mut histogram = HashMap::new();
+        let mut range_count = HashMap::new();
+        let mut add_run = |value: i32, size: u32, previous: &mut Option<i32>| {
+            if size == 0 {
+                return;
+            }
+            if *previous != Some(value) {
+                *range_count.entry(value).or_insert(0) += 1;
+                *previous = Some(value);
+            }
+            *histogram.entry(value).or_insert(0) += size as usize;
+        };
+
+        // For each chromosome: the index of the current window, the position in that window that
+        // has been profiled and the value of the last profiled run
+        let mut cursors: HashMap<&str, (usize, u32, Option<i32>)> = HashMap::new();
+        for (chrom, begin, end, value) in intervals {
+            let (chrom, chrom_windows) = match windows.get_key_value(chrom.as_ref()) {
+                Some((&chrom, chrom_windows)) => (chrom, chrom_windows),
+                None => continue,
+            };
+            let (idx, pos, previous) = cursors.entry(chrom).or_insert((0, 0, None));
+            while let Some(&(from, to)) = chrom_windows.get(*idx) {
+                if to <= begin {
+                    // The tail of this window isn't covered by any interval
+                    add_run(0, to - (*pos).max(from), previous);
+                    *idx += 1;
+                    *previous = None;
+                    continue;
+                }
+                if end <= from {
+                    break;
+                }
+                let pos_ref = (*pos).max(from);
+                let left = begin.max(from);
+                let right = end.min(to);
+                if pos_ref < left {
+                    add_run(0, left - pos_ref, previous);
+                }
+                add_run(value, right - left.max(pos_ref).min(right), previous);
+                *pos = pos_ref.max(right);
+                if right < to {
+                    break;
+                }
+                *idx += 1;
+                *previous = None;
             }
         }
+        // The windows that are not fully covered by the intervals
+        for (chrom, chrom_windows) in windows.iter() {
+            let (idx, pos, mut previous) = cursors.get(chrom).cloned().unwrap_or((0, 0, None));
+            for (offset, &(from, to)) in chrom_windows.iter().enumerate().skip(idx) {
+                if offset > idx {
+                    previous = None;
+                }
+                add_run(0, to - pos.max(from), &mut previous);
+            }
+        }
+
+        Self::from_value_profile(histogram, range_count, total_size, sample_size, true)
+    }
+
+    /// Determine the optimal dictionary from the profile of the sampled regions.
+    /// The `histogram` is used to rank the values and the `range_count` is the number of runs
+    /// of each value, which is the number of records the secondary table needs if the value is
+    /// out of the dictionary.
+    /// When `zero_code` is set, value 0 is always encoded by code 0. This is required by the
+    /// interval inputs, since the bases that are not covered by any interval are never written
+    /// and the primary table is filled with code 0.
+    fn from_value_profile(
+        mut histogram: HashMap<i32, usize>,
+        mut range_count: HashMap<i32, usize>,
+        total_size: usize,
+        sample_size: usize,
+        zero_code: bool,
+    ) -> Result<Self> {
+        if zero_code {
+            histogram.entry(0).or_insert(0);
+            range_count.entry(0).or_insert(0);
+        }
         let mut histogram: Vec<_> = histogram.into_iter().collect();
-        histogram.sort_by_key(|(_, count)| total_size - count);
+        histogram.sort_by_key(|&(value, count)| {
+            (!(zero_code && value == 0), total_size - count, value)
+        });
         let total_intervals: usize = range_count.values().sum();
 
         let best_bit_width = (0..=16)
@@ -122,28 +299,37 @@ impl Dictionary {
                     out_of_range_values -= range_count[&key];
                 }
                 let p_size = total_size as f64 * b as f64 / 8.0;
-                let s_size =
-                    (out_of_range_values as f64) * 4.0 / sample_size as f64 * total_size as f64;
+                let s_size = (out_of_range_values as f64) * 4.0 / sample_size.max(1) as f64
+                    * total_size as f64;
                 (b, (p_size + s_size).round() as usize)
             })
             .min_by_key(|&(_, size)| size)
             .unwrap();
 
-        let mut dict = vec![];
-        if histogram.is_empty() {
+        let mut dict: Vec<_> = histogram
+            .iter()
+            .take(1 << best_bit_width.0)
+            .map(|&(value, _)| value)
+            .collect();
+        if dict.is_empty() {
             dict.push(0);
-        } else {
-            for i in 0..(1 << best_bit_width.0) {
-                dict.push(histogram[i as usize].0 as i32);
-            }
+        }
+        // The unused codes are assigned to the values right above the largest value
+        let mut next_unused = dict.iter().max().unwrap() + 1;
+        while dict.len() < 1 << best_bit_width.0 {
+            dict.push(next_unused);
+            next_unused += 1;
         }
 
         let min = dict.iter().min().unwrap();
         let max = dict.iter().max().unwrap();
-        if max - min + 1 == dict.len() as i32 && (dict.len() == 1 || *min != dict[0]) {
+        if max - min + 1 == dict.len() as i32
+            && (dict.len() == 1 || *min != dict[0])
+            && (!zero_code || *min == 0)
+        {
             dict.sort_unstable();
         }
-        Ok(Self::from_dict_list(dict)?)
+        Self::from_dict_list(dict)
     }
 
     /// Create a dictionary from the mapping vector
@@ -156,8 +342,7 @@ impl Dictionary {
                 )
             );
         }
-        if mapping.last().unwrap_or(&0) - mapping.first().unwrap_or(&0) == mapping.len() as i32 - 1
-        {
+        if mapping.windows(2).all(|pair| pair[1] == pair[0] + 1) {
             return Self::new_simple_range_dict(
                 *mapping.first().unwrap(),
                 *mapping.last().unwrap() + 1,
@@ -171,6 +356,22 @@ impl Dictionary {
         Ok(ret)
     }
 
+    /// Create a float dictionary from the mapping vector, this is used by the tracks in the float
+    /// value mode
+    pub fn from_float_list(mapping: Vec<f32>) -> Result<Self> {
+        if mapping.is_empty() {
+            return Err(std::io::Error::other(
+                "Invalid dictionary range setting - At least one value should be present in the file",
+            ));
+        }
+        let mut ret = Self::FloatDictionary {
+            _v2i_map: Default::default(),
+            i2v_map: mapping,
+        };
+        ret.ensure_v2i_map();
+        Ok(ret)
+    }
+
     /// Create a dictionary from a dictionary specification file.
     /// The dictionary has 2^K lines, the N-th lines contains the actual value that
     /// code N-1 encodes.
@@ -211,14 +412,26 @@ impl Dictionary {
 
     #[inline(always)]
     pub(crate) fn ensure_v2i_map(&mut self) {
-        if let Self::Dictionary { _v2i_map, i2v_map } = self {
-            if _v2i_map.len() == i2v_map.len() {
-                return;
+        match self {
+            Self::Dictionary { _v2i_map, i2v_map } => {
+                if _v2i_map.len() == i2v_map.len() {
+                    return;
+                }
+                _v2i_map.clear();
+                for (idx, &value) in i2v_map.iter().enumerate() {
+                    _v2i_map.insert(value, idx as u32);
+                }
             }
-            _v2i_map.clear();
-            for (idx, &value) in i2v_map.iter().enumerate() {
-                _v2i_map.insert(value, idx as u32);
+            Self::FloatDictionary { _v2i_map, i2v_map } => {
+                if _v2i_map.len() == i2v_map.len() {
+                    return;
+                }
+                _v2i_map.clear();
+                for (idx, &value) in i2v_map.iter().enumerate() {
+                    _v2i_map.insert(value.to_bits() as i32, idx as u32);
+                }
             }
+            Self::SimpleRange { .. } => {}
         }
     }
     #[inline(always)]
@@ -226,6 +439,7 @@ impl Dictionary {
         let mut n_values = match self {
             Self::SimpleRange { low, high } => (high - low).max(0) as usize,
             Self::Dictionary { i2v_map, .. } => i2v_map.len(),
+            Self::FloatDictionary { i2v_map, .. } => i2v_map.len(),
         };
         let mut ret = 0;
         while n_values > 1 {
@@ -240,6 +454,7 @@ impl Dictionary {
         match self {
             Self::SimpleRange { low, .. } => *low,
             Self::Dictionary { i2v_map, .. } => i2v_map[0],
+            Self::FloatDictionary { i2v_map, .. } => i2v_map[0].to_bits() as i32,
         }
     }
 
@@ -248,6 +463,9 @@ impl Dictionary {
         match self {
             Self::SimpleRange { low, .. } => Some(*low + idx as i32),
             Self::Dictionary { i2v_map, .. } => i2v_map.get(idx as usize).copied(),
+            Self::FloatDictionary { i2v_map, .. } => i2v_map
+                .get(idx as usize)
+                .map(|value| value.to_bits() as i32),
         }
     }
 
@@ -261,7 +479,7 @@ impl Dictionary {
                 }
                 EncodeResult::OutOfRange(value)
             }
-            Self::Dictionary { _v2i_map, .. } => {
+            Self::Dictionary { _v2i_map, .. } | Self::FloatDictionary { _v2i_map, .. } => {
                 if let Some(idx) = _v2i_map.get(&value) {
                     return EncodeResult::DictionaryIndex(*idx);
                 }
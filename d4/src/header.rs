@@ -16,6 +16,37 @@ impl Default for Denominator {
     }
 }
 
+/// The type of the values stored in the track
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub enum ValueType {
+    /// The values are 32-bit integers, optionally scaled by the denominator
+    #[default]
+    Integer,
+    /// The values are 32-bit floats, the bit pattern of the float is stored in place of the
+    /// integer value in both the primary and secondary table
+    Float,
+}
+
+impl ValueType {
+    /// Convert the value stored in the file to the actual value
+    #[inline(always)]
+    pub fn decode(&self, raw: i32) -> f64 {
+        match self {
+            Self::Integer => raw as f64,
+            Self::Float => f32::from_bits(raw as u32) as f64,
+        }
+    }
+
+    /// Convert the actual value to the value stored in the file
+    #[inline(always)]
+    pub fn encode(&self, value: f64) -> i32 {
+        match self {
+            Self::Integer => value.round() as i32,
+            Self::Float => (value as f32).to_bits() as i32,
+        }
+    }
+}
+
 /// The D4 file header struct, this is store in the ".metadata" stream in a D4 file in JSON format
 #[derive(Serialize, Deserialize)]
 pub struct Header {
@@ -23,6 +54,8 @@ pub struct Header {
     pub(crate) dictionary: Dictionary,
     #[serde(default)]
     pub(crate) denominator: Denominator,
+    #[serde(default)]
+    pub(crate) value_type: ValueType,
 }
 
 impl Default for Header {
@@ -39,6 +72,7 @@ impl Header {
             chrom_list: vec![],
             dictionary: Dictionary::SimpleRange { low: 0, high: 64 },
             denominator: Denominator::One,
+            value_type: ValueType::Integer,
         }
     }
 
@@ -83,7 +117,26 @@ impl Header {
     }
 
     pub fn is_integral(&self) -> bool {
-        matches!(self.denominator, Denominator::One)
+        matches!(self.denominator, Denominator::One) && self.value_type == ValueType::Integer
+    }
+
+    pub fn set_value_type(&mut self, value_type: ValueType) {
+        self.value_type = value_type;
+    }
+
+    pub fn value_type(&self) -> ValueType {
+        self.value_type
+    }
+
+    /// Convert the value stored in the file to the actual value, which handles both the
+    /// denominator and the float value mode
+    pub fn decode_value(&self, raw: i32) -> f64 {
+        self.value_type.decode(raw) / self.get_denominator()
+    }
+
+    /// Convert the actual value to the value stored in the file
+    pub fn encode_value(&self, value: f64) -> i32 {
+        self.value_type.encode(value * self.get_denominator())
     }
 
     pub fn get_denominator(&self) -> f64 {
@@ -1,8 +1,8 @@
-use std::{io::Result, iter::Once, marker::PhantomData};
+use std::{fmt::Debug, io::Result, iter::Once, marker::PhantomData};
 
 use crate::{
     task::{Task, TaskContext, TaskOutputVec, TaskPartition},
-    D4TrackReader,
+    D4TrackReader, ValueType,
 };
 
 use super::DataIndexType;
@@ -32,6 +32,11 @@ pub trait DataSummary: Sized + Send + Sync + Clone {
         iter.fold(Self::identity(), |sum, value| sum.combine(value))
     }
     fn run_summary_task(reader: &mut D4TrackReader, bin_size: u32) -> Result<TaskOutputVec<Self>> {
+        if reader.header().value_type() == ValueType::Float {
+            return Err(std::io::Error::other(
+                "Data index isn't supported by the float value mode",
+            ));
+        }
         let chrom_list = reader.header().chrom_list().to_owned();
         let task_array: Vec<_> = chrom_list
             .iter()
@@ -99,6 +104,205 @@ impl DataSummary for Sum {
     const INDEX_TYPE_CODE: DataIndexType = DataIndexType::Sum;
 }
 
+#[derive(Clone, Copy, Debug)]
+pub struct MinMax {
+    min: i32,
+    max: i32,
+}
+
+impl MinMax {
+    pub fn min(&self) -> i32 {
+        self.min
+    }
+    pub fn max(&self) -> i32 {
+        self.max
+    }
+    /// Check if no value has been summarized yet
+    pub fn is_empty(&self) -> bool {
+        self.min > self.max
+    }
+}
+
+impl DataSummary for MinMax {
+    fn identity() -> Self {
+        MinMax {
+            min: i32::MAX,
+            max: i32::MIN,
+        }
+    }
+
+    fn add_data(&self, _: u32, val: i32) -> Self {
+        MinMax {
+            min: self.min.min(val),
+            max: self.max.max(val),
+        }
+    }
+
+    fn add_data_range(&self, begin: u32, end: u32, val: i32) -> Self {
+        if begin >= end {
+            return *self;
+        }
+        self.add_data(begin, val)
+    }
+
+    fn combine(&self, other: &Self) -> Self {
+        MinMax {
+            min: self.min.min(other.min),
+            max: self.max.max(other.max),
+        }
+    }
+
+    fn to_native_byte_order(&self) -> Self {
+        MinMax {
+            min: i32::from_le(self.min),
+            max: i32::from_le(self.max),
+        }
+    }
+
+    fn to_format_byte_order(&self) -> Self {
+        MinMax {
+            min: self.min.to_le(),
+            max: self.max.to_le(),
+        }
+    }
+
+    const INDEX_NAME: &'static str = "minmax_index";
+
+    const INDEX_TYPE_CODE: DataIndexType = DataIndexType::MinMax;
+}
+
+/// The number of distinct values tracked by a histogram index bin, values beyond this range
+/// are only counted by the overflow counters
+pub const HISTOGRAM_INDEX_RANGE: usize = 256;
+
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct ValueHistogram {
+    below: u32,
+    above: u32,
+    counts: [u32; HISTOGRAM_INDEX_RANGE],
+}
+
+impl ValueHistogram {
+    /// The number of bases with a negative value
+    pub fn below(&self) -> u32 {
+        self.below
+    }
+    /// The number of bases with a value greater than or equal to `HISTOGRAM_INDEX_RANGE`
+    pub fn above(&self) -> u32 {
+        self.above
+    }
+    pub fn counts(&self) -> &[u32] {
+        &self.counts
+    }
+    pub fn total_count(&self) -> u64 {
+        self.counts.iter().map(|&c| c as u64).sum::<u64>() + self.below as u64 + self.above as u64
+    }
+    /// Get the value at the given percentile (0.0 to 1.0). Returns `None` if the value
+    /// falls into one of the overflow counters, or there's no data at all.
+    pub fn percentile(&self, percentile: f64) -> Option<i32> {
+        histogram_percentile(
+            self.below as u64,
+            &self.counts,
+            self.above as u64,
+            percentile,
+        )
+    }
+}
+
+/// Get the value at the given percentile (0.0 to 1.0) from a histogram, where `counts[v]` is the
+/// number of bases with value `v`, `below` and `above` are the number of bases out of the range
+/// of `counts`. The result is the smallest value that at least the given fraction of the bases
+/// are less than or equal to. Returns `None` if the value falls into `below` or `above`, or the
+/// histogram is empty.
+pub fn histogram_percentile(
+    below: u64,
+    counts: &[u32],
+    above: u64,
+    percentile: f64,
+) -> Option<i32> {
+    let total = counts.iter().map(|&c| c as u64).sum::<u64>() + below + above;
+    let target = ((total as f64 * percentile.clamp(0.0, 1.0)).round() as u64).max(1);
+    let mut current = below;
+    if current >= target {
+        return None;
+    }
+    for (value, &count) in counts.iter().enumerate() {
+        current += count as u64;
+        if current >= target {
+            return Some(value as i32);
+        }
+    }
+    None
+}
+
+impl Debug for ValueHistogram {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "<0:{}", self.below)?;
+        for (value, count) in self.counts.iter().enumerate().filter(|(_, &c)| c > 0) {
+            write!(f, " {}:{}", value, count)?;
+        }
+        write!(f, " >={}:{}", HISTOGRAM_INDEX_RANGE, self.above)
+    }
+}
+
+impl DataSummary for ValueHistogram {
+    fn identity() -> Self {
+        ValueHistogram {
+            below: 0,
+            above: 0,
+            counts: [0; HISTOGRAM_INDEX_RANGE],
+        }
+    }
+
+    fn add_data(&self, begin: u32, val: i32) -> Self {
+        self.add_data_range(begin, begin + 1, val)
+    }
+
+    fn add_data_range(&self, begin: u32, end: u32, val: i32) -> Self {
+        let mut ret = *self;
+        let count = end.saturating_sub(begin);
+        if val < 0 {
+            ret.below += count;
+        } else if val as usize >= HISTOGRAM_INDEX_RANGE {
+            ret.above += count;
+        } else {
+            ret.counts[val as usize] += count;
+        }
+        ret
+    }
+
+    fn combine(&self, other: &Self) -> Self {
+        let mut ret = *self;
+        ret.below += other.below;
+        ret.above += other.above;
+        for (a, b) in ret.counts.iter_mut().zip(other.counts.iter()) {
+            *a += b;
+        }
+        ret
+    }
+
+    fn to_native_byte_order(&self) -> Self {
+        let mut ret = *self;
+        ret.below = u32::from_le(ret.below);
+        ret.above = u32::from_le(ret.above);
+        ret.counts.iter_mut().for_each(|c| *c = u32::from_le(*c));
+        ret
+    }
+
+    fn to_format_byte_order(&self) -> Self {
+        let mut ret = *self;
+        ret.below = ret.below.to_le();
+        ret.above = ret.above.to_le();
+        ret.counts.iter_mut().for_each(|c| *c = c.to_le());
+        ret
+    }
+
+    const INDEX_NAME: &'static str = "histogram_index";
+
+    const INDEX_TYPE_CODE: DataIndexType = DataIndexType::Histogram;
+}
+
 pub struct DataSummaryTask<'a, T: DataSummary> {
     chrom: &'a str,
     begin: u32,
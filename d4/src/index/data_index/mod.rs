@@ -1,28 +1,35 @@
 mod data;
 
 use d4_framefile::{Blob, Directory};
-pub use data::{DataSummary, Sum};
+pub use data::{
+    histogram_percentile, DataSummary, MinMax, Sum, ValueHistogram, HISTOGRAM_INDEX_RANGE,
+};
 
 use std::{
     collections::HashMap,
     fmt::Debug,
     fs::File,
-    io::{Read, Result, Seek},
+    io::{Error, Read, Result, Seek},
     marker::PhantomData,
     ops::{Deref, DerefMut},
 };
 
 use crate::{ssio::D4TrackReader as StreamD4Reader, Chrom, D4TrackReader};
 
-#[derive(PartialEq)]
+#[repr(u32)]
+#[derive(PartialEq, Clone, Copy, Debug)]
 pub enum DataIndexType {
-    Sum,
+    Sum = 0,
+    MinMax = 1,
+    Histogram = 2,
 }
 
 #[repr(C)]
 struct DataIndexHeader {
     granularity: u32,
-    index_type: DataIndexType,
+    /// The little-endian code of the `DataIndexType`, kept as a plain integer since it's read
+    /// from the file as is
+    index_type: u32,
 }
 
 #[repr(C)]
@@ -34,6 +41,9 @@ pub struct DataIndex<T: DataSummary> {
 pub struct DataIndexRef<T: DataSummary> {
     raw_data: Vec<u8>,
     offset_table: HashMap<String, (usize, usize)>,
+    /// The number of bins that can be read from the index, the bins after it are queried
+    /// per-base
+    valid_bins: usize,
     phantom: PhantomData<T>,
 }
 
@@ -81,6 +91,10 @@ impl<'a, T: DataSummary> DataIndexQueryResult<'a, T> {
 }
 
 impl<T: DataSummary> DataIndexRef<T> {
+    /// The size of the region each pre-computed bin covers
+    pub fn granularity(&self) -> u32 {
+        self.header.granularity
+    }
     pub fn print_index(&self)
     where
         T: Debug,
@@ -90,7 +104,7 @@ impl<T: DataSummary> DataIndexRef<T> {
         chroms.sort_unstable_by_key(|(_, (start, _))| *start);
         for (chr, (begin_idx, chrom_size)) in chroms {
             let mut begin = 0;
-            for item in &self.pre_computed_data[*begin_idx..] {
+            for item in &self.pre_computed_data[*begin_idx..self.valid_bins.max(*begin_idx)] {
                 let end = (begin + granularity).min(*chrom_size as u32);
                 println!("{}\t{}\t{}\t{:.5?}", chr, begin, end, item);
                 begin += granularity;
@@ -110,21 +124,30 @@ impl<T: DataSummary> DataIndexRef<T> {
         begin = begin.min(chrom_size as u32);
         end = end.min(chrom_size as u32);
         let grand = self.header.granularity;
-        let actual_begin = if begin % grand == 0 {
+        let mut actual_begin = if begin % grand == 0 {
             begin
         } else {
             begin + grand - begin % grand
         };
-        let actual_end: u32 = if end % grand == 0 {
+        let mut actual_end: u32 = if end % grand == 0 {
             end
         } else {
             end - end % grand
         };
+        let valid_bins = self.valid_bins.saturating_sub(base_offset);
+        if (actual_end / grand) as usize > valid_bins {
+            actual_end = valid_bins as u32 * grand;
+        }
+        // The query doesn't cover any complete bin, so everything is answered per-base
+        if actual_begin > actual_end {
+            actual_begin = begin;
+            actual_end = begin;
+        }
         let actual_begin_idx = (actual_begin / grand) as usize + base_offset;
         let actual_end_idx = (actual_end / grand) as usize + base_offset;
         let mut ret = T::identity();
         for idx in actual_begin_idx..actual_end_idx {
-            ret = ret.combine(&self.pre_computed_data[idx]);
+            ret = ret.combine(&self.pre_computed_data[idx].to_native_byte_order());
         }
         Some(DataIndexQueryResult {
             chrom: self.offset_table.get_key_value(chr)?.0.as_str(),
@@ -168,9 +191,48 @@ impl<T: DataSummary> DataIndex<T> {
     ) -> Result<DataIndexRef<T>> {
         let mut data_buffer = vec![0; blob.size()];
         blob.get_reader().read_exact(&mut data_buffer)?;
+        let header_size = std::mem::size_of::<DataIndexHeader>();
+        if data_buffer.len() < 4 {
+            return Err(Error::other("Data index is truncated"));
+        }
+        let granularity = u32::from_le_bytes(data_buffer[..4].try_into().unwrap()) as usize;
+        if granularity == 0 {
+            return Err(Error::other("Invalid data index granularity"));
+        }
+        let item_count: usize = chrom
+            .iter()
+            .map(|chrom| chrom.size.div_ceil(granularity))
+            .sum();
+        let index_size = header_size + item_count * std::mem::size_of::<T>();
+        let mut valid_bins = item_count;
+        if T::INDEX_TYPE_CODE == DataIndexType::Sum
+            && item_count > 0
+            && data_buffer.len() + 4 == index_size
+        {
+            // The sum index written by the older versions, whose header only has the 4-byte
+            // granularity. The sums are 8-byte aligned, so they start at the same offset, but
+            // the blob is 4 bytes short and the upper half of the last sum is lost. The last
+            // bin is dropped and queried per-base instead.
+            data_buffer.resize(index_size, 0);
+            data_buffer[4..header_size].copy_from_slice(&(DataIndexType::Sum as u32).to_le_bytes());
+            data_buffer[index_size - std::mem::size_of::<T>()..].fill(0);
+            valid_bins -= 1;
+        }
+        if data_buffer.len() < index_size {
+            return Err(Error::other("Data index is truncated"));
+        }
+        let header = &DataIndex::<T>::from_raw(&data_buffer).header;
+        if u32::from_le(header.index_type) != T::INDEX_TYPE_CODE as u32 {
+            return Err(Error::other(format!(
+                "Unexpected data index type {} for {}",
+                u32::from_le(header.index_type),
+                T::INDEX_NAME
+            )));
+        }
         let mut ret = DataIndexRef {
             raw_data: data_buffer,
             offset_table: HashMap::new(),
+            valid_bins,
             phantom: PhantomData,
         };
         let mut offset = 0;
@@ -196,10 +258,10 @@ impl<T: DataSummary> DataIndex<T> {
         let mut mapped_blob = blob.mmap_mut()?;
         let index = Self::from_raw_mut(mapped_blob.as_mut());
         index.header.granularity = granularity.to_le();
-        index.header.index_type = T::INDEX_TYPE_CODE;
+        index.header.index_type = (T::INDEX_TYPE_CODE as u32).to_le();
 
         for (ofs, item) in index_result.into_iter().enumerate() {
-            index.pre_computed_data[ofs] = item.output.clone();
+            index.pre_computed_data[ofs] = item.output.to_format_byte_order();
         }
 
         Ok(())
@@ -1,12 +1,15 @@
 use std::{
-    fs::{File, OpenOptions},
-    io::{Cursor, Read, Result, Seek},
-    path::Path,
+    fs::File,
+    io::{Cursor, Error, Read, Result, Seek},
+    path::{Path, PathBuf},
 };
 
-use d4_framefile::Directory;
+use d4_framefile::{Directory, OpenResult};
 
-use crate::{d4file::validate_header, Header};
+use crate::{
+    d4file::{open_root_for_update, open_track_root_for_update, validate_header},
+    Header,
+};
 
 pub const INDEX_ROOT_NAME: &str = ".index";
 pub const SECONDARY_FRAME_INDEX_NAME: &str = "s_frame_index";
@@ -14,11 +17,28 @@ pub const SECONDARY_FRAME_INDEX_NAME: &str = "s_frame_index";
 mod data_index;
 mod sfi;
 
-pub use data_index::{DataIndexQueryResult, DataIndexRef, DataSummary, Sum};
+pub use data_index::{
+    histogram_percentile, DataIndexQueryResult, DataIndexRef, DataSummary, MinMax, Sum,
+    ValueHistogram, HISTOGRAM_INDEX_RANGE,
+};
 pub use sfi::{RecordFrameAddress, SecondaryFrameIndex};
 
 use self::data_index::DataIndex;
 
+fn resolve_track_path<T: Read + Seek, P: AsRef<Path>>(
+    file_root: &Directory<T>,
+    track: Option<P>,
+) -> Result<PathBuf> {
+    if let Some(track) = track {
+        return Ok(track.as_ref().to_path_buf());
+    }
+    if let Some(mut metadata_path) = file_root.find_first_object(Header::HEADER_STREAM_NAME) {
+        metadata_path.pop();
+        return Ok(metadata_path);
+    }
+    Err(Error::other("no track found in the file"))
+}
+
 #[allow(dead_code)]
 pub struct D4IndexCollection<T> {
     track_root: Directory<T>,
@@ -34,10 +54,19 @@ impl<T: Read + Seek> D4IndexCollection<T> {
             index_root,
         })
     }
-    pub fn from_reader(mut reader: T) -> Result<Self> {
+    pub fn from_reader(reader: T) -> Result<Self> {
+        Self::from_reader_with_track(reader, None::<&str>)
+    }
+    /// Open the index collection of the given track. If no track is specified, the first
+    /// track in the file will be used
+    pub fn from_reader_with_track<P: AsRef<Path>>(mut reader: T, track: Option<P>) -> Result<Self> {
         validate_header(&mut reader)?;
         let file_root = Directory::open_root(reader, 8)?;
-        Self::from_root_container(&file_root)
+        let track_path = resolve_track_path(&file_root, track)?;
+        match file_root.open(track_path)? {
+            OpenResult::SubDir(track_root) => Self::from_root_container(&track_root),
+            _ => Err(Error::other("track root not found")),
+        }
     }
     pub fn load_seconary_frame_index(&self) -> Result<SecondaryFrameIndex> {
         let header = Header::read(self.track_root.open_stream(Header::HEADER_STREAM_NAME)?)?;
@@ -47,6 +76,10 @@ impl<T: Read + Seek> D4IndexCollection<T> {
 
         SecondaryFrameIndex::from_reader(blob.get_reader(), header)
     }
+    /// Check if the data index of the given type has been built for the track
+    pub fn has_data_index<S: DataSummary>(&self) -> bool {
+        self.index_root.entry_kind(S::INDEX_NAME).is_some()
+    }
     pub fn load_data_index<S: DataSummary>(&self) -> Result<DataIndexRef<S>> {
         let header = Header::read(self.track_root.open_stream(Header::HEADER_STREAM_NAME)?)?;
         let mut data_index_blob = self.index_root.open_blob(S::INDEX_NAME)?;
@@ -56,20 +89,19 @@ impl<T: Read + Seek> D4IndexCollection<T> {
 
 impl D4IndexCollection<File> {
     pub fn open_for_write<P: AsRef<Path>>(path: P) -> Result<Self> {
-        let mut fp = OpenOptions::new()
-            .read(true)
-            .write(true)
-            .create(false)
-            .truncate(false)
-            .open(path.as_ref())?;
-        // TODO: Currently we assume that only single track file can be indexed. This means we need to index the file before merge otherwise we can not add any index to the file, but later this might be changed
-        validate_header(&mut fp)?;
-        let mut file_root = Directory::open_root_for_update(fp, 8)?;
-
-        // TODO: For mutliple track support, we need find the track root first
-        let track_root = file_root.clone();
+        Self::open_track_for_write(path, None::<&str>)
+    }
+    /// Open the index collection of the given track for write. If no track is specified, the
+    /// first track in the file will be used
+    pub fn open_track_for_write<P: AsRef<Path>, TP: AsRef<Path>>(
+        path: P,
+        track: Option<TP>,
+    ) -> Result<Self> {
+        let file_root = open_root_for_update(path)?;
+        let track_path = resolve_track_path(&file_root, track)?;
+        let mut track_root = open_track_root_for_update(&file_root, track_path)?;
 
-        let index_root = file_root.open_or_create_directory(INDEX_ROOT_NAME)?;
+        let index_root = track_root.open_or_create_directory(INDEX_ROOT_NAME)?;
 
         Ok(Self {
             track_root,
@@ -91,4 +123,14 @@ impl D4IndexCollection<File> {
         DataIndex::<Sum>::build(&mut self.track_root, &mut self.index_root, 65536)?;
         Ok(())
     }
+    pub fn create_minmax_index(&mut self) -> Result<()> {
+        DataIndex::<MinMax>::build(&mut self.track_root, &mut self.index_root, 65536)?;
+        Ok(())
+    }
+    /// Each histogram bin is about 1KB, so it uses a much coarser granularity than the
+    /// other data indexes to keep the index small enough for HTTP access
+    pub fn create_histogram_index(&mut self) -> Result<()> {
+        DataIndex::<ValueHistogram>::build(&mut self.track_root, &mut self.index_root, 1 << 20)?;
+        Ok(())
+    }
 }
@@ -3,12 +3,12 @@ use std::{
     {fs::File, io::Write},
 };
 
-use d4_framefile::{Directory, Stream};
+use d4_framefile::{mapped::MappedDirectory, Directory, Stream};
 
 use crate::{
     stab::{
-        RangeRecord, Record, RecordBlockParsingState, SparseArraryMetadata,
-        SECONDARY_TABLE_METADATA_NAME, SECONDARY_TABLE_NAME,
+        secondary_table_name, CompressionMethod, RangeRecord, Record, RecordBlockParsingState,
+        RecordFormat, RunLengthRecord, SparseArraryMetadata, SECONDARY_TABLE_METADATA_NAME,
     },
     Header,
 };
@@ -159,7 +159,7 @@ impl SecondaryFrameIndex {
     }
     pub(crate) fn from_data_track(track_root: &Directory<File>) -> Result<Self> {
         let header = Header::read(track_root.open_stream(Header::HEADER_STREAM_NAME)?)?;
-        let stab_root = track_root.map_directory(SECONDARY_TABLE_NAME)?;
+        let stab_root = track_root.map_directory(secondary_table_name(track_root))?;
         let stab_metadata = {
             let stream = stab_root
                 .open_stream(SECONDARY_TABLE_METADATA_NAME)
@@ -184,68 +184,95 @@ impl SecondaryFrameIndex {
             } else {
                 return Err(Error::new(ErrorKind::Other, "No such chrom"));
             };
-            let data_stream = stab_root.open_stream(&stream.id).unwrap();
-            let mut frame = data_stream.get_primary_frame();
-            let mut state =
-                RecordBlockParsingState::<RangeRecord>::new(stab_metadata.compression());
-            let mut parsing_buf = vec![];
-            let mut first_frame = true;
-            loop {
-                let offset = unsafe { frame.offset_from(stab_root.get_base_addr()) };
-                assert!(offset >= 0);
-                let offset = offset as u64;
-
-                parsing_buf.clear();
-                let rec_offset = state.first_record_offset();
-                state.parse_frame(frame.as_ref(), &mut parsing_buf);
-                let frame_size = std::mem::size_of_val(frame);
-
-                let has_next_frame = if let Some(next_frame) = frame.next_frame() {
-                    frame = next_frame;
-                    true
-                } else {
-                    false
-                };
-
-                for (id, block) in parsing_buf.iter().enumerate() {
-                    let mut recs = block.as_ref();
-                    if !has_next_frame {
-                        while let Some(last) = recs.last() {
-                            if !last.is_valid() {
-                                recs = &recs[..recs.len() - 1];
-                            } else {
-                                break;
-                            }
-                        }
-                    }
-                    if !recs.is_empty() {
-                        if id == 0 && block.is_single_record() {
-                            if let Some(last_entry) = items.last_mut() {
-                                last_entry.end_pos = recs[0].effective_range().1;
-                            }
-                        } else {
-                            items.push(FrameIndexEntry {
-                                chrom_id,
-                                start_pos: recs[0].effective_range().0,
-                                end_pos: recs[recs.len() - 1].effective_range().1,
-                                offset,
-                                frame_size: frame_size as u64,
-                                record_offset: rec_offset as u8,
-                                first_frame,
-                            });
-                        }
+            match stab_metadata.record_format()? {
+                RecordFormat::Range => index_data_stream::<RangeRecord>(
+                    &stab_root,
+                    &stream.id,
+                    stab_metadata.compression(),
+                    chrom_id,
+                    &mut items,
+                ),
+                RecordFormat::RunLength => index_data_stream::<RunLengthRecord>(
+                    &stab_root,
+                    &stream.id,
+                    stab_metadata.compression(),
+                    chrom_id,
+                    &mut items,
+                ),
+            }
+        }
+
+        Ok(Self { items, header })
+    }
+}
+
+/// Index the frames of a single secondary table stream, the stream is parsed with the record
+/// format it's actually stored with, so that the record offsets are in the unit of the stored
+/// records.
+fn index_data_stream<Rec: Record>(
+    stab_root: &MappedDirectory,
+    stream_id: &str,
+    compression: CompressionMethod,
+    chrom_id: u32,
+    items: &mut Vec<FrameIndexEntry>,
+) {
+    let data_stream = stab_root.open_stream(stream_id).unwrap();
+    let mut frame = data_stream.get_primary_frame();
+    let mut state = RecordBlockParsingState::<Rec>::new(compression);
+    let mut parsing_buf = vec![];
+    let mut first_frame = true;
+    loop {
+        let offset = unsafe { frame.offset_from(stab_root.get_base_addr()) };
+        assert!(offset >= 0);
+        let offset = offset as u64;
+
+        parsing_buf.clear();
+        let rec_offset = state.first_record_offset();
+        state.parse_frame(frame.as_ref(), &mut parsing_buf);
+        let frame_size = std::mem::size_of_val(frame);
+
+        let has_next_frame = if let Some(next_frame) = frame.next_frame() {
+            frame = next_frame;
+            true
+        } else {
+            false
+        };
+
+        for (id, block) in parsing_buf.iter().enumerate() {
+            let mut recs = block.as_ref();
+            if !has_next_frame {
+                while let Some(last) = recs.last() {
+                    if !last.is_valid() {
+                        recs = &recs[..recs.len() - 1];
                     } else {
                         break;
                     }
                 }
-
-                if !has_next_frame {
-                    break;
+            }
+            if !recs.is_empty() {
+                if id == 0 && block.is_single_record() {
+                    if let Some(last_entry) = items.last_mut() {
+                        last_entry.end_pos = recs[0].effective_range().1;
+                    }
+                } else {
+                    items.push(FrameIndexEntry {
+                        chrom_id,
+                        start_pos: recs[0].effective_range().0,
+                        end_pos: recs[recs.len() - 1].effective_range().1,
+                        offset,
+                        frame_size: frame_size as u64,
+                        record_offset: rec_offset as u8,
+                        first_frame,
+                    });
                 }
-                first_frame = false;
+            } else {
+                break;
             }
         }
 
-        Ok(Self { items, header })
+        if !has_next_frame {
+            break;
+        }
+        first_frame = false;
     }
 }
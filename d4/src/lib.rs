@@ -13,16 +13,19 @@ pub mod ssio;
 
 pub mod index;
 
+#[cfg(all(feature = "arrow", not(target_arch = "wasm32")))]
+pub mod arrow;
+
 pub use chrom::Chrom;
 
 #[cfg(all(feature = "mapped_io", not(target_arch = "wasm32")))]
 pub use d4file::{
-    find_tracks, find_tracks_in_file, D4FileBuilder, D4FileMerger, D4FileWriter, D4FileWriterExt,
-    D4MatrixReader, D4TrackReader, MultiTrackReader,
+    find_tracks, find_tracks_in_file, D4FileBuilder, D4FileMerger, D4FileSubset, D4FileUpdater,
+    D4FileWriter, D4FileWriterExt, D4MatrixReader, D4TrackReader, MultiTrackReader,
 };
 
 pub use dict::Dictionary;
 
-pub use header::Header;
+pub use header::{Header, ValueType};
 
 pub const VERSION: &str = env!("CARGO_PKG_VERSION");
@@ -5,61 +5,130 @@ use std::fs::File;
 use std::io::Result;
 use std::sync::{Arc, Mutex};
 
+use super::compressed::{BlockIndex, LazyBlocks};
 use super::DecodeResult;
 use crate::dict::{Dictionary, EncodeResult};
 use crate::header::Header;
 
 pub trait PrimaryTableMode: Sized {
     type HandleType: Send + ?Sized;
-    fn get_mapping_address(this: &mut PrimaryTable<Self>) -> Result<*const u8>;
+    fn get_mapping_handle(this: &mut PrimaryTable<Self>) -> Result<Arc<Mutex<Self::HandleType>>>;
+    fn get_handle_address(handle: &Mutex<Self::HandleType>) -> *const u8;
 }
 
 pub struct Reader;
 pub struct Writer;
 impl PrimaryTableMode for Reader {
     type HandleType = dyn AsRef<[u8]> + Send;
-    fn get_mapping_address(this: &mut PrimaryTable<Self>) -> Result<*const u8> {
-        let mapping_handle = if let Some(ref handle) = this.mapping_handle {
-            handle
-        } else {
+    fn get_mapping_handle(this: &mut PrimaryTable<Self>) -> Result<Arc<Mutex<Self::HandleType>>> {
+        if this.mapping_handle.is_none() {
             this.mapping_handle = Some(Arc::new(Mutex::new(this.data.mmap()?)));
-            this.mapping_handle.as_ref().unwrap()
-        };
-        Ok(mapping_handle.lock().unwrap().as_ref().as_ptr())
+        }
+        Ok(this.mapping_handle.clone().unwrap())
+    }
+    fn get_handle_address(handle: &Mutex<Self::HandleType>) -> *const u8 {
+        handle.lock().unwrap().as_ref().as_ptr()
     }
 }
 
 impl PrimaryTableMode for Writer {
     type HandleType = dyn AsMut<[u8]> + Send;
-    fn get_mapping_address(this: &mut PrimaryTable<Self>) -> Result<*const u8> {
-        let mapping_handle = if let Some(ref handle) = this.mapping_handle {
-            handle
-        } else {
+    fn get_mapping_handle(this: &mut PrimaryTable<Self>) -> Result<Arc<Mutex<Self::HandleType>>> {
+        if this.mapping_handle.is_none() {
             this.mapping_handle = Some(Arc::new(Mutex::new(this.data.mmap_mut()?)));
-            this.mapping_handle.as_ref().unwrap()
-        };
-        Ok(mapping_handle.lock().unwrap().as_mut().as_ptr())
+        }
+        Ok(this.mapping_handle.clone().unwrap())
+    }
+    fn get_handle_address(handle: &Mutex<Self::HandleType>) -> *const u8 {
+        handle.lock().unwrap().as_mut().as_ptr()
     }
 }
 
 pub struct PrimaryTable<M: PrimaryTableMode> {
-    bit_width: usize,
     dictionary: Dictionary,
     data: Blob<File>,
     mapping_handle: Option<Arc<Mutex<M::HandleType>>>,
+    /// The block index if the primary table is block compressed, in this case `data` holds the
+    /// compressed blocks
+    block_index: Option<Arc<BlockIndex>>,
 }
 
 pub struct PartialPrimaryTable<M: PrimaryTableMode> {
     mapping_handle: Arc<Mutex<M::HandleType>>,
     dictionary: Dictionary,
     bit_width: usize,
-    addr_start: usize,
+    /// The offset of the partition data from the beginning of the mapped memory
+    addr_offset: usize,
     chunk_size: usize,
     name: String,
     start: u32,
     end: u32,
 }
+
+/// The location of a partition in the primary table
+pub(super) struct ChunkLayout {
+    pub name: String,
+    pub start: u32,
+    pub end: u32,
+    /// The offset of the first byte of this partition in the primary table
+    pub offset: usize,
+    /// The number of bytes of this partition
+    pub size: usize,
+}
+
+/// Split the primary table described by the header into partitions
+pub(super) fn chunk_layout(header: &Header, mut max_chunk_size: Option<usize>) -> Vec<ChunkLayout> {
+    if let Some(ref mut max_chunk_size) = max_chunk_size {
+        *max_chunk_size -= *max_chunk_size % 8;
+    }
+
+    let bit_width = header.dictionary.bit_width();
+
+    let mut ret = vec![];
+    let mut offset = 0;
+    for chrom in header.chrom_list.iter() {
+        let mut size = chrom.size;
+        let mut start = 0;
+        while size > 0 {
+            let chunk_size = match max_chunk_size {
+                Some(size_limit) if size_limit < size => size_limit,
+                _ => size,
+            };
+            let nbytes = (chunk_size * bit_width + 7) / 8;
+            ret.push(ChunkLayout {
+                name: chrom.name.clone(),
+                start,
+                end: start + chunk_size as u32,
+                offset,
+                size: nbytes,
+            });
+            size -= chunk_size;
+            offset += nbytes;
+            start += chunk_size as u32;
+        }
+    }
+    ret
+}
+
 impl<M: PrimaryTableMode> PartialPrimaryTable<M> {
+    /// Create a partition that is backed by the given memory handle
+    pub(super) fn new(
+        mapping_handle: Arc<Mutex<M::HandleType>>,
+        dictionary: Dictionary,
+        layout: ChunkLayout,
+        addr_offset: usize,
+    ) -> Self {
+        Self {
+            mapping_handle,
+            bit_width: dictionary.bit_width(),
+            dictionary,
+            addr_offset,
+            chunk_size: layout.size,
+            name: layout.name,
+            start: layout.start,
+            end: layout.end,
+        }
+    }
     pub fn region(&self) -> (&str, u32, u32) {
         (&self.name, self.start, self.end)
     }
@@ -74,8 +143,9 @@ impl<M: PrimaryTableMode> PartialPrimaryTable<M> {
         let base_offset = self.start as usize;
         let bit_width = self.bit_width;
         let dict = self.dictionary.clone();
+        let addr_start = M::get_handle_address(&self.mapping_handle) as usize + self.addr_offset;
         let slice =
-            unsafe { std::slice::from_raw_parts_mut(self.addr_start as *mut u8, self.chunk_size) };
+            unsafe { std::slice::from_raw_parts_mut(addr_start as *mut u8, self.chunk_size) };
         PrimaryTableCodec {
             memory: slice,
             base_offset,
@@ -88,72 +158,87 @@ impl<M: PrimaryTableMode> PartialPrimaryTable<M> {
 }
 
 impl<M: PrimaryTableMode> PrimaryTable<M> {
-    #[allow(clippy::ptr_offset_with_cast)]
     pub fn split_chunk(
         &mut self,
         header: &Header,
-        mut max_chunk_size: Option<usize>,
+        max_chunk_size: Option<usize>,
     ) -> Result<Vec<PartialPrimaryTable<M>>> {
-        let data = M::get_mapping_address(self)?;
-
-        if let Some(ref mut max_chunk_size) = max_chunk_size {
-            *max_chunk_size -= *max_chunk_size % 8;
-        }
-
-        let bit_width = self.bit_width;
-
-        let mut ret = vec![];
-        let mut offset = 0;
-        for chrom in header.chrom_list.iter() {
-            let mut size = chrom.size;
-            let mut start = 0;
-            while size > 0 {
-                let chunk_size = match max_chunk_size {
-                    Some(size_limit) if size_limit < size => size_limit,
-                    _ => size,
-                };
-                let nbytes = (chunk_size * bit_width + 7) / 8;
-                ret.push(PartialPrimaryTable {
-                    mapping_handle: self.mapping_handle.clone().unwrap(),
-                    addr_start: unsafe { data.offset(offset as isize) as usize },
-                    chunk_size: nbytes,
-                    name: chrom.name.clone(),
-                    start,
-                    bit_width,
-                    end: start + chunk_size as u32,
-                    dictionary: self.dictionary.clone(),
-                });
-                size -= chunk_size;
-                offset += nbytes;
-                start += chunk_size as u32;
-            }
-        }
-        Ok(ret)
+        let mapping_handle = M::get_mapping_handle(self)?;
+        Ok(chunk_layout(header, max_chunk_size)
+            .into_iter()
+            .map(|layout| {
+                let offset = layout.offset;
+                PartialPrimaryTable::new(
+                    mapping_handle.clone(),
+                    self.dictionary.clone(),
+                    layout,
+                    offset,
+                )
+            })
+            .collect())
     }
 }
 impl PrimaryTable<Writer> {
     pub(crate) fn create(directory: &mut Directory<File>, header: &Header) -> Result<Self> {
         let size = header.primary_table_size();
-        let data = directory.create_blob(".ptab", size)?;
+        let data = directory.create_blob(PRIMARY_TABLE_NAME, size)?;
+        Ok(PrimaryTable {
+            dictionary: header.dictionary.clone(),
+            data,
+            mapping_handle: None,
+            block_index: None,
+        })
+    }
+    /// Open the primary table of an existing track for in-place update
+    pub(crate) fn open_for_update(
+        directory: &mut Directory<File>,
+        header: &Header,
+    ) -> Result<Self> {
+        let data = directory.open_blob(PRIMARY_TABLE_NAME)?;
         Ok(PrimaryTable {
             dictionary: header.dictionary.clone(),
-            bit_width: header.dictionary.bit_width(),
             data,
             mapping_handle: None,
+            block_index: None,
         })
     }
 }
 
 impl PrimaryTable<Reader> {
     pub(crate) fn open(root_dir: &mut Directory<File>, header: &Header) -> Result<Self> {
-        let chunk = root_dir.open_blob(PRIMARY_TABLE_NAME)?;
+        let (data, block_index) = if root_dir.entry_kind(COMPRESSED_PRIMARY_TABLE_NAME).is_some() {
+            let mut data = root_dir.open_blob(COMPRESSED_PRIMARY_TABLE_NAME)?;
+            let block_index = BlockIndex::load(&mut data)?;
+            (data, Some(Arc::new(block_index)))
+        } else {
+            (root_dir.open_blob(PRIMARY_TABLE_NAME)?, None)
+        };
         Ok(PrimaryTable {
             dictionary: header.dictionary.clone(),
-            bit_width: header.dictionary.bit_width(),
-            data: chunk,
+            data,
             mapping_handle: None,
+            block_index,
         })
     }
+
+    /// Split a block compressed primary table, each partition is decompressed when the first
+    /// decoder is created
+    fn split_compressed(
+        &mut self,
+        header: &Header,
+        max_chunk_size: Option<usize>,
+        block_index: &Arc<BlockIndex>,
+    ) -> Vec<PartialPrimaryTable<Reader>> {
+        chunk_layout(header, max_chunk_size)
+            .into_iter()
+            .map(|layout| {
+                let data: Arc<Mutex<<Reader as PrimaryTableMode>::HandleType>> = Arc::new(
+                    Mutex::new(LazyBlocks::new(&self.data, block_index.clone(), &layout)),
+                );
+                PartialPrimaryTable::new(data, self.dictionary.clone(), layout, 0)
+            })
+            .collect()
+    }
 }
 
 pub struct PrimaryTableCodec<M: PrimaryTableMode> {
@@ -182,7 +267,8 @@ impl DecoderParameter {
     #[inline(always)]
     fn read_value(&mut self, idx: usize) -> u32 {
         let shift = self.shift[self.rule_base + idx];
-        let data: &u32 = unsafe { &*(*self.pointers.get_unchecked(idx) as *const u32) };
+        let data =
+            unsafe { std::ptr::read_unaligned(*self.pointers.get_unchecked(idx) as *const u32) };
         data >> shift
     }
 
@@ -212,9 +298,18 @@ impl DecoderParameter {
     }
 
     fn new(decoders: &[PrimaryTableCodec<Reader>]) -> Self {
+        // A zero-sized primary table doesn't have any data, so we read from a zero buffer
+        // instead. As the bit width is zero, the pointer never moves.
+        static ZERO_SIZED_TABLE: [u8; 8] = [0; 8];
         let pointers = decoders
             .iter()
-            .map(|enc| &enc.memory[0] as *const u8)
+            .map(|enc| {
+                if enc.bit_width == 0 || enc.memory.is_empty() {
+                    ZERO_SIZED_TABLE.as_ptr()
+                } else {
+                    enc.memory.as_ptr()
+                }
+            })
             .collect::<SmallVec<_>>();
 
         let mut shift = smallvec![];
@@ -326,13 +421,16 @@ impl PrimaryTableCodec<Writer> {
         let actual_offset = offset - self.base_offset;
         let start: &mut u32 =
             unsafe { std::mem::transmute(&mut self.memory[actual_offset * self.bit_width / 8]) };
+        let shift = actual_offset * self.bit_width % 8;
         match self.dict.encode_value(value) {
             EncodeResult::DictionaryIndex(idx) => {
-                *start |= idx << (actual_offset * self.bit_width % 8);
+                // Clear the slot first, since we may overwrite a table that is already encoded
+                *start &= !(self.mask << shift);
+                *start |= idx << shift;
                 true
             }
             _ => {
-                *start |= ((1 << self.bit_width) - 1) << (actual_offset * self.bit_width % 8);
+                *start |= self.mask << shift;
                 false
             }
         }
@@ -407,7 +505,7 @@ impl Decoder for PrimaryTableCodec<Reader> {
 
             for idx in 0..count {
                 start = unsafe { start.add(addr_delta[idx % 8]) };
-                let value = unsafe { &*(start as *const u32) };
+                let value = unsafe { std::ptr::read_unaligned(start as *const u32) };
                 let value = (value >> shift[idx % 8]) & mask;
                 let result = if value == mask {
                     DecodeResult::Maybe(self.dict.decode_value(mask).unwrap_or(0))
@@ -453,6 +551,9 @@ impl PrimaryTableReader for PrimaryTable<Reader> {
         header: &Header,
         size_limit: Option<usize>,
     ) -> Result<Vec<Self::Partition>> {
+        if let Some(block_index) = self.block_index.clone() {
+            return Ok(self.split_compressed(header, size_limit, &block_index));
+        }
         self.split_chunk(header, size_limit)
     }
 }
@@ -0,0 +1,510 @@
+//! The block compressed primary table.
+//! The bit array is split into fixed-size blocks and each block is compressed independently, so
+//! that a region of the primary table can be decoded without decompressing the entire table.
+//!
+//! The layout of the blob is:
+//! - The size of the uncompressed bit array (u64)
+//! - The block size in bytes (u32)
+//! - The compression method (u32), 0 for uncompressed, 1 for deflate, 2 for zstd and 3 for lz4
+//! - The offsets of the blocks relative to the beginning of the blob (u64), there are `block_count + 1` offsets
+//!   and the last one is the end of the last block
+//! - The compressed blocks
+
+use super::bit_array::{
+    chunk_layout, ChunkLayout, PartialPrimaryTable, PrimaryTableCodec, PrimaryTableMode, Writer,
+};
+use super::{
+    PTablePartitionWriter, PrimaryTableWriter, COMPRESSED_PRIMARY_TABLE_NAME, PRIMARY_TABLE_NAME,
+};
+use crate::dict::Dictionary;
+use crate::header::Header;
+use crate::stab::CompressionMethod;
+use d4_framefile::{Blob, Directory};
+
+use std::cell::OnceCell;
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{Error, Read, Result, Seek};
+use std::sync::{Arc, Mutex};
+
+const HEADER_SIZE: usize = 16;
+
+/// The default size of an uncompressed block
+const DEFAULT_BLOCK_SIZE: usize = 65536;
+
+fn method_code(method: &CompressionMethod) -> u32 {
+    match method {
+        CompressionMethod::NoCompression => 0,
+        CompressionMethod::Deflate(_) => 1,
+        CompressionMethod::Zstd(_) => 2,
+        CompressionMethod::Lz4 => 3,
+    }
+}
+
+fn method_from_code(code: u32) -> Result<CompressionMethod> {
+    match code {
+        0 => Ok(CompressionMethod::NoCompression),
+        1 => Ok(CompressionMethod::Deflate(0)),
+        2 => Ok(CompressionMethod::Zstd(0)),
+        3 => Ok(CompressionMethod::Lz4),
+        _ => Err(Error::other("Unknown primary table compression method")),
+    }
+}
+
+/// The index of the compressed blocks, which is loaded from the beginning of the blob
+pub(crate) struct BlockIndex {
+    raw_size: usize,
+    block_size: usize,
+    method: CompressionMethod,
+    offsets: Vec<u64>,
+}
+
+impl BlockIndex {
+    pub(crate) fn load<R: Read + Seek>(blob: &mut Blob<R>) -> Result<Self> {
+        let mut header = [0u8; HEADER_SIZE];
+        blob.read_block(0, &mut header)?;
+        let raw_size = u64::from_le_bytes(header[0..8].try_into().unwrap()) as usize;
+        let block_size = u32::from_le_bytes(header[8..12].try_into().unwrap()) as usize;
+        let method = method_from_code(u32::from_le_bytes(header[12..16].try_into().unwrap()))?;
+        if block_size == 0 {
+            return Err(Error::other("Invalid primary table block size"));
+        }
+        let block_count = raw_size.div_ceil(block_size);
+        let mut buffer = vec![0; (block_count + 1) * 8];
+        blob.read_block(HEADER_SIZE as u64, &mut buffer)?;
+        let offsets: Vec<u64> = buffer
+            .chunks(8)
+            .map(|offset| u64::from_le_bytes(offset.try_into().unwrap()))
+            .collect();
+        // The blocks should be laid out one after another right after the offset table
+        if offsets[0] != (HEADER_SIZE + buffer.len()) as u64
+            || offsets.windows(2).any(|pair| pair[0] > pair[1])
+            || offsets[block_count] > blob.size() as u64
+        {
+            return Err(Error::other("Corrupted primary table block index"));
+        }
+        Ok(Self {
+            raw_size,
+            block_size,
+            method,
+            offsets,
+        })
+    }
+
+    /// The size of the uncompressed bit array
+    pub(crate) fn raw_size(&self) -> usize {
+        self.raw_size
+    }
+
+    /// Decompress the bytes starting at `offset` of the uncompressed bit array to the buffer
+    pub(crate) fn read_range<R: Read + Seek>(
+        &self,
+        blob: &mut Blob<R>,
+        offset: usize,
+        buf: &mut [u8],
+    ) -> Result<usize> {
+        let end = (offset + buf.len()).min(self.raw_size);
+        if offset >= end {
+            return Ok(0);
+        }
+        let (first, last) = (offset / self.block_size, (end - 1) / self.block_size);
+        // Read all the compressed blocks at once, since each read is a request for HTTP readers
+        let base = self.offsets[first];
+        let mut compressed = vec![0; (self.offsets[last + 1] - base) as usize];
+        blob.read_block(base, &mut compressed)?;
+        for idx in first..=last {
+            let block_begin = idx * self.block_size;
+            let block_end = (block_begin + self.block_size).min(self.raw_size);
+            let data = &compressed[(self.offsets[idx] - base) as usize..]
+                [..(self.offsets[idx + 1] - self.offsets[idx]) as usize];
+            let block = self
+                .method
+                .decompress_buffer(data, block_end - block_begin)?;
+            if block.len() != block_end - block_begin {
+                return Err(Error::other("Corrupted primary table block"));
+            }
+            let (left, right) = (offset.max(block_begin), end.min(block_end));
+            buf[left - offset..right - offset]
+                .copy_from_slice(&block[left - block_begin..right - block_begin]);
+        }
+        Ok(end - offset)
+    }
+}
+
+/// The data of a primary table partition, which is decompressed when it's accessed for the
+/// first time
+pub(super) struct LazyBlocks {
+    blob: Blob<File>,
+    block_index: Arc<BlockIndex>,
+    offset: usize,
+    size: usize,
+    data: OnceCell<Vec<u8>>,
+}
+
+impl LazyBlocks {
+    pub(super) fn new(
+        blob: &Blob<File>,
+        block_index: Arc<BlockIndex>,
+        layout: &ChunkLayout,
+    ) -> Self {
+        Self {
+            blob: blob.get_view(0, blob.size()),
+            block_index,
+            offset: layout.offset,
+            size: layout.size,
+            data: OnceCell::new(),
+        }
+    }
+}
+
+impl AsRef<[u8]> for LazyBlocks {
+    fn as_ref(&self) -> &[u8] {
+        self.data.get_or_init(|| {
+            // The decoder reads 4 bytes at a time, so we need some padding at the end
+            let mut buffer = vec![0; self.size + 4];
+            let mut blob = self.blob.get_view(0, self.blob.size());
+            // The decoders can't report errors, so a corrupted partition is decoded as zeros
+            if let Err(err) =
+                self.block_index
+                    .read_range(&mut blob, self.offset, &mut buffer[..self.size])
+            {
+                log::error!("Unable to decompress the primary table: {}", err);
+                buffer.iter_mut().for_each(|byte| *byte = 0);
+            }
+            buffer
+        })
+    }
+}
+
+/// The primary table data that is read from any random access reader, the data is decompressed
+/// on the fly if the primary table is block compressed
+pub(crate) struct PrimaryTableBlob<R> {
+    blob: Blob<R>,
+    block_index: Option<Arc<BlockIndex>>,
+    offset: usize,
+    size: usize,
+}
+
+impl<R: Read + Seek> PrimaryTableBlob<R> {
+    pub(crate) fn open(track_root: &Directory<R>) -> Result<Self> {
+        if track_root
+            .entry_kind(COMPRESSED_PRIMARY_TABLE_NAME)
+            .is_some()
+        {
+            let mut blob = track_root.open_blob(COMPRESSED_PRIMARY_TABLE_NAME)?;
+            let block_index = BlockIndex::load(&mut blob)?;
+            Ok(Self {
+                blob,
+                offset: 0,
+                size: block_index.raw_size(),
+                block_index: Some(Arc::new(block_index)),
+            })
+        } else {
+            let blob = track_root.open_blob(PRIMARY_TABLE_NAME)?;
+            Ok(Self {
+                offset: 0,
+                size: blob.size(),
+                blob,
+                block_index: None,
+            })
+        }
+    }
+
+    pub(crate) fn size(&self) -> usize {
+        self.size
+    }
+
+    pub(crate) fn get_view(&self, offset: u64, size: usize) -> Self {
+        let offset = (offset as usize).min(self.size);
+        let size = (self.size - offset).min(size);
+        match self.block_index {
+            Some(ref block_index) => Self {
+                blob: self.blob.get_view(0, self.blob.size()),
+                block_index: Some(block_index.clone()),
+                offset: self.offset + offset,
+                size,
+            },
+            None => Self {
+                blob: self.blob.get_view(offset as u64, size),
+                block_index: None,
+                offset: 0,
+                size,
+            },
+        }
+    }
+
+    pub(crate) fn read_block(&mut self, offset: u64, buf: &mut [u8]) -> Result<usize> {
+        match self.block_index {
+            Some(ref block_index) => {
+                let offset = (offset as usize).min(self.size);
+                let size = buf.len().min(self.size - offset);
+                block_index.read_range(&mut self.blob, self.offset + offset, &mut buf[..size])
+            }
+            None => self.blob.read_block(offset, buf),
+        }
+    }
+}
+
+/// The state shared by the block compressed primary table writer and its partitions. The
+/// compressed table is written to the file by `CompressedBitArrayWriter::finish`.
+struct BlockSink {
+    directory: Directory<File>,
+    method: CompressionMethod,
+    block_size: usize,
+    raw_size: usize,
+    blocks: Vec<Option<Vec<u8>>>,
+    /// The blocks that are shared by multiple partitions and not completely written yet, the
+    /// value is the block data and the number of bytes that have been written
+    pending: HashMap<usize, (Vec<u8>, usize)>,
+    /// If the compressed table has been written to the file
+    written: bool,
+}
+
+impl BlockSink {
+    fn block_range(&self, idx: usize) -> (usize, usize) {
+        let begin = idx * self.block_size;
+        (begin, (begin + self.block_size).min(self.raw_size))
+    }
+
+    fn block_count(&self) -> usize {
+        self.raw_size.div_ceil(self.block_size)
+    }
+
+    fn check_not_written(&self) -> Result<()> {
+        if self.written {
+            return Err(Error::other(
+                "The compressed primary table has already been written",
+            ));
+        }
+        Ok(())
+    }
+
+    fn put_block(&mut self, idx: usize, block: Vec<u8>) -> Result<()> {
+        self.check_not_written()?;
+        self.blocks[idx] = Some(block);
+        Ok(())
+    }
+
+    fn put_partial_block(&mut self, idx: usize, offset: usize, data: &[u8]) -> Result<()> {
+        self.check_not_written()?;
+        let (begin, end) = self.block_range(idx);
+        let (block, written) = self
+            .pending
+            .entry(idx)
+            .or_insert_with(|| (vec![0; end - begin], 0));
+        block[offset..offset + data.len()].copy_from_slice(data);
+        *written += data.len();
+        if *written == end - begin {
+            let (block, _) = self.pending.remove(&idx).unwrap();
+            self.blocks[idx] = Some(self.method.compress_buffer(&block)?);
+        }
+        Ok(())
+    }
+
+    fn write_table(&mut self) -> Result<()> {
+        self.check_not_written()?;
+        let block_count = self.block_count();
+        self.blocks.resize_with(block_count, || None);
+        for idx in 0..block_count {
+            if self.blocks[idx].is_none() {
+                // The partially written blocks and the blocks that no partition has touched
+                let block = match self.pending.remove(&idx) {
+                    Some((block, _)) => block,
+                    None => {
+                        let (begin, end) = self.block_range(idx);
+                        vec![0; end - begin]
+                    }
+                };
+                self.blocks[idx] = Some(self.method.compress_buffer(&block)?);
+            }
+        }
+
+        let data_offset = HEADER_SIZE + (block_count + 1) * 8;
+        let data_size: usize = self.blocks.iter().flatten().map(Vec::len).sum();
+        let mut blob = self
+            .directory
+            .create_blob(COMPRESSED_PRIMARY_TABLE_NAME, data_offset + data_size)?;
+        let mut mapped = blob.mmap_mut()?;
+        let buffer = mapped.as_mut();
+        buffer[0..8].copy_from_slice(&(self.raw_size as u64).to_le_bytes());
+        buffer[8..12].copy_from_slice(&(self.block_size as u32).to_le_bytes());
+        buffer[12..16].copy_from_slice(&method_code(&self.method).to_le_bytes());
+        let mut offset = data_offset;
+        for (idx, block) in self.blocks.iter().flatten().enumerate() {
+            buffer[HEADER_SIZE + idx * 8..][..8].copy_from_slice(&(offset as u64).to_le_bytes());
+            buffer[offset..offset + block.len()].copy_from_slice(block);
+            offset += block.len();
+        }
+        buffer[HEADER_SIZE + block_count * 8..][..8]
+            .copy_from_slice(&(offset as u64).to_le_bytes());
+        self.written = true;
+        self.blocks.clear();
+        Ok(())
+    }
+}
+
+/// The memory that a partition writer encodes to, the blocks are compressed once the partition
+/// is finished
+struct PartitionBuffer {
+    sink: Arc<Mutex<BlockSink>>,
+    method: CompressionMethod,
+    block_size: usize,
+    raw_size: usize,
+    offset: usize,
+    size: usize,
+    data: Vec<u8>,
+    finished: bool,
+}
+
+impl AsMut<[u8]> for PartitionBuffer {
+    fn as_mut(&mut self) -> &mut [u8] {
+        self.data.as_mut()
+    }
+}
+
+impl PartitionBuffer {
+    fn finish(&mut self) -> Result<()> {
+        if self.finished {
+            return Ok(());
+        }
+        let (begin, end) = (self.offset, self.offset + self.size);
+        let mut full_blocks = vec![];
+        let mut partial_blocks = vec![];
+        let mut pos = begin;
+        while pos < end {
+            let idx = pos / self.block_size;
+            let block_begin = idx * self.block_size;
+            let block_end = (block_begin + self.block_size).min(self.raw_size);
+            let segment_end = block_end.min(end);
+            let segment = &self.data[pos - begin..segment_end - begin];
+            if pos == block_begin && segment_end == block_end {
+                // We compress the blocks owned by this partition without holding the lock
+                full_blocks.push((idx, self.method.compress_buffer(segment)?));
+            } else {
+                partial_blocks.push((idx, pos - block_begin, segment));
+            }
+            pos = segment_end;
+        }
+        let mut sink = self.sink.lock().map_err(|_| Error::other("Lock Error"))?;
+        for (idx, block) in full_blocks {
+            sink.put_block(idx, block)?;
+        }
+        for (idx, offset, data) in partial_blocks {
+            sink.put_partial_block(idx, offset, data)?;
+        }
+        self.finished = true;
+        Ok(())
+    }
+}
+
+/// The partition writer for the block compressed primary table. The values are encoded in
+/// memory and the blocks are compressed when the partition is finished.
+pub struct CompressedBitArrayPartWriter {
+    table: PartialPrimaryTable<Writer>,
+    buffer: Arc<Mutex<PartitionBuffer>>,
+}
+
+impl PTablePartitionWriter for CompressedBitArrayPartWriter {
+    type EncoderType = PrimaryTableCodec<Writer>;
+    fn make_encoder(&mut self) -> Self::EncoderType {
+        self.table.make_encoder()
+    }
+    fn region(&self) -> (&str, u32, u32) {
+        self.table.region()
+    }
+    fn can_encode(&self, value: i32) -> bool {
+        self.table.can_encode(value)
+    }
+    fn bit_width(&self) -> usize {
+        self.table.bit_width()
+    }
+    fn finish(&mut self) -> Result<()> {
+        self.buffer
+            .lock()
+            .map_err(|_| Error::other("Lock Error"))?
+            .finish()
+    }
+}
+
+/// The writer for the block compressed primary table. The partitions are the same as the bit
+/// array primary table, and the compressed table can be read by `BitArrayReader`. The table is
+/// only written to the file by `finish`, after all the partitions are finished.
+pub struct CompressedBitArrayWriter {
+    dictionary: Dictionary,
+    sink: Arc<Mutex<BlockSink>>,
+}
+
+impl PrimaryTableWriter for CompressedBitArrayWriter {
+    type Partition = CompressedBitArrayPartWriter;
+
+    fn create(directory: &mut Directory<File>, header: &Header) -> Result<Self> {
+        let sink = BlockSink {
+            directory: directory.clone(),
+            method: CompressionMethod::Deflate(5),
+            block_size: DEFAULT_BLOCK_SIZE,
+            raw_size: header.primary_table_size(),
+            blocks: vec![],
+            pending: HashMap::new(),
+            written: false,
+        };
+        Ok(Self {
+            dictionary: header.dictionary.clone(),
+            sink: Arc::new(Mutex::new(sink)),
+        })
+    }
+
+    fn split(
+        &mut self,
+        header: &Header,
+        size_limit: Option<usize>,
+    ) -> Result<Vec<Self::Partition>> {
+        let (method, block_size, raw_size) = {
+            let mut sink = self.sink.lock().map_err(|_| Error::other("Lock Error"))?;
+            sink.check_not_written()?;
+            let block_count = sink.block_count();
+            sink.blocks.resize_with(block_count, || None);
+            (sink.method, sink.block_size, sink.raw_size)
+        };
+        Ok(chunk_layout(header, size_limit)
+            .into_iter()
+            .map(|layout| {
+                let buffer = Arc::new(Mutex::new(PartitionBuffer {
+                    sink: self.sink.clone(),
+                    method,
+                    block_size,
+                    raw_size,
+                    offset: layout.offset,
+                    size: layout.size,
+                    // The encoder accesses 4 bytes at a time, so we need some padding at the end
+                    data: vec![0; layout.size + 4],
+                    finished: false,
+                }));
+                let handle: Arc<Mutex<<Writer as PrimaryTableMode>::HandleType>> = buffer.clone();
+                CompressedBitArrayPartWriter {
+                    table: PartialPrimaryTable::new(handle, self.dictionary.clone(), layout, 0),
+                    buffer,
+                }
+            })
+            .collect())
+    }
+
+    /// Set the compression method of the blocks, default is deflate with level 5
+    fn set_compression(&mut self, method: CompressionMethod) -> Result<()> {
+        let mut sink = self.sink.lock().map_err(|_| Error::other("Lock Error"))?;
+        // The partitions compress their blocks with the method when they are split
+        if !sink.blocks.is_empty() || sink.written {
+            return Err(Error::other(
+                "The compression method should be set before the table is split",
+            ));
+        }
+        sink.method = method;
+        Ok(())
+    }
+
+    fn finish(&mut self) -> Result<()> {
+        self.sink
+            .lock()
+            .map_err(|_| Error::other("Lock Error"))?
+            .write_table()
+    }
+}
@@ -1,16 +1,21 @@
 //! The primary table implementaion
 //! The default primary table implementation is bit array, which uses K-bits integer represents an entity in the
-//! primary table.
+//! primary table. The bit array can also be stored as independently compressed blocks, which is
+//! transparent to the readers.
 
 use crate::header::Header;
+use crate::stab::CompressionMethod;
 use d4_framefile::Directory;
 use std::fs::File;
-use std::io::Result;
+use std::io::{Error, Result};
 
 mod bit_array;
+mod compressed;
 
 /// The name of the primary table blob under the root container
 pub const PRIMARY_TABLE_NAME: &str = ".ptab";
+/// The name of the block compressed primary table blob under the root container
+pub const COMPRESSED_PRIMARY_TABLE_NAME: &str = ".zptab";
 
 /// The result of decoding a value from a primary table
 pub enum DecodeResult {
@@ -29,6 +34,18 @@ pub trait PrimaryTableWriter: Sized {
     /// Split the primary table into parallel partitions
     fn split(&mut self, header: &Header, size_limit: Option<usize>)
         -> Result<Vec<Self::Partition>>;
+    /// Set the compression method of the primary table, fails if the primary table can't be
+    /// compressed
+    fn set_compression(&mut self, _method: CompressionMethod) -> Result<()> {
+        Err(Error::other(
+            "The primary table doesn't support compression",
+        ))
+    }
+    /// Finish writing the primary table, this should be called after all the partitions are
+    /// finished
+    fn finish(&mut self) -> Result<()> {
+        Ok(())
+    }
 }
 
 /// The trait that is used as one of the parallel partition split from the primary table writer
@@ -43,6 +60,11 @@ pub trait PTablePartitionWriter: Send {
     fn can_encode(&self, value: i32) -> bool;
     /// Report the bit width for this primary table
     fn bit_width(&self) -> usize;
+    /// Finish writing the partition. Some primary tables, e.g. the block compressed one, only
+    /// store the data of a partition once it's finished
+    fn finish(&mut self) -> Result<()> {
+        Ok(())
+    }
 }
 
 /// Any type used to encode a value in primary table
@@ -108,7 +130,7 @@ pub trait Decoder {
 
 /// The writer for bit-array backed primary table
 pub type BitArrayWriter = bit_array::PrimaryTable<bit_array::Writer>;
-/// The reader for bit-array backed primary table
+/// The reader for bit-array backed primary table, both plain and block compressed tables are supported
 pub type BitArrayReader = bit_array::PrimaryTable<bit_array::Reader>;
 /// The partition writer for bit-array backed primary table
 pub type BitArrayPartWriter = bit_array::PartialPrimaryTable<bit_array::Writer>;
@@ -121,3 +143,6 @@ pub type BitArrayDecoder = bit_array::PrimaryTableCodec<bit_array::Reader>;
 pub type BitArrayEncoder = bit_array::PrimaryTableCodec<bit_array::Writer>;
 
 pub use bit_array::MatrixDecoder;
+pub use compressed::{CompressedBitArrayPartWriter, CompressedBitArrayWriter};
+
+pub(crate) use compressed::PrimaryTableBlob;
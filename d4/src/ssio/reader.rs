@@ -1,24 +1,29 @@
 use std::{
+    fs::File,
     io::{Error, ErrorKind, Read, Result, Seek},
     path::{Path, PathBuf},
 };
 
-use d4_framefile::{Blob, Directory, OpenResult};
+use d4_framefile::{Directory, OpenResult};
 use reqwest::IntoUrl;
 
 use crate::{
     d4file::validate_header,
     index::{D4IndexCollection, DataIndexRef, DataSummary, SecondaryFrameIndex},
-    ptab::PRIMARY_TABLE_NAME,
-    stab::{CompressionMethod, RecordBlockParsingState, SECONDARY_TABLE_NAME},
+    ptab::PrimaryTableBlob,
+    stab::{secondary_table_name, CompressionMethod, RecordBlockParsingState},
     Chrom, Header,
 };
 
-use super::{http::HttpReader, table::SecondaryTableRef, view::D4TrackView};
+use super::{
+    http::HttpReader,
+    table::SecondaryTableRef,
+    view::{D4MatrixView, D4TrackView},
+};
 
 pub struct D4TrackReader<R: Read + Seek> {
     header: Header,
-    primary_table: Blob<R>,
+    primary_table: PrimaryTableBlob<R>,
     secondary_table: Vec<SecondaryTableRef<R>>,
     sfi: Option<SecondaryFrameIndex>,
     track_root: Directory<R>,
@@ -46,7 +51,62 @@ impl D4MatrixReader<HttpReader> {
         })
     }
 }
+impl D4MatrixReader<File> {
+    pub fn open_tracks_in_file<P: AsRef<Path>, Pat: FnMut(Option<&Path>) -> bool>(
+        path: P,
+        pat: Pat,
+    ) -> Result<D4MatrixReader<File>> {
+        let mut track_to_open = vec![];
+        crate::d4file::find_tracks(File::open(path.as_ref())?, pat, &mut track_to_open)?;
+        let tracks = track_to_open
+            .into_iter()
+            .map(|track| D4TrackReader::from_reader(File::open(path.as_ref())?, track.to_str()))
+            .collect::<Result<Vec<_>>>()?;
+        Self::new(tracks)
+    }
+}
+
 impl<R: Read + Seek> D4MatrixReader<R> {
+    /// Create a matrix reader from a group of track readers
+    pub fn new<T: IntoIterator<Item = D4TrackReader<R>>>(tracks: T) -> Result<Self> {
+        let tracks: Vec<_> = tracks.into_iter().collect();
+        if tracks
+            .windows(2)
+            .any(|w| w[0].chrom_list() != w[1].chrom_list())
+        {
+            return Err(Error::other("Inconsistent reference genome"));
+        }
+        Ok(Self { tracks })
+    }
+
+    /// The number of tracks in this matrix
+    pub fn track_count(&self) -> usize {
+        self.tracks.len()
+    }
+
+    pub fn chrom_list(&self) -> &[Chrom] {
+        self.tracks.first().map_or(&[], |track| track.chrom_list())
+    }
+
+    /// Get a view that scans all the tracks in the given region row by row
+    pub fn get_matrix_view(
+        &mut self,
+        chrom: &str,
+        begin: u32,
+        end: u32,
+    ) -> Result<D4MatrixView<R>> {
+        let mut views = Vec::with_capacity(self.tracks.len());
+        self.get_view(chrom, begin, end, &mut views)?;
+        Ok(D4MatrixView {
+            chrom: chrom.to_string(),
+            end,
+            cursor: begin,
+            heads: vec![(begin, 0); views.len()],
+            row: vec![0; views.len()],
+            views,
+        })
+    }
+
     pub fn get_view(
         &mut self,
         chrom: &str,
@@ -64,6 +124,32 @@ impl<R: Read + Seek> D4MatrixReader<R> {
         }
         Ok(())
     }
+
+    /// Load the data index of every track in the matrix, fails if any track isn't indexed
+    pub fn load_data_index<S: DataSummary>(&self) -> Result<Vec<DataIndexRef<S>>> {
+        self.tracks.iter().map(|t| t.load_data_index()).collect()
+    }
+
+    /// Summarize the region of every track with the data indexes loaded by `load_data_index`,
+    /// the parts of the region that aren't covered by a complete index bin are scanned
+    pub fn query_data_index<S: DataSummary>(
+        &mut self,
+        index: &[DataIndexRef<S>],
+        chrom: &str,
+        begin: u32,
+        end: u32,
+    ) -> Result<Vec<S>> {
+        index
+            .iter()
+            .zip(self.tracks.iter_mut())
+            .map(|(index, track)| {
+                index
+                    .query(chrom, begin, end)
+                    .ok_or_else(|| Error::other(format!("Unknown chromosome {}", chrom)))?
+                    .get_result(track)
+            })
+            .collect()
+    }
 }
 
 impl<R: Read + Seek> D4TrackReader<R> {
@@ -144,6 +230,7 @@ impl<R: Read + Seek> D4TrackReader<R> {
                                 overlap_end,
                                 addr,
                                 table_ref.comp,
+                                table_ref.record_format,
                             ));
                         }
                     }
@@ -166,13 +253,28 @@ impl<R: Read + Seek> D4TrackReader<R> {
         })
     }
 
+    /// Same as `get_view`, but the values are converted to the actual values, which handles both
+    /// the denominator and the float value mode
+    pub fn get_real_view(
+        &mut self,
+        chrom: &str,
+        begin: u32,
+        end: u32,
+    ) -> Result<impl Iterator<Item = Result<(u32, f64)>>> {
+        let value_type = self.header.value_type();
+        let denominator = self.header.get_denominator();
+        Ok(self.get_view(chrom, begin, end)?.map(move |item| {
+            item.map(|(pos, value)| (pos, value_type.decode(value) / denominator))
+        }))
+    }
+
     pub fn from_track_root(track_root: Directory<R>) -> Result<Self> {
         let header_stream = track_root.open_stream(Header::HEADER_STREAM_NAME)?;
         let header = Header::read(header_stream)?;
-        let primary_table = track_root.open_blob(PRIMARY_TABLE_NAME)?;
+        let primary_table = PrimaryTableBlob::open(&track_root)?;
 
         let secondary_table = SecondaryTableRef::create_stream_index(
-            track_root.open_directory(SECONDARY_TABLE_NAME)?,
+            track_root.open_directory(secondary_table_name(&track_root))?,
             header.chrom_list(),
         )?;
 
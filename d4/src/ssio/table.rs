@@ -5,7 +5,7 @@ use d4_framefile::{Directory, Stream};
 use crate::{
     index::RecordFrameAddress,
     stab::{
-        CompressionMethod, Record, RecordBlockParsingState, SparseArraryMetadata,
+        CompressionMethod, Record, RecordBlockParsingState, RecordFormat, SparseArraryMetadata,
         SECONDARY_TABLE_METADATA_NAME,
     },
     Chrom,
@@ -22,6 +22,7 @@ pub(super) struct SecondaryTableRef<R: Read + Seek> {
     pub begin: u32,
     pub end: u32,
     pub comp: CompressionMethod,
+    pub record_format: RecordFormat,
     addr: DataStreamAddr,
     pub root: Directory<R>,
 }
@@ -34,12 +35,14 @@ impl<R: Read + Seek> SecondaryTableRef<R> {
         end: u32,
         addr: RecordFrameAddress,
         compression: CompressionMethod,
+        record_format: RecordFormat,
     ) -> SecondaryTableRef<R> {
         SecondaryTableRef {
             chrom_id,
             begin,
             end,
             comp: compression,
+            record_format,
             addr: DataStreamAddr::Partial(addr),
             root: st_root,
         }
@@ -69,6 +72,7 @@ impl<R: Read + Seek> SecondaryTableRef<R> {
                     end: stream.range.1,
                     addr: DataStreamAddr::Full(stream_name.to_string()),
                     comp: metadata.compression(),
+                    record_format: metadata.record_format()?,
                     root: sec_tab_root.clone(),
                 };
                 ret.push(stream_ref);
@@ -85,9 +89,10 @@ impl<R: Read + Seek> SecondaryTableRef<R> {
     pub fn get_frame_parsing_state<Rec: Record>(&self) -> RecordBlockParsingState<Rec> {
         match &self.addr {
             DataStreamAddr::Partial(addr) => RecordBlockParsingState::new(self.comp)
+                .set_record_format(self.record_format)
                 .set_is_first_frame(addr.first_frame)
                 .set_skip_bytes(addr.record_offset),
-            _ => RecordBlockParsingState::new(self.comp),
+            _ => RecordBlockParsingState::new(self.comp).set_record_format(self.record_format),
         }
     }
 }
@@ -4,11 +4,12 @@ use std::{
 };
 
 use crate::{
+    ptab::PrimaryTableBlob,
     stab::{RangeRecord, Record, RecordBlockParsingState},
     Dictionary,
 };
 
-use d4_framefile::{Blob, Stream};
+use d4_framefile::Stream;
 
 use super::table::SecondaryTableRef;
 
@@ -17,7 +18,7 @@ pub struct D4TrackView<R: Read + Seek> {
     pub(super) end: u32,
     pub(super) cursor: u32,
     pub(super) fetch_size: usize,
-    pub(super) primary_table: Blob<R>,
+    pub(super) primary_table: PrimaryTableBlob<R>,
     pub(super) primary_table_buffer: Option<(u32, Vec<u8>)>,
     pub(super) secondary_tables: VecDeque<SecondaryTableRef<R>>,
     pub(super) stream: Option<Stream<R>>,
@@ -128,6 +129,9 @@ impl<R: Read + Seek> D4TrackView<R> {
         }
     }
 
+    /// Read the next interval that has the same value, the interval is clipped to the end of
+    /// the view. Note that the primary table is still decoded base by base, thus the adjacent
+    /// intervals may carry the same value.
     pub fn read_next_interval(&mut self) -> Result<(u32, u32, i32)> {
         if self.dictionary.bit_width() > 0 {
             let (pos, val) = self.read_next_value()?;
@@ -140,20 +144,24 @@ impl<R: Read + Seek> D4TrackView<R> {
 
         self.update_current_secrec()?;
 
-        if let Some(current_rec) = self.current_record {
-            let (cur_rec_beg, cur_rec_end) = current_rec.effective_range();
-            if begin_pos < cur_rec_beg {
-                self.cursor = begin_pos;
-                return Ok((begin_pos, cur_rec_beg, current_rec.value()));
-            } else if begin_pos < cur_rec_end {
-                self.cursor = cur_rec_end;
-                return Ok((begin_pos, cur_rec_end, current_rec.value()));
-            } else {
-                unreachable!("Buggy update_current_secrec implementation!")
+        let (end_pos, value) = match self.current_record {
+            Some(current_rec) => {
+                let (cur_rec_beg, cur_rec_end) = current_rec.effective_range();
+                if begin_pos < cur_rec_beg {
+                    (cur_rec_beg, fallback_value)
+                } else if begin_pos < cur_rec_end {
+                    (cur_rec_end, current_rec.value())
+                } else {
+                    (self.end, fallback_value)
+                }
             }
-        }
+            None => (self.end, fallback_value),
+        };
+
+        let end_pos = end_pos.min(self.end).max(begin_pos + 1);
+        self.cursor = end_pos;
 
-        Ok((begin_pos, begin_pos + 1, fallback_value))
+        Ok((begin_pos, end_pos, value))
     }
 
     fn update_current_secrec(&mut self) -> Result<()> {
@@ -208,3 +216,77 @@ impl<R: Read + Seek> D4TrackView<R> {
         }
     }
 }
+
+/// A view that scans multiple tracks in the same region at once. Each row reported by this view
+/// is an interval on which the values of all the tracks are constant.
+pub struct D4MatrixView<R: Read + Seek> {
+    pub(super) chrom: String,
+    pub(super) end: u32,
+    pub(super) cursor: u32,
+    pub(super) views: Vec<D4TrackView<R>>,
+    /// The end position and the value of the interval each track is currently reading
+    pub(super) heads: Vec<(u32, i32)>,
+    pub(super) row: Vec<i32>,
+}
+
+impl<R: Read + Seek> D4MatrixView<R> {
+    pub fn chrom_name(&self) -> &str {
+        self.chrom.as_ref()
+    }
+
+    pub fn tell(&self) -> Option<u32> {
+        if self.cursor >= self.end || self.views.is_empty() {
+            None
+        } else {
+            Some(self.cursor)
+        }
+    }
+
+    /// Read the next row as `(chrom, begin, end, values)`, where `values` holds one value per
+    /// track. The adjacent rows always have different values.
+    #[allow(clippy::type_complexity)]
+    pub fn next_row(&mut self) -> Option<Result<(&str, u32, u32, &[i32])>> {
+        let begin = self.tell()?;
+        let mut first = true;
+        loop {
+            let mut changed = false;
+            for ((view, head), value) in self
+                .views
+                .iter_mut()
+                .zip(self.heads.iter_mut())
+                .zip(self.row.iter_mut())
+            {
+                if head.0 <= self.cursor {
+                    *head = match view.read_next_interval() {
+                        Ok((_, end, value)) => (end, value),
+                        Err(e) => return Some(Err(e)),
+                    };
+                }
+                if first {
+                    *value = head.1;
+                } else if *value != head.1 {
+                    changed = true;
+                }
+            }
+            if changed {
+                break;
+            }
+            first = false;
+            self.cursor = self
+                .heads
+                .iter()
+                .map(|(end, _)| *end)
+                .min()
+                .unwrap_or(self.end);
+            if self.cursor >= self.end {
+                break;
+            }
+        }
+        Some(Ok((
+            self.chrom.as_str(),
+            begin,
+            self.cursor,
+            self.row.as_slice(),
+        )))
+    }
+}
@@ -5,19 +5,44 @@ use d4_framefile::Directory;
 use std::fs::File;
 use std::io::Result;
 
-use crate::header::Header;
+use crate::header::{Header, ValueType};
 
 mod sparse_array;
 
-pub use sparse_array::RangeRecord;
+pub use sparse_array::{CompressionMethod, FloatRangeRecord, RangeRecord, RunLengthRecord};
 
 pub(crate) use sparse_array::{
-    CompressionMethod, Record, RecordBlockParsingState, SparseArraryMetadata,
+    Record, RecordBlockParsingState, RecordFormat, SparseArraryMetadata,
 };
 
 pub const SECONDARY_TABLE_NAME: &str = ".stab";
+/// The name of the secondary table of the float tracks and the tracks with records other than
+/// `RangeRecord`. The older versions of D4 only look for `.stab`, so they refuse to open these
+/// tracks instead of misreading the values.
+pub const EXTENDED_SECONDARY_TABLE_NAME: &str = ".xstab";
 pub const SECONDARY_TABLE_METADATA_NAME: &str = ".metadata";
 
+/// The name of the secondary table that stores the records of type `R` for the given header
+pub(crate) fn secondary_table_name_for<R: Record>(header: &Header) -> &'static str {
+    if header.value_type() == ValueType::Float || R::FORMAT_NAME != RangeRecord::FORMAT_NAME {
+        EXTENDED_SECONDARY_TABLE_NAME
+    } else {
+        SECONDARY_TABLE_NAME
+    }
+}
+
+/// The name of the secondary table in an existing track
+pub(crate) fn secondary_table_name<T>(track_root: &Directory<T>) -> &'static str {
+    if track_root
+        .entry_kind(EXTENDED_SECONDARY_TABLE_NAME)
+        .is_some()
+    {
+        EXTENDED_SECONDARY_TABLE_NAME
+    } else {
+        SECONDARY_TABLE_NAME
+    }
+}
+
 /// Any type that is used to write a secondary table for D4 file
 pub trait SecondaryTableWriter: Sized {
     /// The writer type to write a single parallel partition for the secondary table
@@ -26,8 +51,12 @@ pub trait SecondaryTableWriter: Sized {
     fn create(root: &mut Directory<File>, header: &Header) -> Result<Self>;
     /// Split the secondary table into parallel partitions
     fn split(&mut self, partitions: &[(&str, u32, u32)]) -> Result<Vec<Self::Partition>>;
+    /// Set the compression method of the secondary table
+    fn enable_compression(&mut self, method: CompressionMethod) -> &mut Self;
     /// Enable the secondary table compression
-    fn enable_deflate_encoding(&mut self, level: u32) -> &mut Self;
+    fn enable_deflate_encoding(&mut self, level: u32) -> &mut Self {
+        self.enable_compression(CompressionMethod::Deflate(level))
+    }
 }
 
 /// A type that is used to write a single parallel partition of a secondary table
@@ -94,7 +123,9 @@ impl<'a, S: SecondaryTablePartReader> Iterator for RecordIterator<'a, S> {
 #[cfg(all(feature = "mapped_io", not(target_arch = "wasm32")))]
 pub mod mapped {
     use super::sparse_array;
+    pub use sparse_array::FloatRangeRecord;
     pub use sparse_array::RangeRecord;
+    pub use sparse_array::RunLengthRecord;
     pub use sparse_array::SparseArrayPartReader;
     pub use sparse_array::SparseArrayPartWriter;
     pub use sparse_array::SparseArrayReader;
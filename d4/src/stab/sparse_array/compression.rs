@@ -1,26 +1,67 @@
 use super::record::Record;
 use d4_framefile::Stream;
-use flate2::{write::DeflateEncoder, Compression};
+use flate2::{read::DeflateDecoder, write::DeflateEncoder, Compression};
 use serde_derive::{Deserialize, Serialize};
 
 use std::fs::File;
-use std::io::{Result, Write};
+use std::io::{Read, Result, Write};
 use std::marker::PhantomData;
 
+/// The size of the block header, which is (first_pos, last_pos, count)
+const BLOCK_HEADER_SIZE: usize = 12;
+
 /// The flag that indicates what compression method we are using
 #[derive(Serialize, Deserialize, Copy, Clone)]
 pub enum CompressionMethod {
     NoCompression,
     Deflate(u32),
+    /// Zstandard with the given level, which usually has a better ratio than deflate
+    Zstd(i32),
+    /// LZ4, which has a lower ratio but is much faster to decode
+    Lz4,
+}
+
+/// The compressor that is used to compress a single record block
+pub(super) enum BlockCompressor {
+    Deflate(DeflateEncoder<Vec<u8>>),
+    Zstd(i32),
+    Lz4,
+}
+
+impl BlockCompressor {
+    /// Compress the data, the result is the header followed by the compressed data, which is
+    /// written to the output buffer
+    fn compress(&mut self, header: &[u8], data: &[u8], mut output: Vec<u8>) -> Result<Vec<u8>> {
+        match self {
+            Self::Deflate(compressor) => {
+                // The deflate encoder is reused across blocks, so we write the header to the
+                // current inner buffer and swap the output buffer in once the block is done
+                compressor.get_mut().write_all(header)?;
+                compressor.write_all(data)?;
+                compressor.reset(output)
+            }
+            Self::Zstd(level) => {
+                output.write_all(header)?;
+                zstd::stream::copy_encode(data, &mut output, *level)?;
+                Ok(output)
+            }
+            Self::Lz4 => {
+                output.write_all(header)?;
+                let mut compressor = lz4_flex::frame::FrameEncoder::new(output);
+                compressor.write_all(data)?;
+                compressor.finish().map_err(std::io::Error::other)
+            }
+        }
+    }
 }
 
 /// A context that is used to compress the record blocks
 pub(super) enum CompressionContext<R: Record> {
     NoCompression,
-    Deflate {
+    Compressed {
         first_block: bool,
         buffer: Vec<u8>,
-        compressor: DeflateEncoder<Vec<u8>>,
+        compressor: BlockCompressor,
         unused_buffer: Option<Vec<u8>>,
         first_pos: Option<u32>,
         last_pos: Option<u32>,
@@ -38,21 +79,51 @@ impl Default for CompressionMethod {
 impl CompressionMethod {
     /// Create a new context from the compression method
     pub(super) fn context<R: Record>(&self) -> CompressionContext<R> {
+        let compressor = match self {
+            Self::NoCompression => return CompressionContext::NoCompression,
+            Self::Deflate(level) => {
+                BlockCompressor::Deflate(DeflateEncoder::new(Vec::new(), Compression::new(*level)))
+            }
+            Self::Zstd(level) => BlockCompressor::Zstd(*level),
+            Self::Lz4 => BlockCompressor::Lz4,
+        };
+        CompressionContext::Compressed {
+            _phantom: PhantomData,
+            first_block: true,
+            buffer: vec![],
+            compressor,
+            unused_buffer: Some(vec![]),
+            last_pos: None,
+            first_pos: None,
+            count: 0,
+        }
+    }
+
+    /// Compress a standalone buffer, this is used by the block compressed primary table
+    pub(crate) fn compress_buffer(&self, data: &[u8]) -> Result<Vec<u8>> {
         match self {
-            Self::NoCompression => CompressionContext::NoCompression,
+            Self::NoCompression => Ok(data.to_vec()),
             Self::Deflate(level) => {
-                let compressor = DeflateEncoder::new(Vec::new(), Compression::new(*level));
-                CompressionContext::Deflate {
-                    _phantom: PhantomData,
-                    first_block: true,
-                    buffer: vec![],
-                    compressor,
-                    unused_buffer: Some(vec![]),
-                    last_pos: None,
-                    first_pos: None,
-                    count: 0,
-                }
+                let mut compressor = DeflateEncoder::new(Vec::new(), Compression::new(*level));
+                compressor.write_all(data)?;
+                compressor.finish()
+            }
+            Self::Zstd(level) => zstd::bulk::compress(data, *level),
+            Self::Lz4 => Ok(lz4_flex::block::compress(data)),
+        }
+    }
+
+    /// Decompress a buffer produced by `compress_buffer`, `size` is the size of the original data
+    pub(crate) fn decompress_buffer(&self, data: &[u8], size: usize) -> Result<Vec<u8>> {
+        match self {
+            Self::NoCompression => Ok(data.to_vec()),
+            Self::Deflate(_) => {
+                let mut buffer = vec![0; size];
+                DeflateDecoder::new(data).read_exact(&mut buffer)?;
+                Ok(buffer)
             }
+            Self::Zstd(_) => zstd::bulk::decompress(data, size),
+            Self::Lz4 => lz4_flex::block::decompress(data, size).map_err(std::io::Error::other),
         }
     }
 }
@@ -74,7 +145,7 @@ impl<R: Record> CompressionContext<R> {
                     })?;
                 }
             }
-            Self::Deflate {
+            Self::Compressed {
                 first_block,
                 buffer,
                 compressor,
@@ -85,39 +156,30 @@ impl<R: Record> CompressionContext<R> {
                 ..
             } => {
                 let size_limit = if *first_block {
-                    stream.get_frame_capacity() - 1
+                    stream.get_frame_capacity() - 1 - BLOCK_HEADER_SIZE
                 } else {
                     65536
                 };
                 if buffer.len() + R::SIZE >= size_limit || record.is_none() {
+                    let mut header = Vec::with_capacity(1 + BLOCK_HEADER_SIZE);
                     if *first_block {
                         // By default we put a leading 0 to the first block, indicating this block is compressed
-                        compressor.get_mut().push(0);
+                        header.push(0);
                     }
-                    compressor
-                        .get_mut()
-                        .write_all(&first_pos.unwrap_or(0).to_le_bytes())
-                        .unwrap();
-                    compressor
-                        .get_mut()
-                        .write_all(&last_pos.unwrap_or(0).to_le_bytes())
-                        .unwrap();
-                    compressor
-                        .get_mut()
-                        .write_all(&count.to_le_bytes())
-                        .unwrap();
-                    compressor.write_all(buffer).unwrap();
+                    header.extend_from_slice(&first_pos.unwrap_or(0).to_le_bytes());
+                    header.extend_from_slice(&last_pos.unwrap_or(0).to_le_bytes());
+                    header.extend_from_slice(&count.to_le_bytes());
                     let next_buffer = unused_buffer.take().unwrap();
-                    let mut result = compressor.reset(next_buffer).unwrap();
+                    let mut result = compressor.compress(&header, buffer, next_buffer)?;
                     // We handle the first block differently, since for each stream the first block should be
                     // pre-allocated, which means we can't use variant-length block at this point.
                     // Even though the size of the compressed data usually smaller than the raw data, but this
                     // isn't always true. Thus, we should check if this is the case, if the compressed data is lager than
                     // the raw data, we store the raw data instead of compressed one. By doing so we can guarantee that
                     // the first block always have a valid size which is no more than the preallocated space in the file.
-                    if *first_block && result.len() > size_limit {
-                        result.resize(1 + size_limit, 0);
-                        result[1..].copy_from_slice(buffer);
+                    if *first_block && result.len() > stream.get_frame_capacity() {
+                        result.truncate(header.len());
+                        result.extend_from_slice(buffer);
                         // At this point, we just modify the flag byte from 0 to 1, which indicates the data isn't compressed
                         result[0] = 1;
                     }
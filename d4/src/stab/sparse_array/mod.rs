@@ -33,6 +33,12 @@ impl SparseArraryMetadata {
     pub(crate) fn compression(&self) -> CompressionMethod {
         self.compression
     }
+    pub(crate) fn record_format(&self) -> std::io::Result<RecordFormat> {
+        RecordFormat::from_name(&self.record_format)
+    }
+    pub(crate) fn record_format_name(&self) -> &str {
+        &self.record_format
+    }
     pub(crate) fn streams(&self) -> impl Iterator<Item = StreamInfo> {
         self.partitions
             .clone()
@@ -53,4 +59,5 @@ pub use reader::{SparseArrayPartReader, SparseArrayReader};
 pub use writer::{SparseArrayPartWriter, SparseArrayWriter};
 
 pub(crate) use reader::RecordBlockParsingState;
-pub use record::{RangeRecord, Record};
+pub(crate) use record::RecordFormat;
+pub use record::{FloatRangeRecord, RangeRecord, Record, RunLengthRecord};
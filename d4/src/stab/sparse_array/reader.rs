@@ -1,4 +1,4 @@
-use super::record::Record;
+use super::record::{RangeRecord, Record, RecordFormat, RunLengthRecord};
 use super::record_block::RecordBlock;
 use std::cell::RefCell;
 use std::marker::PhantomData;
@@ -24,6 +24,7 @@ pub(crate) fn assemble_incomplete_records<'a, R: Record>(
 pub(crate) fn load_compressed_frame<'a, R: Record>(
     frame: &'a [u8],
     first: bool,
+    compression: CompressionMethod,
     buffer: &mut Vec<RecordBlock<'a, R>>,
 ) {
     let (is_compressed, first_pos, last_pos, block_count, data) = if first {
@@ -46,6 +47,7 @@ pub(crate) fn load_compressed_frame<'a, R: Record>(
     if is_compressed {
         buffer.push(RecordBlock::CompressedBlock {
             raw: data,
+            compression,
             start: first_pos,
             limit: last_pos,
             block_count: block_count as usize,
@@ -80,24 +82,56 @@ pub(crate) struct RecordBlockParsingState<R: Record> {
     excess: Vec<u8>,
     first: bool,
     skip_bytes: usize,
+    record_format: RecordFormat,
     _phantom: PhantomData<R>,
 }
 
 impl<R: Record> RecordBlockParsingState<R> {
     pub fn first_record_offset(&self) -> usize {
-        let size: usize = std::mem::size_of::<R>();
+        let size = self.record_format.record_size();
         (size - self.excess.len()) % size
     }
-    pub fn parse_frame<'a>(&mut self, data: &'a [u8], buf: &mut Vec<RecordBlock<'a, R>>) {
-        let data = &data[self.skip_bytes..];
+    fn load_frame_data<'a, S: Record>(
+        &mut self,
+        data: &'a [u8],
+        buf: &mut Vec<RecordBlock<'a, S>>,
+    ) {
         match self.compression {
             CompressionMethod::NoCompression => {
                 self.excess = load_frame(data, std::mem::take(&mut self.excess), buf);
             }
-            CompressionMethod::Deflate(_) => {
-                load_compressed_frame(data, self.first, buf);
+            compression => {
+                load_compressed_frame(data, self.first, compression, buf);
+            }
+        }
+    }
+    /// Parse a frame that is stored in a different record format and convert the records to `R`.
+    /// Since the converted records can't be backed by the mapped memory, each of the blocks is
+    /// loaded as an owned block.
+    fn convert_frame<'a, S: Record>(&mut self, data: &'a [u8], buf: &mut Vec<RecordBlock<'a, R>>) {
+        let mut blocks: Vec<RecordBlock<'a, S>> = vec![];
+        self.load_frame_data(data, &mut blocks);
+        for block in blocks {
+            let mut records = vec![];
+            for record in block.as_ref().iter().take_while(|record| record.is_valid()) {
+                let (left, right) = record.effective_range();
+                let _ = R::encode_range(left, right, record.value(), |record| {
+                    records.push(record);
+                    Ok::<_, ()>(())
+                });
+            }
+            if !records.is_empty() {
+                buf.push(RecordBlock::OwnedBlock(records));
             }
         }
+    }
+    pub fn parse_frame<'a>(&mut self, data: &'a [u8], buf: &mut Vec<RecordBlock<'a, R>>) {
+        let data = &data[self.skip_bytes..];
+        match self.record_format {
+            format if format == RecordFormat::of::<R>() => self.load_frame_data(data, buf),
+            RecordFormat::Range => self.convert_frame::<RangeRecord>(data, buf),
+            RecordFormat::RunLength => self.convert_frame::<RunLengthRecord>(data, buf),
+        }
         self.skip_bytes = 0;
         self.first = false;
     }
@@ -113,10 +147,17 @@ impl<R: Record> RecordBlockParsingState<R> {
             excess: Default::default(),
             first: true,
             skip_bytes: 0,
+            record_format: RecordFormat::of::<R>(),
             _phantom: Default::default(),
         }
     }
 
+    /// Set the record format the secondary table is actually stored with
+    pub fn set_record_format(mut self, format: RecordFormat) -> Self {
+        self.record_format = format;
+        self
+    }
+
     pub fn set_is_first_frame(mut self, value: bool) -> Self {
         self.first = value;
         self
@@ -134,9 +175,10 @@ mod mapped_io {
 
     use crate::{
         stab::{
+            secondary_table_name,
             sparse_array::{record_block::RecordBlock, Record},
             RecordIterator, SecondaryTablePartReader, SecondaryTableReader, SparseArraryMetadata,
-            SECONDARY_TABLE_METADATA_NAME, SECONDARY_TABLE_NAME,
+            SECONDARY_TABLE_METADATA_NAME,
         },
         Header,
     };
@@ -184,8 +226,9 @@ mod mapped_io {
             let actual_data = metadata.trim_end_matches(|c| c == '\0');
             serde_json::from_str(actual_data).ok()
         }
-        fn load_record_blocks(&mut self) -> HashMap<String, Vec<RecordBlock<'_, R>>> {
+        fn load_record_blocks(&mut self) -> Result<HashMap<String, Vec<RecordBlock<'_, R>>>> {
             let metadata = self.load_metadata().unwrap();
+            let record_format = metadata.record_format()?;
 
             let mut record_blocks: HashMap<String, Vec<RecordBlock<'_, R>>> = HashMap::new();
 
@@ -196,7 +239,8 @@ mod mapped_io {
 
                 let mut next_frame = Some(stream.get_primary_frame());
 
-                let mut state = RecordBlockParsingState::new(metadata.compression);
+                let mut state = RecordBlockParsingState::new(metadata.compression)
+                    .set_record_format(record_format);
                 let buffer = record_blocks.entry(chr.to_string()).or_default();
 
                 while let Some(this_frame) = next_frame {
@@ -218,7 +262,7 @@ mod mapped_io {
                     }
                 }
             }
-            record_blocks
+            Ok(record_blocks)
         }
     }
 
@@ -416,14 +460,14 @@ mod mapped_io {
         type Partition = SparseArrayPartReader<R>;
         fn create(root: &mut Directory<File>, _header: &Header) -> Result<Self> {
             Ok(Self {
-                s_table_root: Arc::new(root.map_directory(SECONDARY_TABLE_NAME)?),
+                s_table_root: Arc::new(root.map_directory(secondary_table_name(root))?),
                 _p: PhantomData,
             })
         }
 
         fn split(&mut self, partitions: &[(&str, u32, u32)]) -> Result<Vec<Self::Partition>> {
             let root = self.s_table_root.clone();
-            let mut record_blocks = self.load_record_blocks();
+            let mut record_blocks = self.load_record_blocks()?;
 
             let mut displacement: Vec<_> = (0..partitions.len()).collect();
             displacement.sort_by_key(move |idx| partitions[*idx]);
@@ -447,9 +491,11 @@ mod mapped_io {
                     .map(move |block| (chrom.clone(), block))
             }) {
                 let (block_min, block_max) = block.range();
+                // Partitions are half-open, so a block starting right at the end of a partition
+                // belongs to the next one
                 while current_part_id < partitions.len()
                     && (partitions[current_part_id].chrom < chrom.as_str()
-                        || partitions[current_part_id].right < block_min)
+                        || partitions[current_part_id].right <= block_min)
                 {
                     current_part_id += 1;
                 }
@@ -516,6 +562,8 @@ mod mapped_io {
                             block.split_by_size(left_idx, &mut None, &mut buf);
                             block = buf.unwrap();
                         }
+                        // The indices are now relative to the records that are not consumed yet
+                        let right_idx = right_idx - left_idx;
 
                         let right_record = if right == block_max || right_idx >= block.count() {
                             None
@@ -530,7 +578,7 @@ mod mapped_io {
                             }
                         };
 
-                        block.split_by_size(right_idx - left_idx, &mut head, &mut tail);
+                        block.split_by_size(right_idx, &mut head, &mut tail);
 
                         if let Some(head) = head {
                             cur_part.blocks.push(head);
@@ -545,6 +593,19 @@ mod mapped_io {
                         } else {
                             break;
                         }
+                    } else if left_idx == right_idx && right_idx < block.count() {
+                        // No record is entirely inside the partition, but the next one may still
+                        // cross the right boundary, e.g. a long run that is split into records
+                        let right_record = block.get(right_idx);
+                        if right_record.effective_range().0 < right {
+                            cur_part.blocks.push(RecordBlock::Record(
+                                right_record
+                                    .limit_left(left)
+                                    .unwrap()
+                                    .limit_right(right)
+                                    .unwrap(),
+                            ));
+                        }
                     }
 
                     if right == cur_part.right {
@@ -1,3 +1,5 @@
+use std::io::{Error, ErrorKind};
+
 /// The trait that describes a single record in the Key-Value sparse table
 pub trait Record: Sized + Copy + Send + 'static {
     /// An ID used by the metadata for this record format
@@ -148,3 +150,202 @@ impl Record for RangeRecord {
         self.left.to_le() > 0
     }
 }
+
+/// The record that encoded by the triple (left, length, value), unlike `RangeRecord`, the length
+/// is 32-bit wide, thus a single record is able to cover a run of any length
+#[repr(C, packed)]
+#[derive(Clone, Copy)]
+pub struct RunLengthRecord {
+    left: u32,
+    size_enc: u32,
+    value: i32,
+}
+
+impl Record for RunLengthRecord {
+    const FORMAT_NAME: &'static str = "run_length";
+
+    #[inline(always)]
+    fn effective_range(&self) -> (u32, u32) {
+        (
+            self.left.to_le() - 1,
+            self.left.to_le() + self.size_enc.to_le(),
+        )
+    }
+    #[inline(always)]
+    fn limit_left(&self, mut new_left: u32) -> Option<Self> {
+        let (left, right) = self.effective_range();
+        new_left = left.max(new_left);
+        if new_left >= right {
+            None
+        } else {
+            Some(Self {
+                left: (new_left + 1).to_le(),
+                size_enc: (right - new_left - 1).to_le(),
+                value: self.value,
+            })
+        }
+    }
+    #[inline(always)]
+    fn limit_right(&self, mut new_right: u32) -> Option<Self> {
+        let (left, right) = self.effective_range();
+        new_right = new_right.min(right);
+        if new_right <= left {
+            None
+        } else {
+            Some(Self {
+                left: self.left,
+                size_enc: (new_right - left - 1).to_le(),
+                value: self.value,
+            })
+        }
+    }
+    #[inline(always)]
+    fn value(&self) -> i32 {
+        self.value.to_le()
+    }
+
+    #[inline(always)]
+    fn encode(this: Option<&mut Self>, pos: u32, value: i32) -> Option<Self> {
+        if let Some(this) = this {
+            let (_, right) = this.effective_range();
+            if this.value == value && right == pos && this.size_enc != u32::MAX {
+                this.size_enc += 1;
+                return None;
+            }
+        }
+        Some(Self {
+            left: (pos + 1).to_le(),
+            size_enc: 0,
+            value: value.to_le(),
+        })
+    }
+
+    #[inline(always)]
+    fn encode_range<E>(
+        left: u32,
+        right: u32,
+        value: i32,
+        mut ops: impl FnMut(Self) -> Result<(), E>,
+    ) -> Result<(), E> {
+        if left < right {
+            ops(Self {
+                left: (left + 1).to_le(),
+                size_enc: (right - left - 1).to_le(),
+                value: value.to_le(),
+            })?;
+        }
+        Ok(())
+    }
+
+    fn is_valid(&self) -> bool {
+        self.left.to_le() > 0
+    }
+}
+
+/// The record for the float value mode, which has the same layout as `RangeRecord` but the value
+/// is the bit pattern of a 32-bit float
+#[repr(transparent)]
+#[derive(Clone, Copy)]
+pub struct FloatRangeRecord(RangeRecord);
+
+impl FloatRangeRecord {
+    /// Get the float value that record is encoding
+    pub fn float_value(&self) -> f32 {
+        f32::from_bits(self.0.value() as u32)
+    }
+}
+
+impl Record for FloatRangeRecord {
+    const FORMAT_NAME: &'static str = "float_range";
+
+    #[inline(always)]
+    fn effective_range(&self) -> (u32, u32) {
+        self.0.effective_range()
+    }
+    #[inline(always)]
+    fn limit_left(&self, new_left: u32) -> Option<Self> {
+        self.0.limit_left(new_left).map(Self)
+    }
+    #[inline(always)]
+    fn limit_right(&self, new_right: u32) -> Option<Self> {
+        self.0.limit_right(new_right).map(Self)
+    }
+    #[inline(always)]
+    fn value(&self) -> i32 {
+        self.0.value()
+    }
+    #[inline(always)]
+    fn encode(this: Option<&mut Self>, pos: u32, value: i32) -> Option<Self> {
+        RangeRecord::encode(this.map(|this| &mut this.0), pos, value).map(Self)
+    }
+    #[inline(always)]
+    fn encode_range<E>(
+        left: u32,
+        right: u32,
+        value: i32,
+        mut ops: impl FnMut(Self) -> Result<(), E>,
+    ) -> Result<(), E> {
+        RangeRecord::encode_range(left, right, value, |record| ops(Self(record)))
+    }
+    fn is_valid(&self) -> bool {
+        self.0.is_valid()
+    }
+}
+
+/// The record layouts that a secondary table can be stored with, this is determined by the
+/// record format name in the secondary table metadata
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub(crate) enum RecordFormat {
+    /// The layout of `RangeRecord` and `FloatRangeRecord`
+    #[default]
+    Range,
+    /// The layout of `RunLengthRecord`
+    RunLength,
+}
+
+impl RecordFormat {
+    /// Resolve the record layout from the record format name in the secondary table metadata
+    pub fn from_name(name: &str) -> std::io::Result<Self> {
+        match name {
+            RangeRecord::FORMAT_NAME | FloatRangeRecord::FORMAT_NAME => Ok(Self::Range),
+            RunLengthRecord::FORMAT_NAME => Ok(Self::RunLength),
+            _ => Err(Error::new(
+                ErrorKind::InvalidData,
+                format!("Unsupported secondary table record format: {}", name),
+            )),
+        }
+    }
+    /// The record layout of the given record type, the record types that don't have a known
+    /// layout are loaded as they are, which is the same as the range layout
+    pub fn of<R: Record>() -> Self {
+        Self::from_name(R::FORMAT_NAME).unwrap_or_default()
+    }
+    /// The size of a single record in this layout
+    pub fn record_size(&self) -> usize {
+        match self {
+            Self::Range => RangeRecord::SIZE,
+            Self::RunLength => RunLengthRecord::SIZE,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    #[test]
+    fn test_record_format_name() {
+        assert_eq!(
+            RecordFormat::from_name("range").unwrap(),
+            RecordFormat::Range
+        );
+        assert_eq!(
+            RecordFormat::from_name("float_range").unwrap(),
+            RecordFormat::Range
+        );
+        assert_eq!(
+            RecordFormat::from_name("run_length").unwrap(),
+            RecordFormat::RunLength
+        );
+        assert!(RecordFormat::from_name("unknown").is_err());
+    }
+}
@@ -1,4 +1,5 @@
 use super::record::Record;
+use super::CompressionMethod;
 use flate2::read::DeflateDecoder;
 use std::cell::RefCell;
 use std::io::{Read, Result};
@@ -12,6 +13,7 @@ pub(crate) enum RecordBlock<'a, R: Record> {
     /// We gradually decompress the data block when it's need
     CompressedBlock {
         raw: &'a [u8],
+        compression: CompressionMethod,
         decompressed: RefCell<Vec<R>>,
         unused: usize,
         start: u32,
@@ -35,6 +37,7 @@ impl<'a, R: Record> RecordBlock<'a, R> {
     fn decompress(&self, mut count: isize) -> Result<()> {
         if let Self::CompressedBlock {
             raw,
+            compression,
             decompressed,
             unused,
             block_count,
@@ -56,7 +59,13 @@ impl<'a, R: Record> RecordBlock<'a, R> {
                         .max(decompressed.len() * 2)
                         .min(*block_count) as isize;
                 }
-                let mut decoder = DeflateDecoder::new(*raw);
+                let mut decoder: Box<dyn Read> = match compression {
+                    CompressionMethod::Zstd(_) => {
+                        Box::new(zstd::stream::read::Decoder::with_buffer(*raw)?.single_frame())
+                    }
+                    CompressionMethod::Lz4 => Box::new(lz4_flex::frame::FrameDecoder::new(*raw)),
+                    _ => Box::new(DeflateDecoder::new(*raw)),
+                };
                 let bytes_to_read = (if count > 0 {
                     count as usize + unused
                 } else {
@@ -1,8 +1,8 @@
 use d4_framefile::{Directory, Stream};
 
 use crate::stab::{
-    SecondaryTablePartWriter, SecondaryTableWriter, SECONDARY_TABLE_METADATA_NAME,
-    SECONDARY_TABLE_NAME,
+    secondary_table_name, secondary_table_name_for, SecondaryTablePartWriter, SecondaryTableWriter,
+    SECONDARY_TABLE_METADATA_NAME,
 };
 use crate::Header;
 
@@ -24,20 +24,41 @@ pub struct SparseArrayPartWriter<R: Record> {
     compression: CompressionContext<R>,
 }
 
-impl<R: Record> SecondaryTableWriter for SparseArrayWriter<R> {
-    type Partition = SparseArrayPartWriter<R>;
-    fn enable_deflate_encoding(&mut self, level: u32) -> &mut Self {
-        self.1 = CompressionMethod::Deflate(level);
-        self
-    }
-    fn create(root: &mut Directory<File>, _header: &Header) -> Result<Self> {
+impl<R: Record> SparseArrayWriter<R> {
+    /// Open the existing secondary table of the track, so that the partitions can be rewritten
+    /// individually with `replace_partition`
+    pub(crate) fn open_for_update(
+        root: &mut Directory<File>,
+        compression: CompressionMethod,
+    ) -> Result<Self> {
         Ok(SparseArrayWriter(
-            root.create_directory(SECONDARY_TABLE_NAME)?,
-            Default::default(),
+            root.open_directory_for_update(secondary_table_name(root))?,
+            compression,
             PhantomData,
         ))
     }
-    fn split(&mut self, partitions: &[(&str, u32, u32)]) -> Result<Vec<SparseArrayPartWriter<R>>> {
+
+    /// Overwrite the partition list of an existing secondary table. The partitions that are new
+    /// to the table should be written with `replace_partition` afterwards.
+    pub(crate) fn update_partitions(&mut self, partitions: &[(&str, u32, u32)]) -> Result<()> {
+        let mut metadata_stream = self.0.replace_stream(SECONDARY_TABLE_METADATA_NAME, 512)?;
+        self.write_metadata(&mut metadata_stream, partitions)
+    }
+
+    /// Create a partition writer that replaces the content of the idx-th partition
+    pub(crate) fn replace_partition(&mut self, idx: usize) -> Result<SparseArrayPartWriter<R>> {
+        Ok(SparseArrayPartWriter {
+            stream: self.0.replace_stream(format!("{}", idx).as_ref(), 512)?,
+            pending_record: None,
+            compression: self.1.context(),
+        })
+    }
+
+    fn write_metadata(
+        &self,
+        metadata_stream: &mut Stream<File>,
+        partitions: &[(&str, u32, u32)],
+    ) -> Result<()> {
         let metadata = SparseArraryMetadata {
             format: "SimpleKV".to_string(),
             record_format: R::FORMAT_NAME.to_string(),
@@ -49,11 +70,30 @@ impl<R: Record> SecondaryTableWriter for SparseArrayWriter<R> {
             },
             compression: self.1,
         };
-        let mut metadata_stream = self.0.create_stream(SECONDARY_TABLE_METADATA_NAME, 512)?;
         metadata_stream.write_with_alloc_callback(
             serde_json::to_string(&metadata).unwrap().as_bytes(),
             |s| s.set_frame_size(65536),
         )?;
+        Ok(())
+    }
+}
+
+impl<R: Record> SecondaryTableWriter for SparseArrayWriter<R> {
+    type Partition = SparseArrayPartWriter<R>;
+    fn enable_compression(&mut self, method: CompressionMethod) -> &mut Self {
+        self.1 = method;
+        self
+    }
+    fn create(root: &mut Directory<File>, header: &Header) -> Result<Self> {
+        Ok(SparseArrayWriter(
+            root.create_directory(secondary_table_name_for::<R>(header))?,
+            Default::default(),
+            PhantomData,
+        ))
+    }
+    fn split(&mut self, partitions: &[(&str, u32, u32)]) -> Result<Vec<SparseArrayPartWriter<R>>> {
+        let mut metadata_stream = self.0.create_stream(SECONDARY_TABLE_METADATA_NAME, 512)?;
+        self.write_metadata(&mut metadata_stream, partitions)?;
         let compression = self.1;
         Ok(partitions
             .iter()
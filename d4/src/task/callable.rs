@@ -0,0 +1,134 @@
+use super::{Task, TaskPartition};
+use crate::header::ValueType;
+
+/// Extract the intervals where the value of the first track is within the given range, e.g. the
+/// callable regions of a depth profile. The intervals found in different partitions are stitched
+/// together, then the intervals separated by small gaps are merged and the short intervals are
+/// dropped.
+#[derive(Clone)]
+pub struct Callable {
+    chr: String,
+    start: u32,
+    end: u32,
+    min_value: Option<f64>,
+    max_value: Option<f64>,
+    min_length: u32,
+    max_gap: u32,
+    denominator: f64,
+    value_type: ValueType,
+}
+
+impl Callable {
+    /// Create a new task that extracts the intervals with value in range [min_value, max_value],
+    /// both bounds are inclusive and a missing bound means the range is unbounded on that side
+    pub fn new(
+        chr: &str,
+        start: u32,
+        end: u32,
+        min_value: Option<f64>,
+        max_value: Option<f64>,
+    ) -> Self {
+        Self {
+            chr: chr.to_string(),
+            start,
+            end,
+            min_value,
+            max_value,
+            min_length: 0,
+            max_gap: 0,
+            denominator: 1.0,
+            value_type: ValueType::Integer,
+        }
+    }
+
+    /// Drop the intervals shorter than the given length, this is applied after gap merging
+    pub fn with_min_length(mut self, min_length: u32) -> Self {
+        self.min_length = min_length;
+        self
+    }
+
+    /// Merge the intervals that are separated by a gap no longer than the given size
+    pub fn with_max_gap(mut self, max_gap: u32) -> Self {
+        self.max_gap = max_gap;
+        self
+    }
+
+    /// Set the denominator of the track, so that the range is applied to the actual values
+    pub fn with_denominator(mut self, denominator: f64) -> Self {
+        self.denominator = denominator;
+        self
+    }
+}
+
+pub struct CallablePartition {
+    min_value: f64,
+    max_value: f64,
+    denominator: f64,
+    value_type: ValueType,
+    result: Vec<(u32, u32)>,
+}
+
+impl<R: Iterator<Item = i32> + ExactSizeIterator> TaskPartition<R> for CallablePartition {
+    type ParentType = Callable;
+    type ResultType = Vec<(u32, u32)>;
+
+    fn new(_: u32, _: u32, parent: &Self::ParentType) -> Self {
+        Self {
+            min_value: parent.min_value.unwrap_or(f64::NEG_INFINITY),
+            max_value: parent.max_value.unwrap_or(f64::INFINITY),
+            denominator: parent.denominator,
+            value_type: parent.value_type,
+            result: vec![],
+        }
+    }
+
+    #[inline(always)]
+    fn feed_range(&mut self, left: u32, right: u32, value: &mut R) -> bool {
+        let value = match value.next() {
+            Some(value) => self.value_type.decode(value) / self.denominator,
+            None => return true,
+        };
+        if value < self.min_value || self.max_value < value {
+            return true;
+        }
+        match self.result.last_mut() {
+            Some(last) if last.1 == left => last.1 = right,
+            _ => self.result.push((left, right)),
+        }
+        true
+    }
+
+    fn result(&mut self) -> Self::ResultType {
+        std::mem::take(&mut self.result)
+    }
+}
+
+impl<R: Iterator<Item = i32> + ExactSizeIterator> Task<R> for Callable {
+    type Partition = CallablePartition;
+    type Output = Vec<(u32, u32)>;
+
+    fn region(&self) -> (&str, u32, u32) {
+        (self.chr.as_ref(), self.start, self.end)
+    }
+
+    fn set_value_type(&mut self, value_type: ValueType) {
+        self.value_type = value_type;
+    }
+
+    fn combine(&self, parts: &[Vec<(u32, u32)>]) -> Self::Output {
+        let mut ret: Vec<(u32, u32)> = vec![];
+        for &(left, right) in parts.iter().flatten() {
+            if left >= right {
+                continue;
+            }
+            match ret.last_mut() {
+                Some(last) if left <= last.1.saturating_add(self.max_gap) => {
+                    last.1 = last.1.max(right)
+                }
+                _ => ret.push((left, right)),
+            }
+        }
+        ret.retain(|&(left, right)| right - left >= self.min_length);
+        ret
+    }
+}
@@ -0,0 +1,67 @@
+use super::{Task, TaskPartition};
+use crate::header::ValueType;
+
+/// Implements the task traits for the tuples of tasks, so that multiple tasks on the same region
+/// can be computed in one pass. Every task in the tuple receives the same rows and the region of
+/// the tuple is the region of its first task, thus all the tasks should cover the same region.
+macro_rules! impl_combined_task {
+    ($($type:ident : $idx:tt),+) => {
+        impl<R, $($type),+> TaskPartition<R> for ($($type,)+)
+        where
+            R: Iterator<Item = i32> + ExactSizeIterator + Clone,
+            $($type: TaskPartition<R>,)+
+        {
+            type ParentType = ($($type::ParentType,)+);
+            type ResultType = ($($type::ResultType,)+);
+            fn new(left: u32, right: u32, parent: &Self::ParentType) -> Self {
+                ($($type::new(left, right, &parent.$idx),)+)
+            }
+            #[inline(always)]
+            fn init(&mut self) {
+                $(self.$idx.init();)+
+            }
+            #[inline(always)]
+            fn feed(&mut self, pos: u32, value: &mut R) -> bool {
+                let mut ret = true;
+                $(ret &= self.$idx.feed(pos, &mut value.clone());)+
+                ret
+            }
+            #[inline(always)]
+            fn feed_range(&mut self, left: u32, right: u32, value: &mut R) -> bool {
+                let mut ret = true;
+                $(ret &= self.$idx.feed_range(left, right, &mut value.clone());)+
+                ret
+            }
+            fn result(&mut self) -> Self::ResultType {
+                ($(self.$idx.result(),)+)
+            }
+        }
+
+        impl<R, $($type),+> Task<R> for ($($type,)+)
+        where
+            R: Iterator<Item = i32> + ExactSizeIterator + Clone,
+            $($type: Task<R>,)+
+        {
+            type Partition = ($($type::Partition,)+);
+            type Output = ($($type::Output,)+);
+            fn region(&self) -> (&str, u32, u32) {
+                self.0.region()
+            }
+            fn set_value_type(&mut self, value_type: ValueType) {
+                $(self.$idx.set_value_type(value_type);)+
+            }
+            fn combine(
+                &self,
+                parts: &[<Self::Partition as TaskPartition<R>>::ResultType],
+            ) -> Self::Output {
+                ($(self.$idx.combine(
+                    &parts.iter().map(|part| part.$idx.clone()).collect::<Vec<_>>(),
+                ),)+)
+            }
+        }
+    };
+}
+
+impl_combined_task!(A: 0, B: 1);
+impl_combined_task!(A: 0, B: 1, C: 2);
+impl_combined_task!(A: 0, B: 1, C: 2, D: 3);
@@ -0,0 +1,193 @@
+use std::{
+    io::{Error, Result},
+    str::FromStr,
+};
+
+use super::{Task, TaskPartition};
+use crate::header::ValueType;
+
+/// The operation used to combine the values of multiple tracks at each base
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ComputeOp {
+    Sum,
+    Mean,
+    Min,
+    Max,
+    /// The first track minus the second track
+    Diff,
+    /// The first track divided by the second track, zero when the second track is zero
+    Ratio,
+}
+
+impl FromStr for ComputeOp {
+    type Err = Error;
+    fn from_str(s: &str) -> Result<Self> {
+        match s.to_lowercase().as_str() {
+            "sum" | "add" => Ok(Self::Sum),
+            "mean" | "avg" => Ok(Self::Mean),
+            "min" => Ok(Self::Min),
+            "max" => Ok(Self::Max),
+            "diff" | "sub" => Ok(Self::Diff),
+            "ratio" | "div" => Ok(Self::Ratio),
+            _ => Err(Error::other(format!("Unknown operation: {}", s))),
+        }
+    }
+}
+
+impl ComputeOp {
+    /// The number of tracks this operation requires, None if it accepts any number of tracks
+    pub fn arity(&self) -> Option<usize> {
+        match self {
+            Self::Diff | Self::Ratio => Some(2),
+            _ => None,
+        }
+    }
+
+    /// Apply the operation to the values of a row
+    pub fn apply<I: Iterator<Item = f64>>(&self, mut values: I) -> f64 {
+        match self {
+            Self::Sum => values.sum(),
+            Self::Mean => {
+                let (sum, count) = values.fold((0.0, 0), |(s, c), v| (s + v, c + 1));
+                if count > 0 {
+                    sum / count as f64
+                } else {
+                    0.0
+                }
+            }
+            Self::Min => values.reduce(f64::min).unwrap_or(0.0),
+            Self::Max => values.reduce(f64::max).unwrap_or(0.0),
+            Self::Diff => {
+                let first = values.next().unwrap_or(0.0);
+                first - values.sum::<f64>()
+            }
+            Self::Ratio => {
+                let first = values.next().unwrap_or(0.0);
+                let second = values.next().unwrap_or(0.0);
+                if second == 0.0 {
+                    0.0
+                } else {
+                    first / second
+                }
+            }
+        }
+    }
+}
+
+/// Combine all the tracks of a multi-track reader base by base. The output is the list of
+/// intervals with constant result, adjacent intervals with the same value are merged.
+#[derive(Clone)]
+pub struct Compute {
+    chr: String,
+    start: u32,
+    end: u32,
+    op: ComputeOp,
+    denominators: Vec<f64>,
+    value_type: ValueType,
+}
+
+impl Compute {
+    /// Create a new compute task, `denominators` has one entry per track and is used to convert
+    /// the stored integers to the actual values
+    pub fn new(
+        chr: &str,
+        start: u32,
+        end: u32,
+        op: ComputeOp,
+        denominators: Vec<f64>,
+    ) -> Result<Self> {
+        if op.arity().is_some_and(|arity| arity != denominators.len()) {
+            return Err(Error::other(format!(
+                "Operation {:?} requires exactly {} tracks",
+                op,
+                op.arity().unwrap()
+            )));
+        }
+        Ok(Self {
+            chr: chr.to_string(),
+            start,
+            end,
+            op,
+            denominators,
+            value_type: ValueType::Integer,
+        })
+    }
+}
+
+pub struct ComputePartition {
+    op: ComputeOp,
+    denominators: Vec<f64>,
+    value_type: ValueType,
+    result: Vec<(u32, u32, f64)>,
+}
+
+impl ComputePartition {
+    #[inline(always)]
+    fn push(&mut self, left: u32, right: u32, value: f64) {
+        if let Some(last) = self.result.last_mut() {
+            if last.1 == left && last.2 == value {
+                last.1 = right;
+                return;
+            }
+        }
+        self.result.push((left, right, value));
+    }
+}
+
+impl<R: Iterator<Item = i32> + ExactSizeIterator> TaskPartition<R> for ComputePartition {
+    type ParentType = Compute;
+    type ResultType = Vec<(u32, u32, f64)>;
+
+    fn new(_: u32, _: u32, parent: &Self::ParentType) -> Self {
+        Self {
+            op: parent.op,
+            denominators: parent.denominators.clone(),
+            value_type: parent.value_type,
+            result: vec![],
+        }
+    }
+
+    #[inline(always)]
+    fn feed_range(&mut self, left: u32, right: u32, value: &mut R) -> bool {
+        let value_type = self.value_type;
+        let value = self.op.apply(
+            value
+                .zip(self.denominators.iter())
+                .map(|(value, denominator)| value_type.decode(value) / denominator),
+        );
+        self.push(left, right, value);
+        true
+    }
+
+    fn result(&mut self) -> Self::ResultType {
+        std::mem::take(&mut self.result)
+    }
+}
+
+impl<R: Iterator<Item = i32> + ExactSizeIterator> Task<R> for Compute {
+    type Partition = ComputePartition;
+    type Output = Vec<(u32, u32, f64)>;
+
+    fn region(&self) -> (&str, u32, u32) {
+        (self.chr.as_ref(), self.start, self.end)
+    }
+
+    fn set_value_type(&mut self, value_type: ValueType) {
+        self.value_type = value_type;
+    }
+
+    fn combine(&self, parts: &[Vec<(u32, u32, f64)>]) -> Self::Output {
+        let mut ret: Vec<(u32, u32, f64)> =
+            Vec::with_capacity(parts.iter().map(|part| part.len()).sum());
+        for &(left, right, value) in parts.iter().flatten() {
+            if left >= right {
+                continue;
+            }
+            match ret.last_mut() {
+                Some(last) if last.1 == left && last.2 == value => last.1 = right,
+                _ => ret.push((left, right, value)),
+            }
+        }
+        ret
+    }
+}
@@ -59,6 +59,11 @@ where
 {
     /// Create a new task that processing the given file
     pub fn new(reader: &mut R, mut tasks: Vec<T>) -> Result<Self> {
+        let value_type = reader.value_type();
+        for task in tasks.iter_mut() {
+            task.set_value_type(value_type);
+        }
+
         let mut file_partition = MultiTrackReader::split(reader, Some(10_000_000))?;
 
         file_partition.sort_unstable_by(|a, b| {
@@ -0,0 +1,132 @@
+use super::{Task, TaskPartition};
+use crate::header::ValueType;
+
+/// Compare the first two tracks of a multi-track reader base by base. The output is the list of
+/// intervals where the two tracks disagree, together with the values of both tracks. Adjacent
+/// intervals with the same pair of values are merged.
+#[derive(Clone)]
+pub struct Diff {
+    chr: String,
+    start: u32,
+    end: u32,
+    denominators: [f64; 2],
+    value_type: ValueType,
+}
+
+impl Diff {
+    /// Create a new diff task, `denominators` is used to convert the stored integers of each
+    /// track to the actual values, so that the tracks with different denominators are comparable
+    pub fn new(chr: &str, start: u32, end: u32, denominators: [f64; 2]) -> Self {
+        Self {
+            chr: chr.to_string(),
+            start,
+            end,
+            denominators,
+            value_type: ValueType::Integer,
+        }
+    }
+}
+
+/// The summary of the differences between two tracks
+#[derive(Clone, Copy, Default, Debug)]
+pub struct DiffSummary {
+    /// The number of bases that the two tracks disagree
+    pub diff_bases: u64,
+    /// The max absolute difference of the two tracks
+    pub max_abs_diff: f64,
+}
+
+impl DiffSummary {
+    /// Add a differing interval produced by the diff task
+    pub fn add(&mut self, &(left, right, first, second): &(u32, u32, f64, f64)) {
+        self.diff_bases += (right - left) as u64;
+        let diff = (first - second).abs();
+        if diff.is_nan() || diff > self.max_abs_diff {
+            self.max_abs_diff = diff;
+        }
+    }
+}
+
+pub struct DiffPartition {
+    denominators: [f64; 2],
+    value_type: ValueType,
+    result: Vec<(u32, u32, f64, f64)>,
+}
+
+impl DiffPartition {
+    #[inline(always)]
+    fn push(&mut self, left: u32, right: u32, first: f64, second: f64) {
+        if let Some(last) = self.result.last_mut() {
+            if last.1 == left && last.2 == first && last.3 == second {
+                last.1 = right;
+                return;
+            }
+        }
+        self.result.push((left, right, first, second));
+    }
+}
+
+impl<R: Iterator<Item = i32> + ExactSizeIterator> TaskPartition<R> for DiffPartition {
+    type ParentType = Diff;
+    type ResultType = Vec<(u32, u32, f64, f64)>;
+
+    fn new(_: u32, _: u32, parent: &Self::ParentType) -> Self {
+        Self {
+            denominators: parent.denominators,
+            value_type: parent.value_type,
+            result: vec![],
+        }
+    }
+
+    #[inline(always)]
+    fn feed_range(&mut self, left: u32, right: u32, value: &mut R) -> bool {
+        let (first, second) = match (value.next(), value.next()) {
+            (Some(first), Some(second)) => (first, second),
+            _ => return true,
+        };
+        if first == second && self.denominators[0] == self.denominators[1] {
+            return true;
+        }
+        let first = self.value_type.decode(first) / self.denominators[0];
+        let second = self.value_type.decode(second) / self.denominators[1];
+        // Two NaNs are considered as the same value
+        if first != second && !(first.is_nan() && second.is_nan()) {
+            self.push(left, right, first, second);
+        }
+        true
+    }
+
+    fn result(&mut self) -> Self::ResultType {
+        std::mem::take(&mut self.result)
+    }
+}
+
+impl<R: Iterator<Item = i32> + ExactSizeIterator> Task<R> for Diff {
+    type Partition = DiffPartition;
+    type Output = Vec<(u32, u32, f64, f64)>;
+
+    fn region(&self) -> (&str, u32, u32) {
+        (self.chr.as_ref(), self.start, self.end)
+    }
+
+    fn set_value_type(&mut self, value_type: ValueType) {
+        self.value_type = value_type;
+    }
+
+    fn combine(&self, parts: &[Vec<(u32, u32, f64, f64)>]) -> Self::Output {
+        let mut ret: Vec<(u32, u32, f64, f64)> =
+            Vec::with_capacity(parts.iter().map(|part| part.len()).sum());
+        for &(left, right, first, second) in parts.iter().flatten() {
+            if left >= right {
+                continue;
+            }
+            match ret.last_mut() {
+                Some(last) if last.1 == left && last.2 == first && last.3 == second => {
+                    last.1 = right
+                }
+                _ => ret.push((left, right, first, second)),
+            }
+        }
+        ret
+    }
+}
@@ -1,22 +1,26 @@
 use super::{SimpleTask, Task, TaskPartition};
+use crate::header::ValueType;
 use std::{iter::Once, ops::Range};
 
+/// The histogram of the values, in the float value mode each value is counted in the bin of
+/// its floor
 #[derive(Clone)]
-pub struct Histogram(String, u32, u32, Range<i32>);
+pub struct Histogram(String, u32, u32, Range<i32>, ValueType);
 
 impl Histogram {
     pub fn with_bin_range(chrom: &str, begin: u32, end: u32, bin_range: Range<i32>) -> Self {
-        Histogram(chrom.to_string(), begin, end, bin_range)
+        Histogram(chrom.to_string(), begin, end, bin_range, ValueType::Integer)
     }
 }
 
 impl SimpleTask for Histogram {
     fn new(chr: &str, start: u32, end: u32) -> Self {
-        Self(chr.to_string(), start, end, 0..1000)
+        Self(chr.to_string(), start, end, 0..1000, ValueType::Integer)
     }
 }
 
 pub struct Partition {
+    value_type: ValueType,
     base: i32,
     range: usize,
     histogram: Option<Vec<u32>>,
@@ -32,6 +36,7 @@ impl TaskPartition<Once<i32>> for Partition {
         let base = param.start;
         let range = (param.end - param.start).max(0) as usize;
         Self {
+            value_type: parent.4,
             base,
             histogram: None,
             range,
@@ -47,7 +52,10 @@ impl TaskPartition<Once<i32>> for Partition {
 
     #[inline(always)]
     fn feed_range(&mut self, left: u32, right: u32, value: &mut Once<i32>) -> bool {
-        let value = value.next().unwrap();
+        let value = match self.value_type {
+            ValueType::Integer => value.next().unwrap(),
+            ValueType::Float => self.value_type.decode(value.next().unwrap()).floor() as i32,
+        };
         let offset = value - self.base;
         let histogram = self.histogram.as_mut().unwrap();
         if offset < 0 {
@@ -75,6 +83,10 @@ impl Task<std::iter::Once<i32>> for Histogram {
         (self.0.as_ref(), self.1, self.2)
     }
 
+    fn set_value_type(&mut self, value_type: ValueType) {
+        self.4 = value_type;
+    }
+
     fn combine(&self, parts: &[(u32, Vec<u32>, u32)]) -> (u32, Vec<u32>, u32) {
         if parts.is_empty() {
             return (0, vec![], 0);
@@ -1,18 +1,20 @@
 use std::iter::Once;
 
 use super::{SimpleTask, Task, TaskPartition};
+use crate::header::ValueType;
 
 #[derive(Clone)]
 pub struct Mean{
     chr: String, 
     start: u32, 
     end: u32,
+    value_type: ValueType,
 }
 
 impl Mean {
     pub fn sum(chr: &str, start: u32, end: u32) -> Self {
         Self{
-            chr: chr.to_string(), start, end,
+            chr: chr.to_string(), start, end, value_type: ValueType::Integer,
         }
     }
 }
@@ -20,40 +22,54 @@ impl Mean {
 impl SimpleTask for Mean {
     fn new(chr: &str, start: u32, end: u32) -> Self {
         Self{
-            chr: chr.to_string(), start, end,
+            chr: chr.to_string(), start, end, value_type: ValueType::Integer,
         }
     }
 }
 
 pub struct MeanPartition {
     range: (u32, u32),
+    value_type: ValueType,
     sum: i64,
+    float_sum: f64,
+}
+
+impl MeanPartition {
+    #[inline(always)]
+    fn add(&mut self, value: i32, count: u32) {
+        match self.value_type {
+            ValueType::Integer => self.sum += value as i64 * count as i64,
+            ValueType::Float => self.float_sum += self.value_type.decode(value) * count as f64,
+        }
+    }
 }
 
 impl TaskPartition<Once<i32>> for MeanPartition {
     type ParentType = Mean;
-    type ResultType = (i64, usize);
-    fn new(left: u32, right: u32, _: &Self::ParentType) -> Self {
+    type ResultType = (f64, usize);
+    fn new(left: u32, right: u32, parent: &Self::ParentType) -> Self {
         Self {
             range: (left, right),
+            value_type: parent.value_type,
             sum: 0,
+            float_sum: 0.0,
         }
     }
     #[inline(always)]
     fn feed(&mut self, _: u32, value: &mut Once<i32>) -> bool {
         let value = value.next().unwrap();
-        self.sum += value as i64;
+        self.add(value, 1);
         true
     }
     #[inline(always)]
     fn feed_range(&mut self, left: u32, right: u32, value: &mut Once<i32>) -> bool {
         let value = value.next().unwrap();
-        self.sum += value as i64 * (right - left) as i64;
+        self.add(value, right - left);
         true
     }
 
     fn result(&mut self) -> Self::ResultType {
-        (self.sum, (self.range.1 - self.range.0) as usize)
+        (self.sum as f64 + self.float_sum, (self.range.1 - self.range.0) as usize)
     }
 }
 
@@ -65,8 +81,12 @@ impl Task<std::iter::Once<i32>> for Mean {
         (self.chr.as_ref(), self.start, self.end)
     }
 
-    fn combine(&self, parts: &[(i64, usize)]) -> f64 {
-        let mut values = 0;
+    fn set_value_type(&mut self, value_type: ValueType) {
+        self.value_type = value_type;
+    }
+
+    fn combine(&self, parts: &[(f64, usize)]) -> f64 {
+        let mut values = 0.0;
         let mut counts = 0;
         for (v, c) in parts {
             values += v;
@@ -76,6 +96,6 @@ impl Task<std::iter::Once<i32>> for Mean {
         if counts == 0 {
             return 0.0;
         }
-        values as f64 / counts as f64
+        values / counts as f64
     }
 }
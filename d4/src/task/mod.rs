@@ -1,6 +1,10 @@
 /*! The high-level parallel task interface */
 
+mod callable;
+mod combined;
+mod compute;
 mod context;
+mod diff;
 mod histogram;
 mod mean;
 mod sum;
@@ -10,15 +14,19 @@ mod perc_cov;
 
 use std::io::Result;
 
+pub use callable::Callable;
+pub use compute::{Compute, ComputeOp};
 pub use context::TaskContext;
+pub use diff::{Diff, DiffSummary};
 pub use histogram::Histogram;
 pub use mean::Mean;
-pub use sum::Sum;
+pub use sum::{FloatSum, Sum};
 pub use value_range::ValueRange;
 pub use vector::VectorStat;
 pub use perc_cov::PercentCov;
 
 use crate::d4file::{MultiTrackPartitionReader, MultiTrackReader};
+use crate::header::ValueType;
 
 pub trait SimpleTask {
     fn new(chr: &str, start: u32, end: u32) -> Self;
@@ -119,6 +127,9 @@ pub trait Task<RowType: Iterator<Item = i32> + ExactSizeIterator> {
     type Output;
     /// Get the effective range of this task
     fn region(&self) -> (&str, u32, u32);
+    /// Set the type of the values this task reads, this is called by the task context before
+    /// the task is split into partitions. By default the value type is ignored.
+    fn set_value_type(&mut self, _value_type: ValueType) {}
     /// Combine all the partitions and finalize the computation
     fn combine(
         &self,
@@ -1,4 +1,5 @@
 use super::{Task, TaskPartition};
+use crate::header::ValueType;
 use std::iter::Once;
 
 #[derive(Clone)]
@@ -6,6 +7,15 @@ pub struct PercentCovPart {
     //thresholds should be sorted
     thresholds: Vec<u32>,
     counts: Vec<u32>,
+    value_type: ValueType,
+}
+
+#[inline(always)]
+fn above_threshold(value_type: ValueType, value: i32, thresh: u32) -> bool {
+    match value_type {
+        ValueType::Integer => value as u32 >= thresh,
+        ValueType::Float => value_type.decode(value) >= thresh as f64,
+    }
 }
 
 impl TaskPartition<Once<i32>> for PercentCovPart {
@@ -15,13 +25,14 @@ impl TaskPartition<Once<i32>> for PercentCovPart {
         Self {
             thresholds: parent.3.clone(),
             counts: vec![0; parent.3.len()],
+            value_type: parent.4,
         }
     }
     // #[inline(always)]
     fn feed(&mut self, _: u32, value: &mut Once<i32>) -> bool {
         let value = value.next().unwrap();
         for (i, thresh) in self.thresholds.iter().enumerate() {
-            if value as u32 >= *thresh {
+            if above_threshold(self.value_type, value, *thresh) {
                 self.counts[i] += 1
             }
         }
@@ -31,7 +42,7 @@ impl TaskPartition<Once<i32>> for PercentCovPart {
     fn feed_range(&mut self, left: u32, right: u32, value: &mut Once<i32>) -> bool {
         let value = value.next().unwrap();
         for (i, thresh) in self.thresholds.iter().enumerate() {
-            if value as u32 >= *thresh {
+            if above_threshold(self.value_type, value, *thresh) {
                 self.counts[i] += right - left
             }
         }
@@ -43,11 +54,17 @@ impl TaskPartition<Once<i32>> for PercentCovPart {
     }
 }
 
-pub struct PercentCov(String, u32, u32, Vec<u32>);
+pub struct PercentCov(String, u32, u32, Vec<u32>, ValueType);
 
 impl PercentCov {
     pub fn new(chrom: &str, begin: u32, end: u32, thresholds: Vec<u32>) -> Self {
-        PercentCov(chrom.to_string(), begin, end, thresholds)
+        PercentCov(
+            chrom.to_string(),
+            begin,
+            end,
+            thresholds,
+            ValueType::Integer,
+        )
     }
 }
 
@@ -57,6 +74,9 @@ impl Task<std::iter::Once<i32>> for PercentCov {
     fn region(&self) -> (&str, u32, u32) {
         (self.0.as_str(), self.1, self.2)
     }
+    fn set_value_type(&mut self, value_type: ValueType) {
+        self.4 = value_type;
+    }
     fn combine(&self, parts: &[Vec<u32>]) -> Self::Output {
         let divisor = (self.2 - self.1) as f32;
 
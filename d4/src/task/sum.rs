@@ -1,12 +1,14 @@
 use std::iter::Once;
 
 use super::{SimpleTask, Task, TaskPartition};
+use crate::header::ValueType;
 
 #[derive(Clone)]
 pub struct Sum {
     chr: String,
     start: u32,
     end: u32,
+    value_type: ValueType,
 }
 
 impl Sum {
@@ -16,6 +18,7 @@ impl Sum {
             chr: chr.to_string(),
             start,
             end,
+            value_type: ValueType::Integer,
         }
     }
 }
@@ -26,35 +29,98 @@ impl SimpleTask for Sum {
             chr: chr.to_string(),
             start,
             end,
+            value_type: ValueType::Integer,
+        }
+    }
+}
+
+/// Sum of the values in a region as a floating point number, unlike [Sum] this reports the
+/// fractional part of the float value mode tracks
+#[derive(Clone)]
+pub struct FloatSum {
+    chr: String,
+    start: u32,
+    end: u32,
+    value_type: ValueType,
+}
+
+impl SimpleTask for FloatSum {
+    fn new(chr: &str, start: u32, end: u32) -> Self {
+        Self {
+            chr: chr.to_string(),
+            start,
+            end,
+            value_type: ValueType::Integer,
         }
     }
 }
 
 pub struct SumPartition {
+    value_type: ValueType,
     sum: i64,
+    float_sum: f64,
+}
+
+impl SumPartition {
+    fn with_value_type(value_type: ValueType) -> Self {
+        Self {
+            value_type,
+            sum: 0,
+            float_sum: 0.0,
+        }
+    }
+    #[inline(always)]
+    fn add(&mut self, value: i32, count: u32) {
+        match self.value_type {
+            ValueType::Integer => self.sum += value as i64 * count as i64,
+            ValueType::Float => self.float_sum += self.value_type.decode(value) * count as f64,
+        }
+    }
 }
 
 impl TaskPartition<Once<i32>> for SumPartition {
     type ParentType = Sum;
-    type ResultType = i64;
-    fn new(_: u32, _: u32, _: &Self::ParentType) -> Self {
-        Self { sum: 0 }
+    type ResultType = (i64, f64);
+    fn new(_: u32, _: u32, parent: &Self::ParentType) -> Self {
+        Self::with_value_type(parent.value_type)
     }
     #[inline(always)]
     fn feed(&mut self, _: u32, value: &mut Once<i32>) -> bool {
         let value = value.next().unwrap();
-        self.sum += value as i64;
+        self.add(value, 1);
         true
     }
     #[inline(always)]
     fn feed_range(&mut self, left: u32, right: u32, value: &mut Once<i32>) -> bool {
         let value = value.next().unwrap();
-        self.sum += value as i64 * (right - left) as i64;
+        self.add(value, right - left);
         true
     }
 
     fn result(&mut self) -> Self::ResultType {
-        self.sum
+        (self.sum, self.float_sum)
+    }
+}
+
+pub struct FloatSumPartition(SumPartition);
+
+impl TaskPartition<Once<i32>> for FloatSumPartition {
+    type ParentType = FloatSum;
+    type ResultType = (i64, f64);
+    fn new(_: u32, _: u32, parent: &Self::ParentType) -> Self {
+        Self(SumPartition::with_value_type(parent.value_type))
+    }
+    #[inline(always)]
+    fn feed(&mut self, pos: u32, value: &mut Once<i32>) -> bool {
+        self.0.feed(pos, value)
+    }
+    #[inline(always)]
+    fn feed_range(&mut self, left: u32, right: u32, value: &mut Once<i32>) -> bool {
+        self.0.feed_range(left, right, value)
+    }
+
+    fn result(&mut self) -> Self::ResultType {
+        self.0.result()
     }
 }
 
@@ -66,11 +132,41 @@ impl Task<std::iter::Once<i32>> for Sum {
         (self.chr.as_ref(), self.start, self.end)
     }
 
-    fn combine(&self, parts: &[i64]) -> i64 {
+    fn set_value_type(&mut self, value_type: ValueType) {
+        self.value_type = value_type;
+    }
+
+    /// The integer values are summed exactly, the float values are rounded to the nearest integer
+    fn combine(&self, parts: &[(i64, f64)]) -> i64 {
+        let mut values = 0;
+        let mut float_values = 0.0;
+        for (v, f) in parts {
+            values += v;
+            float_values += f;
+        }
+        values + float_values.round() as i64
+    }
+}
+
+impl Task<std::iter::Once<i32>> for FloatSum {
+    type Partition = FloatSumPartition;
+    type Output = f64;
+
+    fn region(&self) -> (&str, u32, u32) {
+        (self.chr.as_ref(), self.start, self.end)
+    }
+
+    fn set_value_type(&mut self, value_type: ValueType) {
+        self.value_type = value_type;
+    }
+
+    fn combine(&self, parts: &[(i64, f64)]) -> f64 {
         let mut values = 0;
-        for v in parts {
+        let mut float_values = 0.0;
+        for (v, f) in parts {
             values += v;
+            float_values += f;
         }
-        values
+        values as f64 + float_values
     }
 }
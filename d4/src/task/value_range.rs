@@ -1,32 +1,45 @@
 use std::iter::Once;
 
 use super::{SimpleTask, Task, TaskPartition};
+use crate::header::ValueType;
 
-pub struct ValueRange(String, u32, u32);
+#[derive(Clone)]
+pub struct ValueRange(String, u32, u32, ValueType);
 
 impl SimpleTask for ValueRange {
     fn new(chr: &str, start: u32, end: u32) -> Self {
-        Self(chr.to_string(), start, end)
+        Self(chr.to_string(), start, end, ValueType::Integer)
+    }
+}
+
+/// The initial (min, max) pair, which is the result of an empty region
+fn empty_range(value_type: ValueType) -> (f64, f64) {
+    match value_type {
+        ValueType::Integer => (i32::MAX as f64, i32::MIN as f64),
+        ValueType::Float => (f64::INFINITY, f64::NEG_INFINITY),
     }
 }
 
 pub struct ValueRangePartition {
-    min_value: i32,
-    max_value: i32,
+    value_type: ValueType,
+    min_value: f64,
+    max_value: f64,
 }
 
 impl TaskPartition<Once<i32>> for ValueRangePartition {
     type ParentType = ValueRange;
-    type ResultType = (i32, i32);
-    fn new(_left: u32, _right: u32, _: &Self::ParentType) -> Self {
+    type ResultType = (f64, f64);
+    fn new(_left: u32, _right: u32, parent: &Self::ParentType) -> Self {
+        let (min_value, max_value) = empty_range(parent.3);
         Self {
-            min_value: i32::MAX,
-            max_value: i32::MIN,
+            value_type: parent.3,
+            min_value,
+            max_value,
         }
     }
     #[inline(always)]
     fn feed(&mut self, _: u32, value: &mut Once<i32>) -> bool {
-        let value = value.next().unwrap();
+        let value = self.value_type.decode(value.next().unwrap());
         self.min_value = self.min_value.min(value);
         self.max_value = self.max_value.max(value);
         true
@@ -42,14 +55,18 @@ impl TaskPartition<Once<i32>> for ValueRangePartition {
 
 impl Task<Once<i32>> for ValueRange {
     type Partition = ValueRangePartition;
-    type Output = (i32, i32);
+    type Output = (f64, f64);
 
     fn region(&self) -> (&str, u32, u32) {
         (self.0.as_ref(), self.1, self.2)
     }
 
-    fn combine(&self, parts: &[(i32, i32)]) -> Self::Output {
-        let mut ret = (i32::MAX, i32::MIN);
+    fn set_value_type(&mut self, value_type: ValueType) {
+        self.3 = value_type;
+    }
+
+    fn combine(&self, parts: &[(f64, f64)]) -> Self::Output {
+        let mut ret = empty_range(self.3);
         for &(min, max) in parts {
             ret.0 = ret.0.min(min);
             ret.1 = ret.1.max(max);
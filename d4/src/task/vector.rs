@@ -1,6 +1,7 @@
 use std::iter::Once;
 
 use super::{SimpleTask, Task, TaskPartition};
+use crate::header::ValueType;
 
 pub struct VectorStat<T: Task<Once<i32>>> {
     scalar_tasks: Vec<T>,
@@ -80,6 +81,12 @@ where
         self.scalar_tasks[0].region()
     }
 
+    fn set_value_type(&mut self, value_type: ValueType) {
+        for task in self.scalar_tasks.iter_mut() {
+            task.set_value_type(value_type);
+        }
+    }
+
     fn combine(
         &self,
         parts: &[<Self::Partition as super::TaskPartition<R>>::ResultType],
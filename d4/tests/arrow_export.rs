@@ -0,0 +1,51 @@
+#![cfg(feature = "arrow")]
+
+mod common;
+
+use arrow_array::{cast::AsArray, types::Int32Type, types::UInt32Type};
+use d4::{arrow::ArrowExporter, D4TrackReader, Dictionary};
+
+#[test]
+fn intervals_spanning_partitions_are_merged() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("test.d4");
+    // The run of 3 spans the boundaries of the 10 Mb partitions
+    let intervals = [("chr1", 5_000_000, 25_000_000, 3), ("chr2", 0, 100, 3)];
+    let dict = Dictionary::new_simple_range_dict(0, 1).unwrap();
+    common::create_file(
+        &path,
+        &[("chr1", 30_000_000), ("chr2", 1000)],
+        dict,
+        &intervals,
+    );
+
+    let reader: D4TrackReader = D4TrackReader::open(path.to_str().unwrap()).unwrap();
+    let mut exporter = ArrowExporter::new([("value", reader)]).unwrap();
+    let mut rows = vec![];
+    exporter
+        .for_each_batch(|batch| {
+            let chroms = batch.column(0).as_dictionary::<Int32Type>();
+            let names = chroms.values().as_string::<i32>();
+            let begins = batch.column(1).as_primitive::<UInt32Type>();
+            let ends = batch.column(2).as_primitive::<UInt32Type>();
+            let values = batch.column(3).as_primitive::<Int32Type>();
+            for idx in 0..batch.num_rows() {
+                let chrom = names.value(chroms.keys().value(idx) as usize).to_string();
+                rows.push((chrom, begins.value(idx), ends.value(idx), values.value(idx)));
+            }
+            Ok(())
+        })
+        .unwrap();
+
+    let row = |chrom: &str, begin, end, value| (chrom.to_string(), begin, end, value);
+    assert_eq!(
+        rows,
+        [
+            row("chr1", 0, 5_000_000, 0),
+            row("chr1", 5_000_000, 25_000_000, 3),
+            row("chr1", 25_000_000, 30_000_000, 0),
+            row("chr2", 0, 100, 3),
+            row("chr2", 100, 1000, 0),
+        ]
+    );
+}
@@ -0,0 +1,58 @@
+//! The fixtures shared by the integration tests
+#![allow(dead_code)]
+
+use d4::{
+    ptab::PTablePartitionWriter, ssio::D4TrackReader as StreamReader,
+    stab::SecondaryTablePartWriter, Chrom, D4FileBuilder, D4FileWriter, Dictionary,
+};
+use std::{fs::File, path::Path};
+
+/// Create a file builder with the given chromosomes
+pub fn builder(path: &Path, chroms: &[(&str, usize)]) -> D4FileBuilder {
+    let mut builder = D4FileBuilder::new(path);
+    builder.append_chrom(chroms.iter().map(|&(name, size)| Chrom {
+        name: name.to_string(),
+        size,
+    }));
+    builder
+}
+
+/// Create a single track file, the values the primary table can't encode go to the secondary
+/// table. The intervals of each chromosome should be sorted and non-overlapping.
+pub fn create_file(
+    path: &Path,
+    chroms: &[(&str, usize)],
+    dict: Dictionary,
+    intervals: &[(&str, u32, u32, i32)],
+) {
+    let mut builder = builder(path, chroms);
+    builder.set_dictionary(dict);
+    let mut writer: D4FileWriter = builder.create().unwrap();
+    let mut partitions = writer.parallel_parts(None).unwrap();
+    for (p_part, s_part) in partitions.iter_mut() {
+        let (chrom, left, right) = p_part.region();
+        let chrom = chrom.to_string();
+        let mut encoder = p_part.make_encoder();
+        for &(_, begin, end, value) in intervals.iter().filter(|item| item.0 == chrom) {
+            for pos in begin.max(left)..end.min(right) {
+                if !encoder.encode(pos as usize, value) {
+                    s_part.encode(pos, value).unwrap();
+                }
+            }
+        }
+        s_part.flush().unwrap();
+    }
+    for (_, mut s_part) in partitions {
+        s_part.finish().unwrap();
+    }
+}
+
+/// Read the per-base values of a region with the stream reader
+pub fn read_values(path: &Path, chrom: &str, begin: u32, end: u32) -> Vec<i32> {
+    let mut reader = StreamReader::from_reader(File::open(path).unwrap(), None).unwrap();
+    reader
+        .get_view(chrom, begin, end)
+        .unwrap()
+        .map(|item| item.unwrap().1)
+        .collect()
+}
@@ -0,0 +1,112 @@
+mod common;
+
+use d4::{
+    ptab::{CompressedBitArrayWriter, DecodeResult, PTablePartitionWriter, PrimaryTablePartReader},
+    ssio::D4TrackReader as StreamReader,
+    stab::{
+        CompressionMethod, RangeRecord, SecondaryTablePartReader, SecondaryTablePartWriter,
+        SparseArrayWriter,
+    },
+    D4FileWriter, D4TrackReader,
+};
+use d4_framefile::Directory;
+use std::{fs::File, path::Path};
+
+const CHROMS: [(&str, usize); 2] = [("chr1", 300_000), ("chr2", 70_000)];
+
+fn expected_value(chrom: &str, pos: u32) -> i32 {
+    match (chrom, pos) {
+        ("chr1", 1000..=99_999) => (pos / 100 % 50) as i32,
+        ("chr1", 200_000..=200_099) => 1000,
+        ("chr2", 65_530..=65_545) => 7,
+        _ => 0,
+    }
+}
+
+fn create_file(path: &Path, method: CompressionMethod) {
+    let mut builder = common::builder(path, &CHROMS);
+    let mut writer: D4FileWriter<CompressedBitArrayWriter, SparseArrayWriter<RangeRecord>> =
+        builder.create().unwrap();
+    writer.set_primary_table_compression(method).unwrap();
+    // Small partitions, so that some of the blocks are shared by multiple partitions
+    let mut partitions = writer.parallel_parts(Some(40_000)).unwrap();
+    for (p_part, s_part) in partitions.iter_mut() {
+        let (chrom, begin, end) = p_part.region();
+        let chrom = chrom.to_string();
+        let mut encoder = p_part.make_encoder();
+        for pos in begin..end {
+            let value = expected_value(&chrom, pos);
+            if !encoder.encode(pos as usize, value) {
+                s_part.encode(pos, value).unwrap();
+            }
+        }
+    }
+    for (mut p_part, mut s_part) in partitions {
+        s_part.flush().unwrap();
+        s_part.finish().unwrap();
+        p_part.finish().unwrap();
+    }
+    writer.finish().unwrap();
+}
+
+#[test]
+fn compressed_primary_table_round_trip() {
+    let methods = [
+        CompressionMethod::NoCompression,
+        CompressionMethod::Deflate(5),
+        CompressionMethod::Zstd(3),
+        CompressionMethod::Lz4,
+    ];
+    for method in methods {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("test.d4");
+        create_file(&path, method);
+
+        let root = Directory::open_root(File::open(&path).unwrap(), 8).unwrap();
+        assert!(root.entry_kind(".zptab").is_some());
+        assert!(root.entry_kind(".ptab").is_none());
+
+        // The mapped reader
+        let mut reader: D4TrackReader = D4TrackReader::open(path.to_str().unwrap()).unwrap();
+        for (mut p_part, mut s_part) in reader.split(Some(30_000)).unwrap() {
+            let (chrom, begin, end) = p_part.region();
+            let chrom = chrom.to_string();
+            let decoder = p_part.make_decoder();
+            for pos in begin..end {
+                let value = match decoder.decode(pos as usize) {
+                    DecodeResult::Definitely(value) => value,
+                    DecodeResult::Maybe(value) => s_part.decode(pos).unwrap_or(value),
+                };
+                assert_eq!(value, expected_value(&chrom, pos), "{}:{}", chrom, pos);
+            }
+        }
+
+        // The stream reader, which decompresses the blocks on the fly
+        let mut reader = StreamReader::from_reader(File::open(&path).unwrap(), None).unwrap();
+        for &(chrom, size) in CHROMS.iter() {
+            for item in reader.get_view(chrom, 0, size as u32).unwrap() {
+                let (pos, value) = item.unwrap();
+                assert_eq!(value, expected_value(chrom, pos), "{}:{}", chrom, pos);
+            }
+        }
+    }
+}
+
+#[test]
+fn compression_is_set_before_split() {
+    let dir = tempfile::tempdir().unwrap();
+    let mut builder = common::builder(&dir.path().join("test.d4"), &[("chr1", 1000)]);
+    let mut writer: D4FileWriter<CompressedBitArrayWriter, SparseArrayWriter<RangeRecord>> =
+        builder.create().unwrap();
+    let _partitions = writer.parallel_parts(None).unwrap();
+    assert!(writer
+        .set_primary_table_compression(CompressionMethod::Lz4)
+        .is_err());
+
+    // The plain bit array can't be compressed
+    let mut builder = common::builder(&dir.path().join("plain.d4"), &[("chr1", 1000)]);
+    let mut writer: D4FileWriter = builder.create().unwrap();
+    assert!(writer
+        .set_primary_table_compression(CompressionMethod::Lz4)
+        .is_err());
+}
@@ -0,0 +1,98 @@
+mod common;
+
+use d4::{
+    task::{Compute, ComputeOp},
+    D4MatrixReader, D4TrackReader, Dictionary, MultiTrackReader,
+};
+use std::path::PathBuf;
+
+const CHROMS: [(&str, usize); 2] = [("chr1", 1000), ("chr2", 500)];
+
+fn create_tracks(dir: &std::path::Path) -> Vec<PathBuf> {
+    let tracks = [
+        vec![
+            ("chr1", 100, 200, 3),
+            ("chr1", 300, 400, 5),
+            ("chr2", 0, 50, 1),
+        ],
+        vec![
+            ("chr1", 150, 350, 4),
+            ("chr1", 350, 360, 1000),
+            ("chr2", 490, 500, 2),
+        ],
+    ];
+    tracks
+        .iter()
+        .enumerate()
+        .map(|(idx, intervals)| {
+            let path = dir.join(format!("{}.d4", idx));
+            let dict = Dictionary::new_simple_range_dict(0, 8).unwrap();
+            common::create_file(&path, &CHROMS, dict, intervals);
+            path
+        })
+        .collect()
+}
+
+fn expected_value(op: ComputeOp, first: i32, second: i32) -> f64 {
+    let (first, second) = (first as f64, second as f64);
+    match op {
+        ComputeOp::Sum => first + second,
+        ComputeOp::Mean => (first + second) / 2.0,
+        ComputeOp::Min => first.min(second),
+        ComputeOp::Max => first.max(second),
+        ComputeOp::Diff => first - second,
+        ComputeOp::Ratio if second == 0.0 => 0.0,
+        ComputeOp::Ratio => first / second,
+    }
+}
+
+#[test]
+fn compute_tracks_base_by_base() {
+    let dir = tempfile::tempdir().unwrap();
+    let paths = create_tracks(dir.path());
+    let ops = [
+        ComputeOp::Sum,
+        ComputeOp::Mean,
+        ComputeOp::Min,
+        ComputeOp::Max,
+        ComputeOp::Diff,
+        ComputeOp::Ratio,
+    ];
+    for op in ops {
+        let readers = paths.iter().map(|path| {
+            let reader: D4TrackReader = D4TrackReader::open(path.to_str().unwrap()).unwrap();
+            reader
+        });
+        let mut matrix = D4MatrixReader::new(readers).unwrap();
+        let tasks: Vec<_> = CHROMS
+            .iter()
+            .map(|&(chrom, size)| Compute::new(chrom, 0, size as u32, op, vec![1.0; 2]).unwrap())
+            .collect();
+        for output in matrix.run_tasks(tasks).unwrap().into_iter() {
+            let first = common::read_values(&paths[0], output.chrom, output.begin, output.end);
+            let second = common::read_values(&paths[1], output.chrom, output.begin, output.end);
+            let mut pos = output.begin;
+            for (idx, &(left, right, value)) in output.output.iter().enumerate() {
+                assert_eq!(left, pos, "{:?} {}", op, output.chrom);
+                pos = right;
+                // Adjacent intervals with the same value are merged
+                if idx > 0 {
+                    assert_ne!(output.output[idx - 1].2, value, "{:?} {}", op, output.chrom);
+                }
+                for base in left..right {
+                    let offset = (base - output.begin) as usize;
+                    let expected = expected_value(op, first[offset], second[offset]);
+                    assert_eq!(value, expected, "{:?} {}:{}", op, output.chrom, base);
+                }
+            }
+            assert_eq!(pos, output.end, "{:?} {}", op, output.chrom);
+        }
+    }
+}
+
+#[test]
+fn compute_checks_the_number_of_tracks() {
+    assert!(Compute::new("chr1", 0, 1000, ComputeOp::Diff, vec![1.0; 3]).is_err());
+    assert!(Compute::new("chr1", 0, 1000, ComputeOp::Ratio, vec![1.0]).is_err());
+    assert!(Compute::new("chr1", 0, 1000, ComputeOp::Sum, vec![1.0; 3]).is_ok());
+}
@@ -0,0 +1,221 @@
+mod common;
+
+use d4::{
+    index::{histogram_percentile, D4IndexCollection, Sum, ValueHistogram},
+    ssio::{D4MatrixReader as StreamMatrixReader, D4TrackReader as StreamReader},
+    task::{Histogram, Task},
+    D4FileMerger, D4FileWriter, D4TrackReader, Dictionary,
+};
+use d4_framefile::Directory;
+use std::{
+    fs::{File, OpenOptions},
+    io::Read,
+    path::Path,
+};
+
+const CHROM_SIZE: usize = 3_000_000;
+
+fn create_test_file(path: &std::path::Path) {
+    let mut builder = common::builder(path, &[("chr1", CHROM_SIZE)]);
+    let mut writer: D4FileWriter = builder.create().unwrap();
+    // The first 1.5M bases are all zero, the rest has a value pattern
+    let intervals = (1_500_000..CHROM_SIZE as u32)
+        .step_by(100)
+        .map(|begin| ("chr1", begin, begin + 100, ((begin / 100) % 40) as i32));
+    writer.write_intervals(intervals).unwrap();
+}
+
+#[test]
+fn histogram_index_agrees_with_scan() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("test.d4");
+    create_test_file(&path);
+    D4IndexCollection::open_for_write(&path)
+        .unwrap()
+        .create_histogram_index()
+        .unwrap();
+
+    let regions = [
+        (0, 1 << 20),
+        (0, 1_500_000),
+        (1_000_000, 2_500_000),
+        (1 << 20, 3 << 20),
+        (0, CHROM_SIZE as u32),
+        (1_600_123, 1_600_777),
+    ];
+
+    let mut reader: D4TrackReader = D4TrackReader::open(path.to_str().unwrap()).unwrap();
+    let tasks: Vec<_> = regions
+        .iter()
+        .map(|&(begin, end)| Histogram::with_bin_range("chr1", begin, end, 0..1000))
+        .collect();
+    let scan_results = Histogram::create_task(&mut reader, tasks).unwrap().run();
+
+    let mut stream_reader = StreamReader::from_reader(File::open(&path).unwrap(), None).unwrap();
+    let index = stream_reader.load_data_index::<ValueHistogram>().unwrap();
+
+    assert_eq!(scan_results.len(), regions.len());
+    for result in scan_results.into_iter() {
+        let (begin, end) = (result.begin, result.end);
+        let (below, hist, above) = result.output;
+        let index_result = index
+            .query("chr1", begin, end)
+            .unwrap()
+            .get_result(&mut stream_reader)
+            .unwrap();
+        for percentile in [0.0, 0.1, 0.5, 0.9, 1.0] {
+            let scan_value = histogram_percentile(*below as u64, hist, *above as u64, percentile);
+            assert_eq!(
+                scan_value,
+                index_result.percentile(percentile),
+                "percentile {} of chr1:{}-{}",
+                percentile,
+                begin,
+                end
+            );
+        }
+    }
+
+    // The median of an all-zero region is 0
+    let all_zero = index
+        .query("chr1", 0, 1 << 20)
+        .unwrap()
+        .get_result(&mut stream_reader)
+        .unwrap();
+    assert_eq!(all_zero.percentile(0.5), Some(0));
+}
+
+/// Rewrite the sum index in the layout of the older versions, whose header is only the 4-byte
+/// granularity, which makes the blob 4 bytes shorter than the index
+fn downgrade_sum_index(path: &Path) {
+    let file = OpenOptions::new()
+        .read(true)
+        .write(true)
+        .open(path)
+        .unwrap();
+    let root = Directory::open_root_for_update(file, 8).unwrap();
+    let mut index_root = root.open_directory_for_update(".index").unwrap();
+    let mut blob = index_root.open_blob("sum_index").unwrap();
+    let mut current = vec![0; blob.size()];
+    blob.get_reader().read_exact(&mut current).unwrap();
+
+    let mut legacy = current[..4].to_vec();
+    // The padding before the 8-byte aligned sums
+    legacy.extend_from_slice(&[0xab; 4]);
+    legacy.extend_from_slice(&current[8..current.len() - 4]);
+    let mut blob = index_root.replace_blob("sum_index", legacy.len()).unwrap();
+    blob.mmap_mut().unwrap().as_mut().copy_from_slice(&legacy);
+}
+
+#[test]
+fn load_legacy_sum_index() {
+    // chr2 ends at a bin boundary, so its last bin is a complete one, which the legacy index
+    // can't answer
+    let chroms = [("chr1", 200_000), ("chr2", 131_072)];
+    let intervals: Vec<_> = chroms
+        .iter()
+        .flat_map(|&(chrom, size)| {
+            (0..size as u32)
+                .step_by(1000)
+                .map(move |begin| (chrom, begin, begin + 500, (begin / 1000 % 37) as i32 + 1))
+        })
+        .collect();
+    let expected_sum = |chrom: &str, begin: u32, end: u32| {
+        intervals
+            .iter()
+            .filter(|item| item.0 == chrom)
+            .map(|&(_, left, right, value)| {
+                right.min(end).saturating_sub(left.max(begin)) as f64 * value as f64
+            })
+            .sum::<f64>()
+    };
+
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("test.d4");
+    let dict = Dictionary::new_simple_range_dict(0, 64).unwrap();
+    common::create_file(&path, &chroms, dict, &intervals);
+    D4IndexCollection::open_for_write(&path)
+        .unwrap()
+        .create_sum_index()
+        .unwrap();
+    downgrade_sum_index(&path);
+
+    let mut stream_reader = StreamReader::from_reader(File::open(&path).unwrap(), None).unwrap();
+    let index = stream_reader.load_data_index::<Sum>().unwrap();
+    assert_eq!(index.granularity(), 65536);
+    let regions = [
+        ("chr1", 0, 200_000),
+        ("chr1", 65536, 131_072),
+        ("chr2", 0, 131_072),
+        ("chr2", 65536, 131_072),
+        ("chr2", 1000, 131_000),
+    ];
+    for (chrom, begin, end) in regions {
+        let sum = index
+            .query(chrom, begin, end)
+            .unwrap()
+            .get_result(&mut stream_reader)
+            .unwrap()
+            .sum();
+        assert_eq!(
+            sum,
+            expected_sum(chrom, begin, end),
+            "{}:{}-{}",
+            chrom,
+            begin,
+            end
+        );
+    }
+}
+
+#[test]
+fn multi_track_sum_index() {
+    let chroms = [("chr1", 300_000)];
+    let dir = tempfile::tempdir().unwrap();
+    let track_values = [3, 5];
+    let mut merger = D4FileMerger::new(dir.path().join("multi.d4"));
+    for (idx, value) in track_values.iter().enumerate() {
+        let path = dir.path().join(format!("track{}.d4", idx));
+        let dict = Dictionary::new_simple_range_dict(0, 8).unwrap();
+        let intervals = [("chr1", 1000 * idx as u32, 200_000, *value)];
+        common::create_file(&path, &chroms, dict, &intervals);
+        merger = merger.add_input(&path);
+    }
+    merger.merge().unwrap();
+    let path = dir.path().join("multi.d4");
+
+    // Only one of the tracks is indexed
+    D4IndexCollection::open_track_for_write(&path, Some("track0"))
+        .unwrap()
+        .create_sum_index()
+        .unwrap();
+    let matrix = StreamMatrixReader::open_tracks_in_file(&path, |_| true).unwrap();
+    assert!(matrix.load_data_index::<Sum>().is_err());
+
+    D4IndexCollection::open_track_for_write(&path, Some("track1"))
+        .unwrap()
+        .create_sum_index()
+        .unwrap();
+    let mut matrix = StreamMatrixReader::open_tracks_in_file(&path, |_| true).unwrap();
+    let index = matrix.load_data_index::<Sum>().unwrap();
+    assert_eq!(index.len(), 2);
+    for (begin, end) in [(0, 300_000), (500, 131_072), (65536, 196_608)] {
+        let sums: Vec<_> = matrix
+            .query_data_index(&index, "chr1", begin, end)
+            .unwrap()
+            .iter()
+            .map(|sum| sum.sum())
+            .collect();
+        let expected: Vec<_> = track_values
+            .iter()
+            .enumerate()
+            .map(|(idx, value)| {
+                let covered = end
+                    .min(200_000)
+                    .saturating_sub(begin.max(1000 * idx as u32));
+                covered as f64 * *value as f64
+            })
+            .collect();
+        assert_eq!(sums, expected, "chr1:{}-{}", begin, end);
+    }
+}
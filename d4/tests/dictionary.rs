@@ -0,0 +1,60 @@
+mod common;
+
+use d4::{D4FileWriter, Dictionary};
+
+#[test]
+fn profiled_dictionary_keeps_gaps_zero() {
+    const CHROM_SIZE: usize = 1_000_000;
+    // The most common value is 5, but the bases after 900,000 aren't covered by any interval
+    let intervals = [("chr1", 0, 900_000, 5)];
+    let dict = Dictionary::from_sample_intervals(&[("chr1", CHROM_SIZE)], intervals).unwrap();
+    assert_eq!(dict.first_value(), 0);
+
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("gap.d4");
+    let mut builder = common::builder(&path, &[("chr1", CHROM_SIZE)]);
+    builder.set_dictionary(dict);
+    let mut writer: D4FileWriter = builder.create().unwrap();
+    writer.write_intervals(intervals).unwrap();
+    drop(writer);
+
+    let values = common::read_values(&path, "chr1", 0, CHROM_SIZE as u32);
+    for (pos, value) in values.into_iter().enumerate() {
+        let expected = if pos < 900_000 { 5 } else { 0 };
+        assert_eq!(value, expected, "chr1:{}", pos);
+    }
+}
+
+#[test]
+fn dictionary_list_is_range_only_if_consecutive() {
+    let dict = Dictionary::from_dict_list(vec![0, 5, -2, 3]).unwrap();
+    assert!(matches!(dict, Dictionary::Dictionary { .. }));
+    let dict = Dictionary::from_dict_list(vec![0, 1, 2, 3]).unwrap();
+    assert!(matches!(dict, Dictionary::SimpleRange { low: 0, high: 4 }));
+}
+
+#[test]
+fn gaps_are_zero_if_code_zero_is_not_zero() {
+    const CHROM_SIZE: usize = 100_000;
+    let intervals = [("chr1", 1_000, 2_000, 5), ("chr1", 3_000, 4_000, 7)];
+    // Both a 0-bit and a multi-bit primary table that don't encode 0
+    for (low, high) in [(5, 6), (5, 9)] {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("gap.d4");
+        let mut builder = common::builder(&path, &[("chr1", CHROM_SIZE)]);
+        builder.set_dictionary(Dictionary::new_simple_range_dict(low, high).unwrap());
+        let mut writer: D4FileWriter = builder.create().unwrap();
+        writer.write_intervals(intervals).unwrap();
+        drop(writer);
+
+        let values = common::read_values(&path, "chr1", 0, CHROM_SIZE as u32);
+        for (pos, value) in values.into_iter().enumerate() {
+            let pos = pos as u32;
+            let expected = intervals
+                .iter()
+                .find(|(_, begin, end, _)| *begin <= pos && pos < *end)
+                .map_or(0, |(_, _, _, value)| *value);
+            assert_eq!(value, expected, "chr1:{} with range {}-{}", pos, low, high);
+        }
+    }
+}
@@ -0,0 +1,139 @@
+mod common;
+
+use d4::{
+    ptab::BitArrayWriter,
+    ssio::D4TrackReader as StreamReader,
+    stab::{FloatRangeRecord, SparseArrayWriter},
+    task::{FloatSum, Mean, SimpleTask, Sum, Task, ValueRange},
+    D4FileWriter, D4TrackReader, Dictionary, ValueType,
+};
+use d4_framefile::Directory;
+use std::fs::File;
+
+const CHROM_SIZE: usize = 100_000;
+
+// Half of the values are encoded by the dictionary, the others go to the secondary table
+const INTERVALS: [(u32, u32, f32); 4] = [
+    (100, 200, 0.5),
+    (200, 250, 1.25),
+    (1_000, 1_010, -3.75),
+    (50_000, 60_000, 0.5),
+];
+
+fn value_at(pos: u32) -> f64 {
+    INTERVALS
+        .iter()
+        .find(|(begin, end, _)| *begin <= pos && pos < *end)
+        .map_or(0.0, |(_, _, value)| *value as f64)
+}
+
+fn create_float_file(path: &std::path::Path) {
+    let mut builder = common::builder(path, &[("chr1", CHROM_SIZE)]);
+    builder
+        .set_dictionary(Dictionary::from_float_list(vec![0.0, 0.5]).unwrap())
+        .set_value_type(ValueType::Float);
+    let mut writer: D4FileWriter<BitArrayWriter, SparseArrayWriter<FloatRangeRecord>> =
+        builder.create().unwrap();
+    let intervals = INTERVALS
+        .iter()
+        .map(|&(begin, end, value)| ("chr1", begin, end, ValueType::Float.encode(value as f64)));
+    writer.write_intervals(intervals).unwrap();
+}
+
+#[test]
+fn float_value_round_trip() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("float.d4");
+    create_float_file(&path);
+
+    // The older versions look for .stab only, so they can't open the float track
+    let root = Directory::open_root(File::open(&path).unwrap(), 8).unwrap();
+    assert!(root.entry_kind(".stab").is_none());
+    assert!(root.entry_kind(".xstab").is_some());
+
+    let mut reader = StreamReader::from_reader(File::open(&path).unwrap(), None).unwrap();
+    assert_eq!(reader.get_header().value_type(), ValueType::Float);
+    assert!(!reader.get_header().is_integral());
+    match reader.get_header().dictionary() {
+        Dictionary::FloatDictionary { i2v_map, .. } => assert_eq!(i2v_map, &[0.0, 0.5]),
+        _ => panic!("The float dictionary isn't preserved"),
+    }
+
+    let mut expected_pos = 0;
+    for item in reader.get_real_view("chr1", 0, CHROM_SIZE as u32).unwrap() {
+        let (pos, value) = item.unwrap();
+        assert_eq!(pos, expected_pos);
+        assert_eq!(value, value_at(pos), "value at chr1:{}", pos);
+        expected_pos += 1;
+    }
+    assert_eq!(expected_pos, CHROM_SIZE as u32);
+}
+
+#[test]
+fn float_value_tasks() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("float.d4");
+    create_float_file(&path);
+
+    let regions = [
+        (0, CHROM_SIZE as u32),
+        (150, 1_005),
+        (900, 1_100),
+        (55_000, 70_000),
+    ];
+    let expected_sum = |begin: u32, end: u32| (begin..end).map(value_at).sum::<f64>();
+
+    let mut reader: D4TrackReader = D4TrackReader::open(path.to_str().unwrap()).unwrap();
+    let tasks: Vec<_> = regions
+        .iter()
+        .map(|&(begin, end)| FloatSum::new("chr1", begin, end))
+        .collect();
+    for result in FloatSum::create_task(&mut reader, tasks)
+        .unwrap()
+        .run()
+        .into_iter()
+    {
+        assert_eq!(*result.output, expected_sum(result.begin, result.end));
+    }
+
+    let tasks: Vec<_> = regions
+        .iter()
+        .map(|&(begin, end)| Sum::new("chr1", begin, end))
+        .collect();
+    for result in Sum::create_task(&mut reader, tasks)
+        .unwrap()
+        .run()
+        .into_iter()
+    {
+        let expected = expected_sum(result.begin, result.end).round() as i64;
+        assert_eq!(*result.output, expected);
+    }
+
+    let tasks: Vec<_> = regions
+        .iter()
+        .map(|&(begin, end)| Mean::new("chr1", begin, end))
+        .collect();
+    for result in Mean::create_task(&mut reader, tasks)
+        .unwrap()
+        .run()
+        .into_iter()
+    {
+        let expected = expected_sum(result.begin, result.end) / (result.end - result.begin) as f64;
+        assert!((*result.output - expected).abs() < 1e-9);
+    }
+
+    let tasks: Vec<_> = regions
+        .iter()
+        .map(|&(begin, end)| ValueRange::new("chr1", begin, end))
+        .collect();
+    for result in ValueRange::create_task(&mut reader, tasks)
+        .unwrap()
+        .run()
+        .into_iter()
+    {
+        let values: Vec<_> = (result.begin..result.end).map(value_at).collect();
+        let min = values.iter().copied().fold(f64::INFINITY, f64::min);
+        let max = values.iter().copied().fold(f64::NEG_INFINITY, f64::max);
+        assert_eq!(*result.output, (min, max));
+    }
+}
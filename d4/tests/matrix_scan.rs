@@ -0,0 +1,55 @@
+mod common;
+
+use d4::{
+    task::{Mean, VectorStat},
+    D4MatrixReader, D4TrackReader, Dictionary, MultiTrackReader,
+};
+
+const CHROM_SIZE: usize = 1000;
+
+#[test]
+fn scan_zero_sized_tracks_per_interval() {
+    let dir = tempfile::tempdir().unwrap();
+    let tracks = [
+        vec![("chr1", 100, 200, 3), ("chr1", 300, 400, 5)],
+        vec![("chr1", 150, 350, 7), ("chr1", 350, 360, 2)],
+    ];
+    let paths: Vec<_> = tracks
+        .iter()
+        .enumerate()
+        .map(|(idx, intervals)| {
+            let path = dir.path().join(format!("{}.d4", idx));
+            // A 0-bit primary table, so every value is in the secondary table
+            let dict = Dictionary::new_simple_range_dict(0, 1).unwrap();
+            common::create_file(&path, &[("chr1", CHROM_SIZE)], dict, intervals);
+            path
+        })
+        .collect();
+
+    let readers = paths.iter().map(|path| {
+        let reader: D4TrackReader = D4TrackReader::open(path.to_str().unwrap()).unwrap();
+        reader
+    });
+    let mut matrix = D4MatrixReader::new(readers).unwrap();
+    let regions: Vec<_> = (0..CHROM_SIZE as u32).step_by(50).collect();
+    let tasks: Vec<_> = regions
+        .iter()
+        .map(|&begin| {
+            VectorStat::<Mean>::create_vector_task_for_region(2, "chr1", begin, begin + 50)
+        })
+        .collect();
+    let result = matrix.run_tasks(tasks).unwrap();
+
+    let paths: Vec<_> = paths.iter().collect();
+    for output in result.into_iter() {
+        for (track, mean) in output.output.iter().enumerate() {
+            let values = common::read_values(paths[track], "chr1", output.begin, output.end);
+            let expected = values.iter().sum::<i32>() as f64 / values.len() as f64;
+            assert_eq!(
+                *mean, expected,
+                "track {} chr1:{}-{}",
+                track, output.begin, output.end
+            );
+        }
+    }
+}
@@ -0,0 +1,65 @@
+mod common;
+
+use d4::{
+    stab::{SecondaryTablePartReader, SecondaryTablePartWriter},
+    task::{Histogram, Task},
+    D4FileWriter, D4TrackReader, Dictionary,
+};
+use std::path::Path;
+
+const PART_SIZE: u32 = 100_000;
+
+/// Create a file with a 0-bit primary table and a secondary table partition for each
+/// `PART_SIZE` bases, each of the given records is written to the partition it starts in
+fn create_file(path: &Path, records: &[(u32, u32, i32)]) {
+    let mut builder = common::builder(path, &[("chr1", 4 * PART_SIZE as usize)]);
+    builder.set_dictionary(Dictionary::new_simple_range_dict(0, 1).unwrap());
+    let mut writer: D4FileWriter = builder.create().unwrap();
+    let mut partitions = writer.parallel_parts(Some(PART_SIZE as usize)).unwrap();
+    for &(begin, end, value) in records {
+        let (_, s_part) = partitions
+            .iter_mut()
+            .find(|(p_part, _)| p_part.region().1 <= begin && begin < p_part.region().2)
+            .unwrap();
+        s_part.encode_record(begin, end, value).unwrap();
+    }
+    for (_, mut s_part) in partitions {
+        s_part.finish().unwrap();
+    }
+}
+
+#[test]
+fn block_starting_at_partition_end() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("test.d4");
+    // The records of the second partition start right at the end of the first one
+    create_file(&path, &[(500, 600, 1), (PART_SIZE, PART_SIZE + 10, 2)]);
+
+    let mut reader: D4TrackReader = D4TrackReader::open(path.to_str().unwrap()).unwrap();
+    let parts = reader.split(Some(PART_SIZE as usize)).unwrap();
+    let records: Vec<Vec<_>> = parts
+        .iter()
+        .map(|(_, s_part)| s_part.iter().collect())
+        .collect();
+    assert_eq!(records[0], [(500, 600, 1)]);
+    assert_eq!(records[1], [(PART_SIZE, PART_SIZE + 10, 2)]);
+    assert!(records[2..].iter().all(Vec::is_empty));
+}
+
+#[test]
+fn records_after_task_region_are_ignored() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("test.d4");
+    create_file(&path, &[(100, 200, 3), (1_000, 1_100, 4)]);
+
+    // The region ends in the gap between the records, so the scan must stop before the second
+    let mut reader: D4TrackReader = D4TrackReader::open(path.to_str().unwrap()).unwrap();
+    let tasks = vec![Histogram::with_bin_range("chr1", 0, 500, 0..10)];
+    let results = Histogram::create_task(&mut reader, tasks).unwrap().run();
+    let result = results.into_iter().next().unwrap();
+    let (below, hist, above) = result.output;
+    assert_eq!((*below, *above), (0, 0));
+    assert_eq!(hist[0], 400);
+    assert_eq!(hist[3], 100);
+    assert_eq!(hist.iter().sum::<u32>(), 500);
+}
@@ -0,0 +1,74 @@
+mod common;
+
+use d4::{
+    task::{Mean, SimpleTask, Task, VectorStat},
+    D4MatrixReader, D4TrackReader, Dictionary, MultiTrackReader,
+};
+use std::path::{Path, PathBuf};
+
+const CHROM_SIZE: u32 = 10_000;
+
+/// The value of a track with a 4-bit primary table, most of the values are read from an address
+/// that isn't 4-byte aligned
+fn value_at(seed: u32, pos: u32) -> i32 {
+    ((pos * 7 + seed) % 13) as i32
+}
+
+fn expected_mean(seed: u32, begin: u32, end: u32) -> f64 {
+    (begin..end).map(|pos| value_at(seed, pos)).sum::<i32>() as f64 / (end - begin) as f64
+}
+
+fn create_tracks(dir: &Path, count: u32) -> Vec<PathBuf> {
+    (0..count)
+        .map(|seed| {
+            let path = dir.join(format!("{}.d4", seed));
+            let intervals: Vec<_> = (0..CHROM_SIZE)
+                .map(|pos| ("chr1", pos, pos + 1, value_at(seed, pos)))
+                .collect();
+            let dict = Dictionary::new_simple_range_dict(0, 16).unwrap();
+            common::create_file(&path, &[("chr1", CHROM_SIZE as usize)], dict, &intervals);
+            path
+        })
+        .collect()
+}
+
+#[test]
+fn decode_track_at_unaligned_addresses() {
+    let dir = tempfile::tempdir().unwrap();
+    let paths = create_tracks(dir.path(), 1);
+    let mut reader: D4TrackReader = D4TrackReader::open(paths[0].to_str().unwrap()).unwrap();
+    let tasks: Vec<_> = (0..CHROM_SIZE)
+        .step_by(1000)
+        .map(|begin| Mean::new("chr1", begin + 3, begin + 1000))
+        .collect();
+    for result in Mean::create_task(&mut reader, tasks)
+        .unwrap()
+        .run()
+        .into_iter()
+    {
+        assert_eq!(*result.output, expected_mean(0, result.begin, result.end));
+    }
+}
+
+#[test]
+fn decode_matrix_at_unaligned_addresses() {
+    let dir = tempfile::tempdir().unwrap();
+    let paths = create_tracks(dir.path(), 2);
+    let readers = paths.iter().map(|path| {
+        let reader: D4TrackReader = D4TrackReader::open(path.to_str().unwrap()).unwrap();
+        reader
+    });
+    let mut matrix = D4MatrixReader::new(readers).unwrap();
+    // Only the regions longer than 1000 bases are decoded as a block
+    let tasks: Vec<_> = (0..CHROM_SIZE)
+        .step_by(5000)
+        .map(|begin| {
+            VectorStat::<Mean>::create_vector_task_for_region(2, "chr1", begin + 3, begin + 5000)
+        })
+        .collect();
+    for result in matrix.run_tasks(tasks).unwrap().into_iter() {
+        for (seed, mean) in result.output.iter().enumerate() {
+            assert_eq!(*mean, expected_mean(seed as u32, result.begin, result.end));
+        }
+    }
+}
@@ -0,0 +1,110 @@
+mod common;
+
+use d4::{
+    index::D4IndexCollection,
+    ptab::{BitArrayReader, BitArrayWriter, DecodeResult, PrimaryTablePartReader},
+    ssio::D4TrackReader as StreamReader,
+    stab::{
+        RangeRecord, RunLengthRecord, SecondaryTablePartReader, SecondaryTableReader,
+        SecondaryTableWriter, SparseArrayReader, SparseArrayWriter,
+    },
+    D4FileWriter, D4TrackReader, Dictionary,
+};
+use d4_framefile::Directory;
+use std::{fs::File, path::Path};
+
+const CHROM_SIZE: u32 = 1_000_000;
+// A single run that is longer than a range record is able to cover
+const LONG_RUN: (u32, u32) = (500_000, 800_000);
+
+fn expected_value(pos: u32) -> i32 {
+    match pos {
+        0..=399_999 if pos % 10 < 5 => (pos / 10 % 7) as i32 + 1,
+        _ if LONG_RUN.0 <= pos && pos < LONG_RUN.1 => 7,
+        _ => 0,
+    }
+}
+
+fn create_file<ST: SecondaryTableWriter>(path: &Path) {
+    let mut builder = common::builder(path, &[("chr1", CHROM_SIZE as usize)]);
+    builder
+        // Only 0 is encoded by the primary table, all the other values go to the secondary table
+        .set_dictionary(Dictionary::new_simple_range_dict(0, 1).unwrap());
+    let mut writer: D4FileWriter<BitArrayWriter, ST> = builder.create().unwrap();
+    let intervals = (0..400_000)
+        .step_by(10)
+        .map(|begin| ("chr1", begin, begin + 5, expected_value(begin)))
+        .chain(std::iter::once(("chr1", LONG_RUN.0, LONG_RUN.1, 7)));
+    writer.write_intervals(intervals).unwrap();
+}
+
+fn check_mapped_reader<S: SecondaryTableReader>(path: &Path) {
+    let mut reader: D4TrackReader<BitArrayReader, S> =
+        D4TrackReader::open(path.to_str().unwrap()).unwrap();
+    for (mut p_part, mut s_part) in reader.split(Some(100_000)).unwrap() {
+        let (_, begin, end) = p_part.region();
+        let decoder = p_part.make_decoder();
+        for pos in begin..end {
+            let value = match decoder.decode(pos as usize) {
+                DecodeResult::Definitely(value) => value,
+                DecodeResult::Maybe(value) => s_part.decode(pos).unwrap_or(value),
+            };
+            assert_eq!(value, expected_value(pos), "chr1:{}", pos);
+        }
+    }
+}
+
+fn check_stream_reader(path: &Path) {
+    let mut reader = StreamReader::from_reader(File::open(path).unwrap(), None).unwrap();
+    let regions = [
+        (0, CHROM_SIZE),
+        (123_457, 700_001),
+        (399_990, 500_010),
+        (650_000, 660_000),
+        (799_999, 800_001),
+    ];
+    for (begin, end) in regions {
+        let mut expected_pos = begin;
+        for item in reader.get_view("chr1", begin, end).unwrap() {
+            let (pos, value) = item.unwrap();
+            assert_eq!(pos, expected_pos);
+            assert_eq!(value, expected_value(pos), "chr1:{}", pos);
+            expected_pos += 1;
+        }
+        assert_eq!(expected_pos, end);
+    }
+}
+
+#[test]
+fn run_length_round_trip() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("run_length.d4");
+    create_file::<SparseArrayWriter<RunLengthRecord>>(&path);
+
+    // The older versions look for .stab only, so they can't open the run-length track
+    let root = Directory::open_root(File::open(&path).unwrap(), 8).unwrap();
+    assert!(root.entry_kind(".stab").is_none());
+    assert!(root.entry_kind(".xstab").is_some());
+
+    // Read the records as they are stored and converted to range records
+    check_mapped_reader::<SparseArrayReader<RunLengthRecord>>(&path);
+    check_mapped_reader::<SparseArrayReader<RangeRecord>>(&path);
+
+    check_stream_reader(&path);
+    // The secondary frame index lets the stream reader start in the middle of a partition
+    D4IndexCollection::open_for_write(&path)
+        .unwrap()
+        .create_secondary_frame_index()
+        .unwrap();
+    check_stream_reader(&path);
+}
+
+#[test]
+fn range_records_read_as_run_length() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("range.d4");
+    create_file::<SparseArrayWriter<RangeRecord>>(&path);
+
+    check_mapped_reader::<SparseArrayReader<RunLengthRecord>>(&path);
+    check_mapped_reader::<SparseArrayReader<RangeRecord>>(&path);
+}
@@ -0,0 +1,40 @@
+use d4::Dictionary;
+use d4_hts::{Alignment, DepthMode};
+use std::path::{Path, PathBuf};
+
+fn test_bam(name: &str) -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("../d4tools/test/create")
+        .join(name)
+}
+
+fn profile<P: AsRef<Path>>(paths: &[P], mode: DepthMode) -> Result<Dictionary, String> {
+    Dictionary::from_sample_bams(paths, |_, _| true, None, |_: &Alignment| true, mode)
+        .map_err(|err| err.to_string())
+}
+
+#[test]
+fn sample_bams_profile_all_the_files() {
+    let small = test_bam("from-bam/small.bam");
+    for mode in [DepthMode::ReadSpan, DepthMode::Fragment] {
+        // Most of the bases of small.bam aren't covered
+        let dict = profile(&[&small, &small], mode).unwrap();
+        assert_eq!(dict.first_value(), 0);
+    }
+}
+
+#[test]
+fn sample_bams_check_the_inputs() {
+    let small = test_bam("from-bam/small.bam");
+    assert!(profile::<&Path>(&[], DepthMode::ReadSpan).is_err());
+    assert!(profile(
+        &[small.clone(), test_bam("missing.bam")],
+        DepthMode::ReadSpan
+    )
+    .is_err());
+    let inconsistent = [small, test_bam("single-read/single_read.bam")];
+    match profile(&inconsistent, DepthMode::ReadSpan) {
+        Err(err) => assert!(err.contains("Inconsistent reference genome"), "{}", err),
+        Ok(_) => panic!("The reference genomes of the inputs are different"),
+    }
+}
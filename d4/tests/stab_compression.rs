@@ -0,0 +1,64 @@
+mod common;
+
+use d4::{stab::SecondaryTablePartWriter, D4FileWriter, Dictionary};
+use d4_framefile::Directory;
+use std::{fs::File, path::Path};
+
+/// The flag byte of the first block of the secondary table, which is 1 if the block is stored
+/// uncompressed
+fn first_block_flag(path: &Path) -> u8 {
+    let root = Directory::open_root(File::open(path).unwrap(), 8).unwrap();
+    let mut stream = root
+        .open_directory(".stab")
+        .unwrap()
+        .open_stream("0")
+        .unwrap();
+    let mut flag = [0];
+    stream.read(&mut flag).unwrap();
+    flag[0]
+}
+
+#[test]
+fn first_block_not_shrunk_by_compression() {
+    let dir = tempfile::tempdir().unwrap();
+    let mut uncompressed_blocks = 0;
+    // Deflate level 0 only stores the data, so the compressed block is always larger than the
+    // raw records, and one of the record counts fills up the first block
+    for count in 40..60 {
+        let path = dir.path().join(format!("{}.d4", count));
+        let intervals: Vec<_> = (0..count)
+            .map(|idx| {
+                (
+                    "chr1",
+                    idx * 10,
+                    idx * 10 + 5,
+                    (idx * 7919 % 1000) as i32 + 1,
+                )
+            })
+            .collect();
+        let mut builder = common::builder(&path, &[("chr1", 1000)]);
+        builder.set_dictionary(Dictionary::new_simple_range_dict(0, 1).unwrap());
+        let mut writer: D4FileWriter = builder.create().unwrap();
+        writer.enable_secondary_table_compression(0);
+        let mut partitions = writer.parallel_parts(None).unwrap();
+        for &(_, begin, end, value) in intervals.iter() {
+            partitions[0].1.encode_record(begin, end, value).unwrap();
+        }
+        for (_, mut s_part) in partitions {
+            s_part.finish().unwrap();
+        }
+        drop(writer);
+
+        uncompressed_blocks += (first_block_flag(&path) == 1) as usize;
+        let values = common::read_values(&path, "chr1", 0, 1000);
+        for (pos, value) in values.into_iter().enumerate() {
+            let pos = pos as u32;
+            let expected = intervals
+                .iter()
+                .find(|item| item.1 <= pos && pos < item.2)
+                .map_or(0, |item| item.3);
+            assert_eq!(value, expected, "{} records, chr1:{}", count, pos);
+        }
+    }
+    assert!(uncompressed_blocks > 0);
+}
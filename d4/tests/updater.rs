@@ -0,0 +1,200 @@
+mod common;
+
+use d4::{
+    ptab::BitArrayWriter,
+    ssio::D4TrackReader,
+    stab::{FloatRangeRecord, SecondaryTablePartReader, SparseArrayWriter},
+    Chrom, D4FileUpdater, D4FileWriter, Dictionary, ValueType,
+};
+use d4_framefile::Directory;
+use std::{fs::File, io::Read, path::Path};
+
+/// The metadata of the secondary table of a float track
+fn float_secondary_table_metadata(path: &Path) -> String {
+    let root = Directory::open_root(File::open(path).unwrap(), 8).unwrap();
+    let mut content = String::new();
+    root.open_directory(".xstab")
+        .unwrap()
+        .open_stream(".metadata")
+        .unwrap()
+        .read_to_string(&mut content)
+        .unwrap();
+    content
+}
+
+/// The offsets of the objects in the secondary table, an object is rewritten if its offset
+/// changes
+fn secondary_table_objects(path: &Path) -> Vec<(String, u64)> {
+    let root = Directory::open_root(File::open(path).unwrap(), 8).unwrap();
+    let mut objects: Vec<_> = root
+        .open_directory(".stab")
+        .unwrap()
+        .entries()
+        .into_iter()
+        .map(|entry| (entry.name, entry.primary_offset))
+        .collect();
+    objects.sort();
+    objects
+}
+
+#[test]
+fn set_value_rewrites_affected_partitions_only() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("test.d4");
+    let mut builder = common::builder(&path, &[("chr1", 1000), ("chr2", 1000)]);
+    let mut writer: D4FileWriter = builder.create().unwrap();
+    // The values out of the dictionary range are stored in the secondary table
+    writer
+        .write_intervals(vec![
+            ("chr1", 100, 200, 1000),
+            ("chr1", 500, 600, 3),
+            ("chr2", 10, 20, 500),
+        ])
+        .unwrap();
+    drop(writer);
+
+    let objects_before = secondary_table_objects(&path);
+
+    D4FileUpdater::new(&path)
+        .set_value("chr1", 150, 160, 5.0)
+        .set_value("chr1", 300, 400, 700.0)
+        .set_value("chr1", 550, 560, 0.0)
+        .update()
+        .unwrap();
+
+    let chr1 = common::read_values(&path, "chr1", 0, 1000);
+    for (pos, value) in chr1.into_iter().enumerate() {
+        let expected = match pos {
+            150..=159 => 5,
+            100..=199 => 1000,
+            300..=399 => 700,
+            550..=559 => 0,
+            500..=599 => 3,
+            _ => 0,
+        };
+        assert_eq!(value, expected, "chr1:{}", pos);
+    }
+    let chr2 = common::read_values(&path, "chr2", 0, 1000);
+    for (pos, value) in chr2.into_iter().enumerate() {
+        let expected = if (10..20).contains(&pos) { 500 } else { 0 };
+        assert_eq!(value, expected, "chr2:{}", pos);
+    }
+
+    // Only the partition of chr1 is rewritten
+    let objects_after = secondary_table_objects(&path);
+    assert_eq!(objects_before.len(), objects_after.len());
+    let rewritten = objects_before
+        .iter()
+        .zip(objects_after.iter())
+        .filter(|(before, after)| before != after)
+        .count();
+    assert_eq!(rewritten, 1);
+}
+
+#[test]
+fn append_chrom_keeps_existing_values() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("test.d4");
+    let mut builder = common::builder(&path, &[("chr1", 1000)]);
+    let mut writer: D4FileWriter = builder.create().unwrap();
+    writer
+        .write_intervals(vec![("chr1", 100, 200, 1000), ("chr1", 300, 400, 7)])
+        .unwrap();
+    drop(writer);
+
+    D4FileUpdater::new(&path)
+        .append_chrom(std::iter::once(Chrom {
+            name: "decoy".to_string(),
+            size: 500,
+        }))
+        .set_value("chr1", 0, 10, 2000.0)
+        .update()
+        .unwrap();
+
+    let reader = D4TrackReader::from_reader(File::open(&path).unwrap(), None).unwrap();
+    assert_eq!(reader.chrom_list().len(), 2);
+    let chr1 = common::read_values(&path, "chr1", 0, 1000);
+    for (pos, value) in chr1.into_iter().enumerate() {
+        let expected = match pos {
+            0..=9 => 2000,
+            100..=199 => 1000,
+            300..=399 => 7,
+            _ => 0,
+        };
+        assert_eq!(value, expected, "chr1:{}", pos);
+    }
+    assert!(common::read_values(&path, "decoy", 0, 500)
+        .iter()
+        .all(|&v| v == 0));
+}
+
+#[test]
+fn update_keeps_the_float_record_format() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("float.d4");
+    let mut builder = common::builder(&path, &[("chr1", 1000)]);
+    builder
+        // Zero isn't in the dictionary, so the new chromosome goes to the secondary table
+        .set_dictionary(Dictionary::from_float_list(vec![0.5, 1.25]).unwrap())
+        .set_value_type(ValueType::Float);
+    let mut writer: D4FileWriter<BitArrayWriter, SparseArrayWriter<FloatRangeRecord>> =
+        builder.create().unwrap();
+    writer
+        .write_intervals(vec![
+            ("chr1", 100, 200, ValueType::Float.encode(1.25)),
+            ("chr1", 300, 400, ValueType::Float.encode(0.5)),
+        ])
+        .unwrap();
+    drop(writer);
+
+    D4FileUpdater::new(&path)
+        .append_chrom(std::iter::once(Chrom {
+            name: "decoy".to_string(),
+            size: 500,
+        }))
+        .set_value("chr1", 150, 160, 2.75)
+        .set_value("chr1", 350, 360, 0.0)
+        .update()
+        .unwrap();
+
+    assert!(float_secondary_table_metadata(&path).contains("\"record_format\":\"float_range\""));
+    let mut reader = D4TrackReader::from_reader(File::open(&path).unwrap(), None).unwrap();
+    for item in reader.get_real_view("chr1", 0, 1000).unwrap() {
+        let (pos, value) = item.unwrap();
+        let expected = match pos {
+            150..=159 => 2.75,
+            100..=199 => 1.25,
+            350..=359 => 0.0,
+            300..=399 => 0.5,
+            _ => 0.0,
+        };
+        assert_eq!(value, expected, "chr1:{}", pos);
+    }
+    let mut reader = D4TrackReader::from_reader(File::open(&path).unwrap(), None).unwrap();
+    for item in reader.get_real_view("decoy", 0, 500).unwrap() {
+        assert_eq!(item.unwrap().1, 0.0);
+    }
+}
+
+#[test]
+fn updated_secondary_table_can_be_mapped() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("test.d4");
+    let mut builder = common::builder(&path, &[("chr1", 1000)]);
+    let mut writer: D4FileWriter = builder.create().unwrap();
+    writer
+        .write_intervals(vec![("chr1", 100, 200, 1000)])
+        .unwrap();
+    drop(writer);
+
+    D4FileUpdater::new(&path)
+        .set_value("chr1", 500, 600, 700.0)
+        .update()
+        .unwrap();
+
+    // The mapped reader only sees the data in the range of the secondary table
+    let mut reader: d4::D4TrackReader = d4::D4TrackReader::open(path.to_str().unwrap()).unwrap();
+    let parts = reader.split(None).unwrap();
+    let records: Vec<_> = parts[0].1.iter().collect();
+    assert_eq!(records, [(100, 200, 1000), (500, 600, 700)]);
+}
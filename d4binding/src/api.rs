@@ -261,21 +261,28 @@ pub extern "C" fn d4_file_load_metadata(
                 };
             }
 
-            match header.dictionary() {
+            let value_map = match header.dictionary() {
                 Dictionary::SimpleRange { low, high } => {
                     (*buf).dict_type = crate::c_api::d4_dict_type_t_D4_DICT_SIMPLE_RANGE;
                     (*buf).dict_data.simple_range.low = *low;
                     (*buf).dict_data.simple_range.high = *high;
+                    None
                 }
-                Dictionary::Dictionary { i2v_map, .. } => {
-                    (*buf).dict_type = crate::c_api::d4_dict_type_t_D4_DICT_VALUE_MAP;
-                    (*buf).dict_data.value_map.size = i2v_map.len() as u64;
-                    (*buf).dict_data.value_map.values =
-                        crate::c_api::malloc((std::mem::size_of::<i32>() * i2v_map.len()) as u64)
-                            as *mut _;
-                    for (idx, val) in i2v_map.iter().enumerate() {
-                        *(*buf).dict_data.value_map.values.offset(idx as isize) = *val;
-                    }
+                Dictionary::Dictionary { i2v_map, .. } => Some(i2v_map.clone()),
+                // The C API reads the raw values, so we expose the bit patterns of the floats
+                Dictionary::FloatDictionary { i2v_map, .. } => {
+                    Some(i2v_map.iter().map(|val| val.to_bits() as i32).collect())
+                }
+            };
+
+            if let Some(i2v_map) = value_map {
+                (*buf).dict_type = crate::c_api::d4_dict_type_t_D4_DICT_VALUE_MAP;
+                (*buf).dict_data.value_map.size = i2v_map.len() as u64;
+                (*buf).dict_data.value_map.values =
+                    crate::c_api::malloc((std::mem::size_of::<i32>() * i2v_map.len()) as u64)
+                        as *mut _;
+                for (idx, val) in i2v_map.iter().enumerate() {
+                    *(*buf).dict_data.value_map.values.offset(idx as isize) = *val;
                 }
             }
 
@@ -0,0 +1,240 @@
+use clap::{load_yaml, App};
+use d4::{
+    index::{D4IndexCollection, DataIndexRef, Sum as SumIndex},
+    task::{FloatSum, Histogram, Mean, SimpleTask, Task, ValueRange},
+    D4TrackReader, ValueType,
+};
+use d4tools::{make_windows, setup_thread_pool, write_window_track, AppResult};
+use log::info;
+use std::{
+    fs::File,
+    io::{BufWriter, Write},
+    iter::Once,
+    str::FromStr,
+};
+
+/// The sum index is used only if each window covers at least this many index bins, otherwise
+/// the per-base query of the window edges that are not aligned to the bins dominates
+const MIN_INDEX_BINS_PER_WINDOW: u32 = 8;
+
+#[derive(Clone, Copy, PartialEq)]
+enum BinStat {
+    Mean,
+    Sum,
+    Min,
+    Max,
+    Median,
+}
+
+impl FromStr for BinStat {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "mean" | "avg" => Ok(Self::Mean),
+            "sum" => Ok(Self::Sum),
+            "min" => Ok(Self::Min),
+            "max" => Ok(Self::Max),
+            "median" => Ok(Self::Median),
+            _ => Err(format!("Unsupported stat type: {}", s)),
+        }
+    }
+}
+
+impl BinStat {
+    /// Check if the summary of an integer track can be stored exactly in the integer value mode
+    fn is_exact_integer(&self) -> bool {
+        matches!(self, Self::Min | Self::Max | Self::Median)
+    }
+}
+
+/// Run the summary tasks on the windows, the results are in the same order as the windows.
+/// `summarize` returns `None` only if the median of a window is beyond the histogram range.
+fn run_windows<T, F>(reader: &mut D4TrackReader, tasks: Vec<T>, summarize: F) -> AppResult<Vec<f64>>
+where
+    T: Task<Once<i32>>,
+    F: Fn(&T::Output) -> Option<f64>,
+{
+    let mut results = Vec::with_capacity(tasks.len());
+    for part in T::create_task(reader, tasks)?.run().into_iter() {
+        let value = summarize(part.output).ok_or_else(|| {
+            format!(
+                "Unable to compute the median of {}:{}-{}, try a larger --max-bin",
+                part.chrom, part.begin, part.end
+            )
+        })?;
+        results.push((part.begin, value));
+    }
+    results.sort_by_key(|&(begin, _)| begin);
+    Ok(results.into_iter().map(|(_, value)| value).collect())
+}
+
+fn simple_tasks<T: SimpleTask>(windows: &[(String, u32, u32)]) -> Vec<T> {
+    windows
+        .iter()
+        .map(|(chr, begin, end)| T::new(chr, *begin, *end))
+        .collect()
+}
+
+/// Get the median of the histogram, `None` if the median isn't in the range of the histogram
+fn histogram_median((below, histogram, above): &(u32, Vec<u32>, u32)) -> Option<f64> {
+    let total = *below as u64 + histogram.iter().map(|&c| c as u64).sum::<u64>() + *above as u64;
+    let target = ((total as f64 * 0.5).round() as u64).max(1);
+    let mut current = *below as u64;
+    if current >= target {
+        return None;
+    }
+    for (value, &count) in histogram.iter().enumerate() {
+        current += count as u64;
+        if current >= target {
+            return Some(value as f64);
+        }
+    }
+    None
+}
+
+/// Load the sum index of the track if it's present and useful for the window size
+fn load_sum_index(
+    path: &str,
+    track: Option<&str>,
+    window_size: u32,
+) -> Option<DataIndexRef<SumIndex>> {
+    let index = D4IndexCollection::from_reader_with_track(File::open(path).ok()?, track)
+        .and_then(|collection| collection.load_data_index::<SumIndex>())
+        .ok()?;
+    let granularity = index.granularity();
+    if window_size.is_multiple_of(granularity)
+        || window_size >= granularity * MIN_INDEX_BINS_PER_WINDOW
+    {
+        Some(index)
+    } else {
+        None
+    }
+}
+
+pub fn entry_point(args: Vec<String>) -> AppResult<()> {
+    let yaml = load_yaml!("cli.yml");
+    let matches = App::from_yaml(yaml)
+        .version(d4tools::VERSION)
+        .get_matches_from(args);
+
+    setup_thread_pool(&matches)?;
+
+    let window_size: u32 = matches.value_of("window").unwrap().parse()?;
+    if window_size == 0 {
+        return Err("The window size should be positive".into());
+    }
+    let stat: BinStat = matches.value_of("stat").unwrap_or("mean").parse()?;
+    let max_bin: i32 = matches.value_of("max-bin").unwrap_or("1000").parse()?;
+
+    let input = matches.value_of("input").unwrap();
+    let (path, track) = match input.find(':') {
+        Some(pos) => (&input[..pos], Some(&input[pos + 1..])),
+        None => (input, None),
+    };
+
+    let mut reader: D4TrackReader = D4TrackReader::open(input)?;
+    let denominator = reader.header().get_denominator();
+    let integer_input = reader.header().value_type() == ValueType::Integer;
+    let chrom_list = reader.header().chrom_list().to_owned();
+
+    let sum_index = if matches!(stat, BinStat::Mean | BinStat::Sum)
+        && !matches.is_present("no-index")
+        && integer_input
+    {
+        load_sum_index(path, track, window_size)
+    } else {
+        None
+    };
+    let mut ssio_reader = match sum_index {
+        Some(_) => {
+            info!("Summarizing the windows with the sum index");
+            Some(d4::ssio::D4TrackReader::from_reader(
+                File::open(path)?,
+                track,
+            )?)
+        }
+        None => None,
+    };
+
+    let mut output = BufWriter::new(std::io::stdout());
+    let write_bed = !matches.is_present("output");
+    let mut chrom_values = Vec::with_capacity(chrom_list.len());
+
+    for chrom in chrom_list.iter() {
+        let windows = make_windows(&chrom.name, chrom.size as u32, window_size);
+        let values = if let (Some(index), Some(ssio_reader)) = (&sum_index, &mut ssio_reader) {
+            let mut values = Vec::with_capacity(windows.len());
+            for (chr, begin, end) in windows.iter() {
+                let query = index
+                    .query(chr, *begin, *end)
+                    .ok_or_else(|| format!("Chromosome {} is missing from the sum index", chr))?;
+                let result = query.get_result(ssio_reader)?;
+                values.push(match stat {
+                    BinStat::Mean => result.mean(end - begin),
+                    _ => result.sum(),
+                });
+            }
+            values
+        } else {
+            match stat {
+                BinStat::Mean => {
+                    run_windows(&mut reader, simple_tasks::<Mean>(&windows), |&v| Some(v))?
+                }
+                BinStat::Sum => {
+                    run_windows(&mut reader, simple_tasks::<FloatSum>(&windows), |&v| {
+                        Some(v)
+                    })?
+                }
+                BinStat::Min => run_windows(
+                    &mut reader,
+                    simple_tasks::<ValueRange>(&windows),
+                    |&(v, _)| Some(v),
+                )?,
+                BinStat::Max => run_windows(
+                    &mut reader,
+                    simple_tasks::<ValueRange>(&windows),
+                    |&(_, v)| Some(v),
+                )?,
+                BinStat::Median => {
+                    let tasks = windows
+                        .iter()
+                        .map(|(chr, begin, end)| {
+                            Histogram::with_bin_range(chr, *begin, *end, 0..max_bin)
+                        })
+                        .collect();
+                    run_windows(&mut reader, tasks, histogram_median)?
+                }
+            }
+        };
+
+        let values: Vec<_> = values.into_iter().map(|v| v / denominator).collect();
+        if write_bed {
+            for ((chr, begin, end), value) in windows.iter().zip(values) {
+                writeln!(output, "{}\t{}\t{}\t{}", chr, begin, end, value)?;
+            }
+        } else {
+            chrom_values.push(values);
+        }
+    }
+    output.flush()?;
+
+    if let Some(output_path) = matches.value_of("output") {
+        // The summaries of an integer track are stored with the same denominator if they are
+        // exact, otherwise the actual values are stored in the float value mode
+        let value_type = if integer_input && stat.is_exact_integer() {
+            ValueType::Integer
+        } else {
+            ValueType::Float
+        };
+        write_window_track(
+            output_path,
+            &chrom_list,
+            &chrom_values,
+            window_size,
+            value_type,
+            denominator,
+        )?;
+    }
+
+    Ok(())
+}
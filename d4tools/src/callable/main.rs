@@ -0,0 +1,66 @@
+use clap::{load_yaml, App};
+use d4::{
+    task::{Callable, Task},
+    D4TrackReader,
+};
+use d4tools::{setup_thread_pool, AppResult};
+use std::{
+    collections::HashMap,
+    io::{BufWriter, Write},
+};
+
+pub fn entry_point(args: Vec<String>) -> AppResult<()> {
+    let yaml = load_yaml!("cli.yml");
+    let matches = App::from_yaml(yaml)
+        .version(d4tools::VERSION)
+        .get_matches_from(args);
+
+    setup_thread_pool(&matches)?;
+
+    let min_value: Option<f64> = matches.value_of("min").map(str::parse).transpose()?;
+    let max_value: Option<f64> = matches.value_of("max").map(str::parse).transpose()?;
+    let min_length: u32 = matches.value_of("min-length").unwrap_or("0").parse()?;
+    let merge_gap: u32 = matches.value_of("merge-gap").unwrap_or("0").parse()?;
+
+    if min_value.is_none() && max_value.is_none() {
+        return Err("At least one of --min and --max should be specified".into());
+    }
+
+    let mut reader: D4TrackReader = D4TrackReader::open(matches.value_of("input").unwrap())?;
+    let denominator = reader.header().get_denominator();
+    let chroms: Vec<_> = reader
+        .chrom_regions()
+        .into_iter()
+        .map(|(name, _, size)| (name.to_string(), size))
+        .collect();
+
+    // Each chromosome is a single task, the task context splits it into partitions that are
+    // scanned in parallel and the intervals crossing the partition boundaries are stitched
+    // when the partition results are combined.
+    let tasks: Vec<_> = chroms
+        .iter()
+        .map(|(chr, size)| {
+            Callable::new(chr, 0, *size, min_value, max_value)
+                .with_min_length(min_length)
+                .with_max_gap(merge_gap)
+                .with_denominator(denominator)
+        })
+        .collect();
+    let results = Callable::create_task(&mut reader, tasks)?.run();
+    let mut results: HashMap<_, _> = results
+        .into_iter()
+        .map(|part| (part.chrom, part.output))
+        .collect();
+
+    let mut output = BufWriter::new(std::io::stdout());
+    for (chr, _) in chroms.iter() {
+        if let Some(intervals) = results.remove(chr.as_str()) {
+            for &(left, right) in intervals {
+                writeln!(output, "{}\t{}\t{}", chr, left, right)?;
+            }
+        }
+    }
+
+    output.flush()?;
+    Ok(())
+}
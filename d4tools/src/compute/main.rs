@@ -0,0 +1,277 @@
+use clap::{load_yaml, App, ArgMatches};
+use d4::{
+    ptab::PTablePartitionWriter,
+    stab::SecondaryTablePartWriter,
+    task::{Compute, ComputeOp, Task},
+    Chrom, D4FileBuilder, D4FileWriter, D4MatrixReader, D4TrackReader, Dictionary,
+};
+use d4tools::{make_dictionary, setup_thread_pool, AppResult};
+use log::info;
+use rayon::prelude::*;
+use std::collections::HashMap;
+
+/// The size of each region we compute and encode at once
+const PART_SIZE: usize = 1_000_000;
+
+/// The largest denominator we automatically choose for fractional results
+const MAX_AUTO_DENOMINATOR: f64 = 1000.0;
+
+/// Summary of the computed values, used to choose the dictionary and the denominator
+#[derive(Default)]
+struct ResultSummary {
+    min_value: f64,
+    max_value: f64,
+    denominator: f64,
+    non_zero_bases: usize,
+    total_bases: usize,
+}
+
+impl ResultSummary {
+    fn add(&mut self, left: u32, right: u32, value: f64) {
+        let size = (right - left) as usize;
+        self.total_bases += size;
+        if value == 0.0 {
+            return;
+        }
+        self.non_zero_bases += size;
+        self.min_value = self.min_value.min(value);
+        self.max_value = self.max_value.max(value);
+
+        let mut denominator = 1.0;
+        while denominator < MAX_AUTO_DENOMINATOR
+            && ((value * denominator).round() - (value * denominator)).abs() > 1e-10
+        {
+            denominator *= 10.0;
+        }
+        self.denominator = self.denominator.max(denominator);
+    }
+
+    fn choose_denominator(&self) -> Option<f64> {
+        let max_abs = self.max_value.abs().max(self.min_value.abs());
+        let mut denominator = self.denominator.max(1.0);
+        // Reduce the denominator if the max value is too large to fit in i32
+        while denominator > 1.0 && max_abs * denominator > i32::MAX as f64 {
+            denominator /= 10.0;
+        }
+        if denominator > 1.0 {
+            Some(denominator)
+        } else {
+            None
+        }
+    }
+
+    fn choose_dictionary(&self, denominator: f64) -> std::io::Result<(Dictionary, bool)> {
+        // Similar to the BigWig input, if most of the genome is zero, we use a 0-bit primary
+        // table and put everything in the compressed secondary table
+        if self.non_zero_bases * 10 < self.total_bases * 6 {
+            return Ok((Dictionary::new_simple_range_dict(0, 1)?, true));
+        }
+        let min_value = (self.min_value.min(0.0) * denominator).round() as i64;
+        let max_value = (self.max_value.max(0.0) * denominator).round() as i64;
+        let n_values = ((max_value - min_value + 1) as usize).next_power_of_two();
+        if n_values <= 256 {
+            return Ok((
+                Dictionary::new_simple_range_dict(
+                    min_value as i32,
+                    min_value as i32 + n_values as i32,
+                )?,
+                false,
+            ));
+        }
+        let low = min_value.max(-32) as i32;
+        Ok((Dictionary::new_simple_range_dict(low, low + 64)?, false))
+    }
+}
+
+fn open_inputs(matches: &ArgMatches) -> AppResult<Vec<D4TrackReader>> {
+    let mut readers = vec![];
+    for input in matches.values_of("input-files").unwrap() {
+        if input.contains(':') {
+            readers.push(D4TrackReader::open(input)?);
+        } else {
+            let tracks = D4TrackReader::open_tracks(input, |_| true)?;
+            if tracks.is_empty() {
+                return Err(format!("No data track found in {}", input).into());
+            }
+            readers.extend(tracks);
+        }
+    }
+    Ok(readers)
+}
+
+/// Run the compute task on a batch of regions, the results are in the same order as the regions
+fn compute_regions(
+    reader: &mut D4MatrixReader<d4::stab::SparseArrayReader<d4::stab::RangeRecord>>,
+    regions: &[(String, u32, u32)],
+    op: ComputeOp,
+    denominators: &[f64],
+) -> AppResult<Vec<Vec<(u32, u32, f64)>>> {
+    let tasks = regions
+        .iter()
+        .map(|(chr, begin, end)| Compute::new(chr, *begin, *end, op, denominators.to_vec()))
+        .collect::<Result<Vec<_>, _>>()?;
+    let output = Compute::create_task(reader, tasks)?.run();
+    let mut result_map: HashMap<_, _> = output
+        .into_iter()
+        .map(|part| ((part.chrom.to_string(), part.begin), part.output.clone()))
+        .collect();
+    Ok(regions
+        .iter()
+        .map(|(chr, begin, _)| {
+            result_map
+                .remove(&(chr.clone(), *begin))
+                .unwrap_or_default()
+        })
+        .collect())
+}
+
+pub fn entry_point(args: Vec<String>) -> AppResult<()> {
+    let yaml = load_yaml!("cli.yml");
+    let matches = App::from_yaml(yaml)
+        .version(d4tools::VERSION)
+        .get_matches_from(args);
+
+    setup_thread_pool(&matches)?;
+
+    let op: ComputeOp = matches.value_of("op").unwrap().parse()?;
+    let output = matches.value_of("output-file").unwrap();
+
+    let readers = open_inputs(&matches)?;
+    if op.arity().is_some_and(|arity| arity != readers.len()) {
+        return Err(format!(
+            "Operation {} requires exactly {} tracks, but {} tracks are given",
+            matches.value_of("op").unwrap(),
+            op.arity().unwrap(),
+            readers.len()
+        )
+        .into());
+    }
+
+    let denominators: Vec<_> = readers
+        .iter()
+        .map(|reader| reader.header().get_denominator())
+        .collect();
+    let mut reader = D4MatrixReader::new(readers)?;
+    let chroms: Vec<_> = reader
+        .chrom_regions()
+        .into_iter()
+        .map(|(name, _, size)| Chrom {
+            name: name.to_string(),
+            size: size as usize,
+        })
+        .collect();
+
+    let batch_size = rayon::current_num_threads() * 4;
+
+    let user_denominator: Option<f64> = matches
+        .value_of("denominator")
+        .map(|v| v.parse())
+        .transpose()?;
+
+    let (denominator, dictionary, compression) =
+        if user_denominator.is_some() && matches.is_present("dict-range") {
+            (
+                user_denominator,
+                make_dictionary(matches.value_of("dict-range"), None)?,
+                false,
+            )
+        } else {
+            // The first pass, scan the result to determine the denominator and dictionary
+            let mut summary = ResultSummary::default();
+            let regions: Vec<_> = chroms
+                .iter()
+                .flat_map(|chrom| {
+                    (0..chrom.size)
+                        .step_by(PART_SIZE)
+                        .map(|begin| {
+                            (
+                                chrom.name.clone(),
+                                begin as u32,
+                                (begin + PART_SIZE).min(chrom.size) as u32,
+                            )
+                        })
+                        .collect::<Vec<_>>()
+                })
+                .collect();
+            for batch in regions.chunks(batch_size) {
+                for intervals in compute_regions(&mut reader, batch, op, &denominators)? {
+                    for (left, right, value) in intervals {
+                        summary.add(left, right, value);
+                    }
+                }
+            }
+            let denominator = user_denominator.or_else(|| summary.choose_denominator());
+            let (dictionary, compression) = if matches.is_present("dict-range") {
+                (
+                    make_dictionary(matches.value_of("dict-range"), None)?,
+                    false,
+                )
+            } else {
+                summary.choose_dictionary(denominator.unwrap_or(1.0))?
+            };
+            (denominator, dictionary, compression)
+        };
+
+    info!(
+        "Output denominator: {:?}, dictionary bit width: {}",
+        denominator,
+        dictionary.bit_width()
+    );
+
+    let mut builder = D4FileBuilder::new(output);
+    builder.append_chrom(chroms.into_iter());
+    builder.set_dictionary(dictionary);
+    if let Some(denominator) = denominator {
+        builder.set_denominator(denominator);
+    }
+    let default_pt_value = if builder.dictionary().bit_width() == 0 {
+        Some(builder.dictionary().first_value())
+    } else {
+        None
+    };
+
+    let mut writer: D4FileWriter = builder.create()?;
+    if compression || matches.is_present("deflate") {
+        writer.enable_secondary_table_compression(5);
+    }
+
+    let mut partitions = writer.parallel_parts(Some(PART_SIZE))?;
+    let denominator = denominator.unwrap_or(1.0);
+
+    for batch in partitions.chunks_mut(batch_size) {
+        let regions: Vec<_> = batch
+            .iter()
+            .map(|(pt, _)| {
+                let (chr, begin, end) = pt.region();
+                (chr.to_string(), begin, end)
+            })
+            .collect();
+        let results = compute_regions(&mut reader, &regions, op, &denominators)?;
+        batch.par_iter_mut().zip(results).try_for_each(
+            |((pt, st), intervals)| -> std::io::Result<()> {
+                let mut encoder = pt.make_encoder();
+                for (left, right, value) in intervals {
+                    let value = (value * denominator).round() as i32;
+                    if let Some(default_value) = default_pt_value {
+                        if value != default_value {
+                            st.encode_record(left, right, value)?;
+                        }
+                    } else {
+                        for pos in left..right {
+                            if !encoder.encode(pos as usize, value) {
+                                st.encode(pos, value)?;
+                            }
+                        }
+                    }
+                }
+                st.flush()
+            },
+        )?;
+    }
+
+    for (_, mut st) in partitions {
+        st.finish()?;
+    }
+
+    Ok(())
+}
@@ -1,36 +1,267 @@
 use clap::{load_yaml, App, ArgMatches};
-use d4::ptab::PTablePartitionWriter;
-use d4::stab::SecondaryTablePartWriter;
-use d4::{Chrom, D4FileBuilder, D4FileWriter, Dictionary};
-use d4_hts::{BamFile, DepthIter, Alignment};
-use d4tools::{make_dictionary, parse_bed_file, parse_genome_file, setup_thread_pool, InputType};
+use d4::ptab::{
+    BitArrayWriter, CompressedBitArrayWriter, Encoder, PTablePartitionWriter, PrimaryTableWriter,
+};
+use d4::stab::{
+    CompressionMethod, FloatRangeRecord, RangeRecord, RunLengthRecord, SecondaryTablePartWriter,
+    SecondaryTableWriter, SparseArrayWriter,
+};
+use d4::{Chrom, D4FileBuilder, D4FileMerger, D4FileWriter, Dictionary, ValueType};
+use d4_hts::{BamFile, DepthIter, DepthMode, Alignment, AlignmentReader};
+use d4tools::{
+    make_dictionary, parse_bed_file, parse_genome_file, parse_multi_column_bed_file,
+    setup_thread_pool, stop_at_error, InputType,
+};
 use log::{info, warn};
 use rayon::prelude::*;
 use regex::Regex;
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{BufRead, BufReader};
 use std::path::{Path, PathBuf};
 
 type DynErr = Box<dyn std::error::Error>;
 
 struct CreateAppCtx {
     input_path: PathBuf,
+    /// The alignment inputs and their track tags, when multiple samples are profiled at once
+    sample_inputs: Vec<(PathBuf, String)>,
+    output_path: PathBuf,
     input_type: InputType,
+    /// The track tags of a multi-column bedGraph input, empty if the input has a single value
+    /// column
+    track_names: Vec<String>,
+    /// The records of a multi-column bedGraph input
+    multi_column_input: Option<MultiColumnInput>,
+    /// The policy to combine the overlapping bedGraph records, which is set if the input may be
+    /// unsorted. In this case the records are buffered and sorted before encoding.
+    overlap_policy: Option<OverlapPolicy>,
     min_mq: u8,
     bam_flags: Option<u16>,
     inclusive_flag: u16,
     exclusive_flag: u16,
+    strand: Option<Strand>,
+    split_strand: bool,
+    depth_mode: DepthMode,
     chr_filter: Regex,
     compression: bool,
     denominator: Option<f64>,
-    compression_level: u32,
+    /// Store the values as floats rather than fixed-point integers
+    float_value: bool,
+    /// Store the secondary table with the 32-bit run length records
+    run_length: bool,
+    compression_method: CompressionMethod,
+    /// The compression method of the primary table blocks, the primary table isn't compressed
+    /// if it's not set
+    primary_compression: Option<CompressionMethod>,
     builder: D4FileBuilder,
 }
 
+#[derive(Clone, Copy, PartialEq)]
+enum Strand {
+    Forward,
+    Reverse,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum OverlapPolicy {
+    Sum,
+    Max,
+    Last,
+    Error,
+}
+
+/// The records of a multi-column bedGraph input. As the tracks of a container are written one
+/// after another, the input is loaded once and shared by the profiling and all the tracks rather
+/// than being parsed again for each of them.
+struct MultiColumnInput {
+    chroms: Vec<String>,
+    /// The chromosome index, begin and end of each record
+    intervals: Vec<(usize, u32, u32)>,
+    /// The values of each column
+    columns: Vec<Vec<f64>>,
+}
+
+impl MultiColumnInput {
+    fn load(
+        num_columns: usize,
+        records: impl Iterator<Item = std::io::Result<(String, u32, u32, Vec<f64>)>>,
+    ) -> std::io::Result<Self> {
+        let mut ret = Self {
+            chroms: vec![],
+            intervals: vec![],
+            columns: vec![vec![]; num_columns],
+        };
+        for record in records {
+            let (chr, begin, end, values) = record?;
+            let chrom = match ret.chroms.iter().rposition(|name| *name == chr) {
+                Some(idx) => idx,
+                None => {
+                    ret.chroms.push(chr);
+                    ret.chroms.len() - 1
+                }
+            };
+            ret.intervals.push((chrom, begin, end));
+            for (column, value) in ret.columns.iter_mut().zip(values) {
+                column.push(value);
+            }
+        }
+        Ok(ret)
+    }
+    /// The records with the values of the `idx`-th column
+    fn column(&self, idx: usize) -> impl Iterator<Item = (&str, u32, u32, f64)> + '_ {
+        self.intervals
+            .iter()
+            .zip(&self.columns[idx])
+            .map(|(&(chrom, begin, end), &value)| (self.chroms[chrom].as_str(), begin, end, value))
+    }
+}
+
+/// Check that a bedGraph/WIG record is a valid interval of the genome, `size` is the size of the
+/// chromosome if it's in the genome and `seq` is the index of the record in the input
+fn check_record(
+    seq: usize,
+    chr: &str,
+    begin: u32,
+    end: u32,
+    size: Option<usize>,
+) -> Result<(), DynErr> {
+    let size = size.ok_or_else(|| {
+        format!(
+            "Record {} ({}:{}-{}) is on a chromosome that is not in the genome file",
+            seq + 1,
+            chr,
+            begin,
+            end
+        )
+    })?;
+    if begin >= end || end as usize > size {
+        return Err(format!(
+            "Record {} ({}:{}-{}) is not a valid interval of {}, whose size is {}",
+            seq + 1,
+            chr,
+            begin,
+            end,
+            chr,
+            size
+        )
+        .into());
+    }
+    Ok(())
+}
+
+/// Check a record of the sorted mode, see `check_record`. The records are encoded as they come, so
+/// a record can't start before the end of the previous record on the same chromosome, whose end
+/// is kept in `last_end`.
+fn check_sorted_record(
+    seq: usize,
+    chr: &str,
+    begin: u32,
+    end: u32,
+    chrom_sizes: &HashMap<String, usize>,
+    last_end: &mut HashMap<String, u32>,
+) -> Result<(), DynErr> {
+    check_record(seq, chr, begin, end, chrom_sizes.get(chr).copied())?;
+    match last_end.get_mut(chr) {
+        Some(last_end) if begin < *last_end => Err(format!(
+            "Record {} ({}:{}-{}) starts before the end of the previous record on {} ({}), use --unsorted if the input isn't sorted or has overlapping records",
+            seq + 1,
+            chr,
+            begin,
+            end,
+            chr,
+            last_end
+        )
+        .into()),
+        Some(last_end) => {
+            *last_end = end;
+            Ok(())
+        }
+        None => {
+            last_end.insert(chr.to_string(), end);
+            Ok(())
+        }
+    }
+}
+
+/// A buffered bedGraph record of the unsorted mode, `seq` is the index of the record in the input
+struct UnsortedRecord {
+    begin: u32,
+    end: u32,
+    seq: usize,
+    value: f64,
+}
+
+/// Sort the records of a chromosome and combine the values of the overlapping records with the
+/// policy, the resulting non-overlapping intervals are appended to `output`
+fn resolve_overlaps(
+    chrom: &str,
+    mut records: Vec<UnsortedRecord>,
+    policy: OverlapPolicy,
+    output: &mut Vec<(String, u32, u32, f64)>,
+) -> Result<(), DynErr> {
+    records.sort_by_key(|record| (record.begin, record.seq));
+    let mut breakpoints: Vec<u32> = records
+        .iter()
+        .flat_map(|record| [record.begin, record.end])
+        .collect();
+    breakpoints.sort_unstable();
+    breakpoints.dedup();
+
+    let mut next = 0;
+    let mut active: Vec<&UnsortedRecord> = vec![];
+    for segment in breakpoints.windows(2) {
+        let (left, right) = (segment[0], segment[1]);
+        while next < records.len() && records[next].begin <= left {
+            active.push(&records[next]);
+            next += 1;
+        }
+        active.retain(|record| record.end > left);
+        let value = match (policy, active.as_slice()) {
+            (_, []) => continue,
+            (_, [record]) => record.value,
+            (OverlapPolicy::Error, [first, second, ..]) => {
+                return Err(format!(
+                    "Record {} ({}:{}-{}) overlaps with record {} ({}:{}-{}), use --overlap to combine them",
+                    first.seq + 1,
+                    chrom,
+                    first.begin,
+                    first.end,
+                    second.seq + 1,
+                    chrom,
+                    second.begin,
+                    second.end
+                )
+                .into())
+            }
+            (OverlapPolicy::Sum, active) => active.iter().map(|record| record.value).sum(),
+            (OverlapPolicy::Max, active) => active
+                .iter()
+                .map(|record| record.value)
+                .fold(f64::NEG_INFINITY, f64::max),
+            (OverlapPolicy::Last, active) => {
+                active.iter().max_by_key(|record| record.seq).unwrap().value
+            }
+        };
+        match output.last_mut() {
+            Some((last_chrom, _, end, last_value))
+                if last_chrom == chrom && *end == left && *last_value == value =>
+            {
+                *end = right
+            }
+            _ => output.push((chrom.to_string(), left, right, value)),
+        }
+    }
+    Ok(())
+}
+
 #[derive(Clone, Copy)]
 struct BamFilter {
     min_mq: u8,
     bam_flags: Option<u16>,
     inclusive_flag: u16,
     exclusive_flag: u16,
+    strand: Option<Strand>,
 }
 
 impl BamFilter {
@@ -40,17 +271,92 @@ impl BamFilter {
         let exact_match = self.bam_flags.map_or(true, |expected| expected == flag);
         let inclusive_match = (self.inclusive_flag & flag) == self.inclusive_flag;
         let exclusive_match = (self.exclusive_flag & flag) == 0;
-        quality && exact_match && inclusive_match && exclusive_match
+        let strand_match = self
+            .strand
+            .is_none_or(|strand| (strand == Strand::Reverse) == read.is_reverse_strand());
+        quality && exact_match && inclusive_match && exclusive_match && strand_match
     }
 }
 
 impl CreateAppCtx {
     fn get_bam_filter(&self) -> BamFilter {
-        BamFilter { min_mq: self.min_mq, bam_flags: self.bam_flags, inclusive_flag: self.inclusive_flag, exclusive_flag: self.exclusive_flag }
+        BamFilter { min_mq: self.min_mq, bam_flags: self.bam_flags, inclusive_flag: self.inclusive_flag, exclusive_flag: self.exclusive_flag, strand: self.strand }
+    }
+    /// Create a new file builder for the given path, which shares the dictionary, denominator
+    /// and chromosome filter with the main builder
+    fn builder_for_path(&self, path: &Path) -> D4FileBuilder {
+        let mut builder = D4FileBuilder::new(path);
+        let chr_filter = self.chr_filter.clone();
+        builder.set_filter(move |chr, _size| chr_filter.is_match(chr));
+        builder.set_dictionary(self.builder.dictionary().clone());
+        if let Some(denominator) = self.denominator {
+            builder.set_denominator(denominator);
+        }
+        builder
+    }
+    /// Set the compression methods of the tables of a newly created writer
+    fn configure_writer<PT: PrimaryTableWriter, ST: SecondaryTableWriter>(
+        &self,
+        writer: &mut D4FileWriter<PT, ST>,
+    ) -> std::io::Result<()> {
+        if self.compression {
+            writer.set_secondary_table_compression(self.compression_method);
+        }
+        if let Some(method) = self.primary_compression {
+            writer.set_primary_table_compression(method)?;
+        }
+        Ok(())
     }
     fn new(matches: &ArgMatches) -> Result<Self, DynErr> {
-        let input_path: &Path = matches.value_of("input-file").unwrap().as_ref();
+        let sample_inputs = parse_sample_inputs(matches)?;
+        let (input_path, output_arg): (&Path, _) = if sample_inputs.is_empty() {
+            (
+                matches.value_of("input-file").unwrap().as_ref(),
+                matches.value_of("output-file"),
+            )
+        } else {
+            // In multi-sample mode, the only positional argument is the output file
+            let output = matches
+                .value_of("output-file")
+                .or_else(|| matches.value_of("input-file"))
+                .ok_or("Output path is required when multiple inputs are specified")?;
+            (sample_inputs[0].0.as_path(), Some(output))
+        };
         let input_type = InputType::detect(input_path);
+        if !sample_inputs.is_empty() && !matches!(input_type, InputType::Alignment) {
+            return Err("Only BAM/CRAM inputs are supported in multi-sample mode".into());
+        }
+        let (track_names, multi_column_input) = match input_type {
+            InputType::BedGraph if input_path != Path::new("-") => {
+                let (names, records) = parse_multi_column_bed_file(input_path)?;
+                if names.len() > 1 {
+                    let input = MultiColumnInput::load(names.len(), records)?;
+                    (names, Some(input))
+                } else {
+                    (vec![], None)
+                }
+            }
+            _ => (vec![], None),
+        };
+        let overlap_policy = match matches.value_of("overlap") {
+            Some("sum") => Some(OverlapPolicy::Sum),
+            Some("max") => Some(OverlapPolicy::Max),
+            Some("last") => Some(OverlapPolicy::Last),
+            Some(_) => Some(OverlapPolicy::Error),
+            None if matches.is_present("unsorted") => Some(OverlapPolicy::Error),
+            None => None,
+        };
+        if overlap_policy.is_some() && !matches!(input_type, InputType::BedGraph | InputType::Wig) {
+            return Err("The unsorted mode is only supported by bedGraph/WIG inputs".into());
+        }
+        let float_value = matches.is_present("float");
+        if float_value && matches!(input_type, InputType::Alignment) {
+            return Err("The float value mode is only supported by BigWig/bedGraph inputs".into());
+        }
+        let run_length = matches.is_present("run-length");
+        if run_length && matches!(input_type, InputType::Alignment) {
+            return Err("The run length records are only supported by BigWig/bedGraph inputs".into());
+        }
 
         let min_mq = matches.value_of("min-mqual").map_or(60, |v| {
             v.parse().expect("Invalid minimal mapping quality option")
@@ -78,9 +384,36 @@ impl CreateAppCtx {
             }
         }
         
-        let output_path = matches.value_of("output-file").map_or_else(
+        let (strand, split_strand) = match matches.value_of("strand") {
+            None => (None, false),
+            Some("forward") | Some("+") => (Some(Strand::Forward), false),
+            Some("reverse") | Some("-") => (Some(Strand::Reverse), false),
+            Some(_) => (None, true),
+        };
+
+        let depth_mode = if matches.is_present("fragment") {
+            DepthMode::Fragment
+        } else if matches.is_present("exclude-deletion") || matches.is_present("exclude-ref-skip") {
+            DepthMode::ExcludeGaps {
+                deletion: matches.is_present("exclude-deletion"),
+                ref_skip: matches.is_present("exclude-ref-skip"),
+            }
+        } else {
+            DepthMode::ReadSpan
+        };
+
+        if input_path == Path::new("-") && output_arg.is_none() {
+            return Err("Output path is required when reading from the standard input".into());
+        }
+        let output_path = output_arg.map_or_else(
             || {
                 let mut ret = input_path.to_owned();
+                if matches!(
+                    ret.extension().and_then(|ext| ext.to_str()),
+                    Some("gz") | Some("bgz")
+                ) {
+                    ret.set_extension("");
+                }
                 ret.set_extension("d4");
                 ret
             },
@@ -89,76 +422,169 @@ impl CreateAppCtx {
         let denominator: Option<f64> = matches
             .value_of("denominator")
             .map(|what| what.parse().unwrap());
-        let mut builder = d4::D4FileBuilder::new(output_path);
+        let mut builder = d4::D4FileBuilder::new(&output_path);
 
         let chr_filter = Regex::new(matches.value_of("filter").unwrap_or(".*"))?;
 
         builder.set_filter(move |chr, _size| chr_filter.is_match(chr));
 
-        let compression = matches.is_present("deflate") || matches.is_present("sparse");
-        let compression_level: u32 = matches.value_of("deflate-level").unwrap_or("5").parse()?;
+        let compression = matches.is_present("deflate")
+            || matches.is_present("sparse")
+            || matches.is_present("compression");
+        let level = matches
+            .value_of("compression-level")
+            .or_else(|| matches.value_of("deflate-level"));
+        let parse_method = |method: &str| -> Result<_, DynErr> {
+            Ok(match method {
+                "zstd" => CompressionMethod::Zstd(level.unwrap_or("3").parse()?),
+                "lz4" => CompressionMethod::Lz4,
+                _ => CompressionMethod::Deflate(level.unwrap_or("5").parse()?),
+            })
+        };
+        let compression_method = parse_method(matches.value_of("compression").unwrap_or("deflate"))?;
+        let primary_compression = matches
+            .value_of("primary-compression")
+            .map(parse_method)
+            .transpose()?;
+        // The level is shared by both tables, so it's only rejected when it isn't used at all
+        let leveled = |method: &CompressionMethod| !matches!(method, CompressionMethod::Lz4);
+        if matches.is_present("compression-level")
+            && !(compression && leveled(&compression_method))
+            && !primary_compression.as_ref().is_some_and(leveled)
+        {
+            return Err("--compression-level can't be used with lz4, which has no levels".into());
+        }
 
         Ok(Self {
             input_path: input_path.to_owned(),
+            sample_inputs,
+            output_path,
             input_type,
+            track_names,
+            multi_column_input,
+            overlap_policy,
             min_mq,
             bam_flags,
             inclusive_flag,
             exclusive_flag,
+            strand,
+            split_strand,
+            depth_mode,
             chr_filter: Regex::new(matches.value_of("filter").unwrap_or(".*"))?,
             compression,
-            compression_level,
+            compression_method,
+            primary_compression,
             denominator,
+            float_value,
+            run_length,
             builder,
         })
     }
+    /// Check if the input is read from the standard input, which can only be read once, so the
+    /// input isn't profiled before encoding
+    fn is_streamed(&self) -> bool {
+        self.input_path == Path::new("-")
+    }
     fn auto_dict_for_bam(&mut self, matches: &ArgMatches) -> Result<(), DynErr> {
         let filter = self.get_bam_filter();
-        let dict = Dictionary::from_sample_bam(
-            self.input_path.as_path(),
+        let inputs: Vec<&Path> = if self.sample_inputs.is_empty() {
+            vec![self.input_path.as_path()]
+        } else {
+            self.sample_inputs.iter().map(|(path, _)| path.as_path()).collect()
+        };
+        let dict = Dictionary::from_sample_bams(
+            &inputs,
             |chr, _size| self.chr_filter.is_match(chr),
             matches.value_of("ref"),
             move |r| filter.filter_alignment(r),
+            self.depth_mode,
         )?;
         self.builder.set_dictionary(dict);
         Ok(())
     }
-    fn auto_dict_for_bw(&mut self) -> Result<(), DynErr> {
-        let fp = std::fs::metadata(self.input_path.as_path())?;
-        let bw_file = d4_bigwig::BigWigFile::open(self.input_path.as_path())?;
-
-        let genome_size: u64 = bw_file.chroms().into_iter().map(|(_, sz)| sz as u64).sum();
-
-        let file_size = fp.len();
-
-        if file_size < genome_size / 8 {
-            self.builder
-                .set_dictionary(Dictionary::new_simple_range_dict(0, 1)?);
+    /// The integer value that is used to profile the dictionary, this is the same as what
+    /// `encode_value` returns, but without the warnings
+    fn profiling_value(&self, value: f64) -> i32 {
+        match self.denominator {
+            Some(denominator) => (value * denominator).round() as i32,
+            None => value as i32,
+        }
+    }
+    fn set_auto_dict(&mut self, dict: Dictionary) {
+        // When everything goes to the secondary table, it's worth compressing it
+        if dict.bit_width() == 0 {
             self.compression = true;
         }
-
+        self.builder.set_dictionary(dict);
+    }
+    fn auto_dict_for_bw(&mut self) -> Result<(), DynErr> {
+        let bw_file = d4_bigwig::BigWigFile::open(self.input_path.as_path())?;
+        let chroms: Vec<_> = bw_file
+            .chroms()
+            .into_iter()
+            .filter(|(chr, _)| self.chr_filter.is_match(chr))
+            .collect();
+        let this = &*self;
+        let intervals = chroms.iter().flat_map(|(chr, size)| {
+            bw_file
+                .query_range(chr, 0, *size as u32)
+                .into_iter()
+                .flatten()
+                .map(move |interval| {
+                    let value = this.profiling_value(interval.value as f64);
+                    (chr, interval.begin, interval.end, value)
+                })
+        });
+        let dict = Dictionary::from_sample_intervals(&chroms, intervals)?;
+        self.set_auto_dict(dict);
         Ok(())
     }
     fn auto_dict_for_bedgraph(&mut self, matches: &ArgMatches) -> Result<(), DynErr> {
-        let genomes = parse_genome_file(
+        let chroms: Vec<_> = parse_genome_file(
             matches
                 .value_of("genome")
                 .expect("Genome file is required for text file format"),
-        )?;
-        let genome_size: u64 = genomes.into_iter().map(|chr| chr.size as u64).sum();
-
-        let fp = std::fs::metadata(self.input_path.as_path())?;
-        let file_size = fp.len();
-
-        if file_size < genome_size {
-            self.builder
-                .set_dictionary(Dictionary::new_simple_range_dict(0, 1)?);
-            self.compression = true;
-        }
-
+        )?
+        .into_iter()
+        .map(|chr| (chr.name, chr.size))
+        .collect();
+        // The tracks of a multi-column input share the dictionary profiled on the first column
+        let dict = if let Some(input) = self.multi_column_input.as_ref() {
+            let intervals = input
+                .column(0)
+                .map(|(chr, begin, end, value)| (chr, begin, end, self.profiling_value(value)));
+            Dictionary::from_sample_intervals(&chroms, intervals)?
+        } else {
+            let mut error = None;
+            let intervals = stop_at_error(parse_bed_file(self.input_path.as_path())?, &mut error)
+                .map(|(chr, begin, end, value)| (chr, begin, end, self.profiling_value(value)));
+            let dict = Dictionary::from_sample_intervals(&chroms, intervals)?;
+            if let Some(err) = error {
+                return Err(err.into());
+            }
+            dict
+        };
+        self.set_auto_dict(dict);
         Ok(())
     }
     fn configure_dict(&mut self, matches: &ArgMatches) -> Result<(), DynErr> {
+        if self.float_value {
+            // The integer dictionaries don't make sense for floats, so unless a float dictionary
+            // is given, all the non-zero values go to the secondary table
+            let dict = if let Some(path) = matches.value_of("dict-file") {
+                let mut values = vec![];
+                for line in BufReader::new(File::open(path)?).lines() {
+                    values.push(line?.trim().parse::<f32>()?);
+                }
+                Dictionary::from_float_list(values)?
+            } else {
+                self.compression = true;
+                Dictionary::new_simple_range_dict(0, 1)?
+            };
+            self.builder.set_dictionary(dict);
+            return Ok(());
+        }
+
         self.builder.set_dictionary(make_dictionary(
             matches.value_of("dict-range"),
             matches.value_of("dict-file"),
@@ -173,11 +599,13 @@ impl CreateAppCtx {
             && !matches.is_present("dict-file"))
             || matches.is_present("dict-auto");
 
-        if auto_dict_detection {
+        if auto_dict_detection && self.is_streamed() {
+            info!("The input is streamed, the default dictionary is used without profiling");
+        } else if auto_dict_detection {
             match self.input_type {
                 InputType::Alignment => self.auto_dict_for_bam(matches)?,
                 InputType::BiwWig => self.auto_dict_for_bw()?,
-                InputType::BedGraph => self.auto_dict_for_bedgraph(matches)?,
+                InputType::BedGraph | InputType::Wig => self.auto_dict_for_bedgraph(matches)?,
                 _ => {
                     panic!("Unsupported input type")
                 }
@@ -187,29 +615,17 @@ impl CreateAppCtx {
         Ok(())
     }
 
-    fn detect_default_denominator_for_bigwig(
-        &mut self,
-        matches: &ArgMatches,
-    ) -> Result<(), DynErr> {
-        let auto_dict_detection = (!matches.is_present("dict_range")
-            && !matches.is_present("dict-file"))
-            || matches.is_present("dict-auto");
-
+    fn detect_default_denominator_for_bigwig(&mut self) -> Result<(), DynErr> {
         let bw_file = d4_bigwig::BigWigFile::open(self.input_path.as_path())?;
         let mut purposed_denominator = 1.0f64;
-        let mut num_of_intervals = 0;
-        let mut genome_size = 0;
         let mut max_value = 0.0f64;
         for (chr_name, chr_size) in bw_file.chroms() {
-            genome_size += chr_size;
             if let Some(result) = bw_file.query_range(&chr_name, 0, chr_size as u32) {
                 for bw_interval in result {
                     let value = bw_interval.value as f64;
                     if value.abs() < 1e-10 {
                         continue;
                     }
-                    num_of_intervals += 1;
-
                     let mut denominator = 1.0;
 
                     while ((value * denominator).round() - (value * denominator)).abs() > 1e-10 {
@@ -227,11 +643,6 @@ impl CreateAppCtx {
             purposed_denominator /= 10.0;
         }
 
-        if auto_dict_detection && num_of_intervals * 10 < genome_size * 6 {
-            self.builder
-                .set_dictionary(Dictionary::new_simple_range_dict(0, 1)?);
-            self.compression = true;
-        }
         if purposed_denominator != 1.0 {
             self.builder.set_denominator(purposed_denominator);
             self.denominator = Some(purposed_denominator);
@@ -240,11 +651,18 @@ impl CreateAppCtx {
     }
 
     fn detect_default_denominator_for_bedgraph(&mut self) -> Result<(), DynErr> {
-        let input = parse_bed_file(self.input_path.as_path())?;
+        let mut error = None;
+        let input: Box<dyn Iterator<Item = f64> + '_> = match self.multi_column_input.as_ref() {
+            Some(input) => Box::new(input.columns.iter().flatten().copied()),
+            None => Box::new(
+                stop_at_error(parse_bed_file(self.input_path.as_path())?, &mut error)
+                    .map(|(_, _, _, value)| value),
+            ),
+        };
         let mut purposed_denominator = 1.0f64;
         let mut max_value = 0.0f64;
 
-        for (_, _, _, value) in input {
+        for value in input {
             if value.abs() < 1e-10 {
                 continue;
             }
@@ -257,6 +675,9 @@ impl CreateAppCtx {
             purposed_denominator = purposed_denominator.max(denominator);
             max_value = max_value.max(value.abs());
         }
+        if let Some(err) = error {
+            return Err(err.into());
+        }
 
         // Reduce the denominator if the max value is too large to fit in i32
         while max_value * purposed_denominator > i32::MAX as f64 {
@@ -270,93 +691,141 @@ impl CreateAppCtx {
         Ok(())
     }
 
-    fn determine_default_denominator(&mut self, matches: &ArgMatches) -> Result<(), DynErr> {
+    fn determine_default_denominator(&mut self) -> Result<(), DynErr> {
+        if self.float_value {
+            self.builder.set_value_type(ValueType::Float);
+            self.denominator = None;
+            return Ok(());
+        }
+
         if self.denominator.is_some() {
             self.builder.set_denominator(self.denominator.unwrap());
             return Ok(());
         }
 
+        if self.is_streamed() {
+            info!("The input is streamed, the denominator is not detected from the values");
+            return Ok(());
+        }
+
         match self.input_type {
-            InputType::BiwWig => self.detect_default_denominator_for_bigwig(matches)?,
-            InputType::BedGraph => self.detect_default_denominator_for_bedgraph()?,
+            InputType::BiwWig => self.detect_default_denominator_for_bigwig()?,
+            InputType::BedGraph | InputType::Wig => {
+                self.detect_default_denominator_for_bedgraph()?
+            }
             _ => (),
         }
 
         Ok(())
     }
     
-    fn create_from_alignment(mut self, matches: &ArgMatches) -> Result<(), DynErr> {
+    fn create_from_alignment<PT: PrimaryTableWriter + Send>(
+        self,
+        matches: &ArgMatches,
+    ) -> Result<(), DynErr> {
         let reference = matches.value_of("ref");
 
-        self.builder.load_chrom_info_from_bam(&self.input_path)?;
-        let mut d4_writer: D4FileWriter = self.builder.create()?;
-
-        if self.compression {
-            d4_writer.enable_secondary_table_compression(self.compression_level);
+        if self.sample_inputs.is_empty() && !self.split_strand {
+            let mut builder = self.builder_for_path(&self.output_path);
+            builder.load_chrom_info_from_bam(&self.input_path)?;
+            let mut d4_writer: D4FileWriter<PT> = builder.create()?;
+            self.configure_writer(&mut d4_writer)?;
+            return write_alignment_track(
+                &self.input_path,
+                d4_writer,
+                self.get_bam_filter(),
+                self.depth_mode,
+                self.denominator,
+                reference,
+            );
         }
 
-        let partitions = d4_writer.parallel_parts(Some(10_000_000))?;
+        let inputs = if self.sample_inputs.is_empty() {
+            vec![(self.input_path.clone(), String::new())]
+        } else {
+            self.sample_inputs.clone()
+        };
 
-        info!("Total number of parallel tasks: {}", partitions.len());
-        
-        let bam_filter = self.get_bam_filter();
+        let strands = if self.split_strand {
+            vec![(Some(Strand::Forward), "forward"), (Some(Strand::Reverse), "reverse")]
+        } else {
+            vec![(self.strand, "")]
+        };
 
-        partitions
-            .into_par_iter()
-            .for_each(|(mut p_table, mut s_table)| {
-                let (chr, from, to) = p_table.region();
-                let chr = chr.to_owned();
-                let mut alignment = BamFile::open(&self.input_path).unwrap();
-                if let Some(reference) = reference {
-                    alignment.reference_path(reference);
-                }
-                let al_from = from - from.min(5000);
-                let time_begin = std::time::SystemTime::now();
-                info!("Task begin: {}:{}-{}", chr, from, to);
-                let range_iter = alignment
-                    .range(&chr, al_from as usize, to as usize)
-                    .unwrap();
-                let mut p_encoder = p_table.make_encoder();
-                let mut last_pos = 0;
-                for (_, pos, depth) in DepthIter::with_filter(range_iter, |r| bam_filter.filter_alignment(r)) {
-                    let depth = if let Some(denominator) = self.denominator {
-                        (depth as f64 * denominator).round() as u32
-                    } else {
-                        depth
-                    };
+        let expected_chroms = BamFile::open(&self.input_path)?.chroms().to_owned();
 
-                    last_pos = pos;
-                    if pos < from as usize {
-                        continue;
-                    }
-                    if pos as u32 >= to {
-                        break;
-                    }
-                    if !p_encoder.encode(pos, depth as i32) {
-                        s_table.encode(pos as u32, depth as i32).unwrap();
-                    }
-                }
-                for pos in last_pos.max(from as usize)..to as usize {
-                    if !p_encoder.encode(pos, 0) {
-                        s_table.encode(pos as u32, 0).unwrap();
-                    }
-                }
-                s_table.flush().unwrap();
-                s_table.finish().unwrap();
-                let time_end = std::time::SystemTime::now();
-                let duration = time_end.duration_since(time_begin).unwrap_or_default();
-                info!(
-                    "Task completed: {}:{}-{} Duration: {}ms",
-                    chr,
-                    from,
-                    to,
-                    duration.as_millis()
-                );
-            });
+        // The tracks are encoded in parallel, each into a temporary file next to the output, and
+        // copied into the output container once they are all done
+        let output_dir = self
+            .output_path
+            .parent()
+            .filter(|dir| !dir.as_os_str().is_empty())
+            .unwrap_or_else(|| Path::new("."));
+        let temp_dir = tempfile::Builder::new()
+            .prefix(".d4-create")
+            .tempdir_in(output_dir)?;
+        let mut merger = D4FileMerger::new(&self.output_path);
+        let mut tracks = vec![];
+        for (input_path, tag) in inputs.iter() {
+            if BamFile::open(input_path)?.chroms() != expected_chroms.as_slice() {
+                return Err(format!("Inconsistent reference genome in file {}", input_path.display()).into());
+            }
+            for &(strand, strand_tag) in strands.iter() {
+                let track_name = match (tag.as_str(), strand_tag) {
+                    ("", strand_tag) => strand_tag.to_string(),
+                    (tag, "") => tag.to_string(),
+                    (tag, strand_tag) => format!("{}.{}", tag, strand_tag),
+                };
+                let track_path = temp_dir.path().join(format!("{}.d4", tracks.len()));
+                let mut builder = self.builder_for_path(&track_path);
+                builder.load_chrom_info_from_bam(input_path)?;
+                let mut d4_writer: D4FileWriter<PT> = builder.create()?;
+                self.configure_writer(&mut d4_writer)?;
+                let bam_filter = BamFilter {
+                    strand,
+                    ..self.get_bam_filter()
+                };
+                merger = merger.add_input_with_tag(&track_path, &track_name);
+                tracks.push((input_path, track_name, d4_writer, bam_filter));
+            }
+        }
+
+        let (depth_mode, denominator) = (self.depth_mode, self.denominator);
+        tracks
+            .into_par_iter()
+            .try_for_each(|(input_path, track_name, d4_writer, bam_filter)| {
+                info!("Creating track {} from {}", track_name, input_path.display());
+                write_alignment_track(
+                    input_path,
+                    d4_writer,
+                    bam_filter,
+                    depth_mode,
+                    denominator,
+                    reference,
+                )
+                .map_err(|err| format!("Unable to create track {}: {}", track_name, err))
+            })?;
+        merger.merge()?;
         Ok(())
     }
 
-    fn create_from_bigwig(mut self) -> Result<(), DynErr> {
+    /// Encode the value read from a BigWig or bedGraph file
+    fn encode_value(&self, value: f64) -> i32 {
+        if self.float_value {
+            ValueType::Float.encode(value)
+        } else if let Some(denominator) = self.denominator {
+            (value * denominator).round() as i32
+        } else {
+            if value - value.floor() > 1e-10 {
+                warn!("Encoding a decimal valued input to a integer D4, fix-point mode is recommended");
+            }
+            value as i32
+        }
+    }
+
+    fn create_from_bigwig<PT: PrimaryTableWriter, ST: SecondaryTableWriter>(
+        mut self,
+    ) -> Result<(), DynErr> {
         let bw_file = d4_bigwig::BigWigFile::open(&self.input_path)?;
         self.builder.append_chrom(
             bw_file
@@ -364,10 +833,8 @@ impl CreateAppCtx {
                 .into_iter()
                 .map(|(name, size)| Chrom { name, size }),
         );
-        let mut d4_writer: D4FileWriter = self.builder.create()?;
-        if self.compression {
-            d4_writer.enable_secondary_table_compression(self.compression_level);
-        }
+        let mut d4_writer: D4FileWriter<PT, ST> = self.builder.create()?;
+        self.configure_writer(&mut d4_writer)?;
         let partition = d4_writer.parallel_parts(None)?;
         for (mut pt, mut st) in partition {
             let (chrom, left, right) = pt.region();
@@ -387,17 +854,19 @@ impl CreateAppCtx {
                     value,
                 } in iter
                 {
-                    let value = if let Some(denominator) = self.denominator {
-                        ((value as f64) * denominator).round() as f32
+                    let value = if self.float_value {
+                        ValueType::Float.encode(value as f64)
+                    } else if let Some(denominator) = self.denominator {
+                        ((value as f64) * denominator).round() as i32
                     } else {
-                        value
+                        value as i32
                     };
 
                     for pos in last..left {
                         write_value(pos, 0);
                     }
                     for pos in left..right {
-                        write_value(pos, value as i32);
+                        write_value(pos, value);
                     }
                     last = right;
                 }
@@ -405,39 +874,160 @@ impl CreateAppCtx {
             for pos in last..right {
                 write_value(pos, 0);
             }
+            st.flush()?;
+            st.finish()?;
+            pt.finish()?;
         }
+        d4_writer.finish()?;
         Ok(())
     }
-    fn create_from_bedgraph(mut self, matches: &ArgMatches) -> Result<(), DynErr> {
-        self.builder.append_chrom(
-            parse_genome_file(
-                matches
-                    .value_of("genome")
-                    .expect("Genome file is required for text file format"),
-            )?
-            .into_iter(),
-        );
+    /// Create a multi-track D4 file from a multi-column bedGraph file, each value column is
+    /// written to the track tagged by the column header
+    fn create_from_multi_column_bedgraph<PT: PrimaryTableWriter, ST: SecondaryTableWriter>(
+        mut self,
+        matches: &ArgMatches,
+    ) -> Result<(), DynErr> {
+        let chroms = parse_genome_file(
+            matches
+                .value_of("genome")
+                .expect("Genome file is required for text file format"),
+        )?;
+        self.builder.append_chrom(chroms.iter().cloned());
+        let input = self
+            .multi_column_input
+            .as_ref()
+            .expect("The multi-column input should be loaded");
+        if self.overlap_policy.is_none() {
+            let chrom_sizes: HashMap<_, _> = chroms
+                .iter()
+                .map(|chrom| (chrom.name.clone(), chrom.size))
+                .collect();
+            let mut last_end = HashMap::new();
+            for (seq, (chr, begin, end, _)) in input.column(0).enumerate() {
+                check_sorted_record(seq, chr, begin, end, &chrom_sizes, &mut last_end)?;
+            }
+        }
+        // The builder is taken out, since the track writer callback borrows the context
+        let mut builder =
+            std::mem::replace(&mut self.builder, D4FileBuilder::new(&self.output_path));
+        builder.create_multi_track(
+            &self.track_names,
+            |idx, mut d4_writer: D4FileWriter<PT, ST>| {
+                info!("Creating track {}", self.track_names[idx]);
+                self.configure_writer(&mut d4_writer)?;
+                if let Some(policy) = self.overlap_policy {
+                    let intervals = self
+                        .load_unsorted_bedgraph(&chroms, input.column(idx).map(Ok), policy)
+                        .map_err(|err| std::io::Error::other(err.to_string()))?;
+                    d4_writer.write_intervals(intervals.into_iter().map(
+                        |(chr, begin, end, value)| (chr, begin, end, self.encode_value(value)),
+                    ))?;
+                    return d4_writer.finish();
+                }
+                d4_writer.write_intervals(input.column(idx).map(|(chr, begin, end, value)| {
+                    (chr, begin, end, self.encode_value(value))
+                }))?;
+                d4_writer.finish()
+            },
+        )?;
+        Ok(())
+    }
+    /// Load the records of a value column of the bedGraph/WIG input in the unsorted mode. The
+    /// records are validated against the genome, sorted and the overlapping records are combined
+    /// with the policy.
+    fn load_unsorted_bedgraph<N: AsRef<str>>(
+        &self,
+        chroms: &[Chrom],
+        records: impl Iterator<Item = std::io::Result<(N, u32, u32, f64)>>,
+        policy: OverlapPolicy,
+    ) -> Result<Vec<(String, u32, u32, f64)>, DynErr> {
+        let chrom_ids: HashMap<_, _> = chroms
+            .iter()
+            .enumerate()
+            .map(|(id, chrom)| (chrom.name.as_str(), id))
+            .collect();
+        let mut chrom_records: Vec<Vec<UnsortedRecord>> = chroms.iter().map(|_| vec![]).collect();
+        for (seq, record) in records.enumerate() {
+            let (chr, begin, end, value) = record?;
+            let chr = chr.as_ref();
+            let id = chrom_ids.get(chr).copied();
+            check_record(seq, chr, begin, end, id.map(|id| chroms[id].size))?;
+            if let Some(id) = id.filter(|_| self.chr_filter.is_match(chr)) {
+                chrom_records[id].push(UnsortedRecord {
+                    begin,
+                    end,
+                    seq,
+                    value,
+                });
+            }
+        }
+        let mut intervals = vec![];
+        for (chrom, records) in chroms.iter().zip(chrom_records) {
+            resolve_overlaps(&chrom.name, records, policy, &mut intervals)?;
+        }
+        Ok(intervals)
+    }
+    fn create_from_unsorted_bedgraph<PT: PrimaryTableWriter, ST: SecondaryTableWriter>(
+        mut self,
+        matches: &ArgMatches,
+        policy: OverlapPolicy,
+    ) -> Result<(), DynErr> {
+        let chroms = parse_genome_file(
+            matches
+                .value_of("genome")
+                .expect("Genome file is required for text file format"),
+        )?;
+        self.builder.append_chrom(chroms.iter().cloned());
+        let records = parse_bed_file(&self.input_path)?;
+        let intervals = self.load_unsorted_bedgraph(&chroms, records, policy)?;
+        let mut d4_writer: D4FileWriter<PT, ST> = self.builder.create()?;
+        self.configure_writer(&mut d4_writer)?;
+        d4_writer.write_intervals(
+            intervals
+                .into_iter()
+                .map(|(chr, begin, end, value)| (chr, begin, end, self.encode_value(value))),
+        )?;
+        d4_writer.finish()?;
+        Ok(())
+    }
+    fn create_from_bedgraph<PT: PrimaryTableWriter, ST: SecondaryTableWriter>(
+        mut self,
+        matches: &ArgMatches,
+    ) -> Result<(), DynErr> {
+        if !self.track_names.is_empty() {
+            return self.create_from_multi_column_bedgraph::<PT, ST>(matches);
+        }
+        if let Some(policy) = self.overlap_policy {
+            return self.create_from_unsorted_bedgraph::<PT, ST>(matches, policy);
+        }
+        let chroms = parse_genome_file(
+            matches
+                .value_of("genome")
+                .expect("Genome file is required for text file format"),
+        )?;
+        let chrom_sizes: HashMap<_, _> = chroms
+            .iter()
+            .map(|chrom| (chrom.name.clone(), chrom.size))
+            .collect();
+        self.builder.append_chrom(chroms.into_iter());
         let default_pt_value = if self.builder.dictionary().bit_width() == 0 {
             Some(self.builder.dictionary().first_value())
         } else {
             None
         };
-        let mut d4_writer: D4FileWriter = self.builder.create()?;
-        if self.compression {
-            d4_writer.enable_secondary_table_compression(self.compression_level);
-        }
+        let mut d4_writer: D4FileWriter<PT, ST> = self.builder.create()?;
+        self.configure_writer(&mut d4_writer)?;
         let mut partition = d4_writer.parallel_parts(None)?;
         let input = parse_bed_file(&self.input_path)?;
         let mut current = 0;
-        for (chr, from, to, depth) in input {
-            let depth = if let Some(denominator) = self.denominator {
-                (depth * denominator).round() as i32
-            } else {
-                if depth - depth.floor() > 1e-10 {
-                    warn!("Encoding a decimal valued input to a integer D4, fix-point mode is recommended");
-                }
-                depth as i32
-            };
+        let mut last_end = HashMap::new();
+        for (seq, record) in input.enumerate() {
+            let (chr, from, to, depth) = record?;
+            check_sorted_record(seq, &chr, from, to, &chrom_sizes, &mut last_end)?;
+            if !self.chr_filter.is_match(&chr) {
+                continue;
+            }
+            let depth = self.encode_value(depth);
 
             if let Some(default) = default_pt_value {
                 if default == depth {
@@ -447,7 +1037,7 @@ impl CreateAppCtx {
                     let mut from = from;
                     while from < to {
                         let mut region = partition[current].0.region();
-                        if region.0 != chr || region.1 < from || region.2 >= from {
+                        if region.0 != chr || from < region.1 || region.2 <= from {
                             if let Some((idx, _)) = (0..).zip(partition.iter()).find(|(_, part)| {
                                 let reg = part.0.region();
                                 reg.0 == chr && reg.1 <= from && from < reg.2
@@ -455,7 +1045,7 @@ impl CreateAppCtx {
                                 current = idx;
                                 region = partition[current].0.region();
                             } else {
-                                continue;
+                                break;
                             }
                         }
                         let record_from = from;
@@ -469,7 +1059,7 @@ impl CreateAppCtx {
             } else {
                 for pos in from..to {
                     let region = partition[current].0.region();
-                    if region.0 != chr || region.1 < pos || region.2 >= pos {
+                    if region.0 != chr || pos < region.1 || region.2 <= pos {
                         if let Some((idx, _)) = (0..).zip(partition.iter()).find(|(_, part)| {
                             let reg = part.0.region();
                             reg.0 == chr && reg.1 <= pos && pos < reg.2
@@ -487,18 +1077,170 @@ impl CreateAppCtx {
             }
             partition[current].1.flush()?;
         }
-        for (_, mut stab) in partition {
+        for (mut ptab, mut stab) in partition {
             stab.finish()?;
+            ptab.finish()?;
+        }
+        d4_writer.finish()?;
+        Ok(())
+    }
+    /// Create the D4 file with the given primary table writer
+    fn create<PT: PrimaryTableWriter + Send>(self, matches: &ArgMatches) -> Result<(), DynErr> {
+        match self.input_type {
+            InputType::Alignment => self.create_from_alignment::<PT>(matches)?,
+            InputType::BiwWig if self.run_length => {
+                self.create_from_bigwig::<PT, SparseArrayWriter<RunLengthRecord>>()?
+            }
+            InputType::BiwWig if self.float_value => {
+                self.create_from_bigwig::<PT, SparseArrayWriter<FloatRangeRecord>>()?
+            }
+            InputType::BiwWig => self.create_from_bigwig::<PT, SparseArrayWriter<RangeRecord>>()?,
+            InputType::BedGraph | InputType::Wig if self.run_length => {
+                self.create_from_bedgraph::<PT, SparseArrayWriter<RunLengthRecord>>(matches)?
+            }
+            InputType::BedGraph | InputType::Wig if self.float_value => {
+                self.create_from_bedgraph::<PT, SparseArrayWriter<FloatRangeRecord>>(matches)?
+            }
+            InputType::BedGraph | InputType::Wig => {
+                self.create_from_bedgraph::<PT, SparseArrayWriter<RangeRecord>>(matches)?
+            }
+            _ => panic!("Unsupported input file format"),
         }
         Ok(())
     }
 }
 
+/// Encode the depth profile of an alignment file into the track
+fn write_alignment_track<PT: PrimaryTableWriter>(
+    input_path: &Path,
+    mut d4_writer: D4FileWriter<PT>,
+    bam_filter: BamFilter,
+    depth_mode: DepthMode,
+    denominator: Option<f64>,
+    reference: Option<&str>,
+) -> Result<(), DynErr> {
+    let partitions = d4_writer.parallel_parts(Some(10_000_000))?;
+
+    info!("Total number of parallel tasks: {}", partitions.len());
+
+    partitions
+        .into_par_iter()
+        .try_for_each(|(mut p_table, mut s_table)| -> std::io::Result<()> {
+            let (chr, from, to) = p_table.region();
+            let chr = chr.to_owned();
+            let mut alignment = BamFile::open(input_path).unwrap();
+            if let Some(reference) = reference {
+                alignment.reference_path(reference);
+            }
+            let margin = if depth_mode == DepthMode::Fragment {
+                // A fragment that covers the partition may start long before it, so the reads
+                // are loaded from where the longest fragment overlapping the partition starts
+                alignment
+                    .range(&chr, from as usize, to as usize)
+                    .unwrap()
+                    .into_alignment_iter()
+                    .filter_map(|read| read.ok())
+                    .filter(|read| read.is_proper_pair() && bam_filter.filter_alignment(read))
+                    .map(|read| read.insert_size().unsigned_abs() as u32)
+                    .fold(5000, u32::max)
+            } else {
+                5000
+            };
+            let al_from = from - from.min(margin);
+            let time_begin = std::time::SystemTime::now();
+            info!("Task begin: {}:{}-{}", chr, from, to);
+            let range_iter = alignment
+                .range(&chr, al_from as usize, to as usize)
+                .unwrap();
+            let mut p_encoder = p_table.make_encoder();
+            let mut last_pos = 0;
+            for (_, pos, depth) in DepthIter::with_filter_and_mode(range_iter, |r| bam_filter.filter_alignment(r), depth_mode) {
+                let depth = if let Some(denominator) = denominator {
+                    (depth as f64 * denominator).round() as u32
+                } else {
+                    depth
+                };
+
+                last_pos = pos;
+                if pos < from as usize {
+                    continue;
+                }
+                if pos as u32 >= to {
+                    break;
+                }
+                if !p_encoder.encode(pos, depth as i32) {
+                    s_table.encode(pos as u32, depth as i32)?;
+                }
+            }
+            for pos in last_pos.max(from as usize)..to as usize {
+                if !p_encoder.encode(pos, 0) {
+                    s_table.encode(pos as u32, 0)?;
+                }
+            }
+            s_table.flush()?;
+            s_table.finish()?;
+            p_table.finish()?;
+            let time_end = std::time::SystemTime::now();
+            let duration = time_end.duration_since(time_begin).unwrap_or_default();
+            info!(
+                "Task completed: {}:{}-{} Duration: {}ms",
+                chr,
+                from,
+                to,
+                duration.as_millis()
+            );
+            Ok(())
+        })?;
+    d4_writer.finish()?;
+    Ok(())
+}
+
+/// Collect the alignment inputs specified by --input and --manifest. Each line of the manifest
+/// file contains the path to the alignment file, optionally followed by the track tag.
+fn parse_sample_inputs(matches: &ArgMatches) -> Result<Vec<(PathBuf, String)>, DynErr> {
+    let default_tag = |path: &Path| {
+        path.file_stem()
+            .map(|stem| stem.to_string_lossy().to_string())
+            .unwrap_or_default()
+    };
+    let mut ret = vec![];
+    if let Some(manifest) = matches.value_of("manifest") {
+        for line in BufReader::new(File::open(manifest)?).lines() {
+            let line = line?;
+            let mut tokens = line.split('\t').map(str::trim);
+            let path: PathBuf = match tokens.next() {
+                Some(path) if !path.is_empty() && !path.starts_with('#') => path.into(),
+                _ => continue,
+            };
+            let tag = tokens
+                .next()
+                .filter(|tag| !tag.is_empty())
+                .map_or_else(|| default_tag(&path), ToString::to_string);
+            ret.push((path, tag));
+        }
+    }
+    if let Some(inputs) = matches.values_of("input") {
+        for path in inputs.map(PathBuf::from) {
+            let tag = default_tag(&path);
+            ret.push((path, tag));
+        }
+    }
+    let mut tags: Vec<_> = ret.iter().map(|(_, tag)| tag.as_str()).collect();
+    tags.sort_unstable();
+    if let Some(dup) = tags.windows(2).find(|pair| pair[0] == pair[1]) {
+        return Err(format!("Duplicated track tag: {}", dup[0]).into());
+    }
+    Ok(ret)
+}
+
 fn main_impl(matches: ArgMatches) -> Result<(), Box<dyn std::error::Error>> {
     setup_thread_pool(&matches)?;
 
     let mut ctx = CreateAppCtx::new(&matches)?;
 
+    // The denominator is determined first, since the dictionary is profiled on the encoded values
+    ctx.determine_default_denominator()?;
+
     ctx.configure_dict(&matches)?;
 
     if matches.values_of("dump-dict").is_some() {
@@ -506,15 +1248,10 @@ fn main_impl(matches: ArgMatches) -> Result<(), Box<dyn std::error::Error>> {
         std::process::exit(0);
     }
 
-    ctx.determine_default_denominator(&matches)?;
-
-    match ctx.input_type {
-        InputType::Alignment => ctx.create_from_alignment(&matches)?,
-        InputType::BiwWig => ctx.create_from_bigwig()?,
-        InputType::BedGraph => ctx.create_from_bedgraph(&matches)?,
-        _ => panic!("Unsupported input file format"),
+    match ctx.primary_compression {
+        Some(_) => ctx.create::<CompressedBitArrayWriter>(&matches),
+        None => ctx.create::<BitArrayWriter>(&matches),
     }
-    Ok(())
 }
 
 pub fn entry_point(args: Vec<String>) -> Result<(), Box<dyn std::error::Error>> {
@@ -0,0 +1,96 @@
+use clap::{load_yaml, App};
+use d4::{
+    task::{Diff, DiffSummary, Task},
+    D4MatrixReader, D4TrackReader,
+};
+use d4tools::{setup_thread_pool, AppResult};
+use std::{
+    collections::HashMap,
+    io::{BufWriter, Write},
+};
+
+/// The size of each region we compare at once
+const PART_SIZE: u32 = 1_000_000;
+
+pub fn entry_point(args: Vec<String>) -> AppResult<()> {
+    let yaml = load_yaml!("cli.yml");
+    let matches = App::from_yaml(yaml)
+        .version(d4tools::VERSION)
+        .get_matches_from(args);
+
+    setup_thread_pool(&matches)?;
+
+    let readers: Vec<D4TrackReader> = vec![
+        D4TrackReader::open(matches.value_of("first-file").unwrap())?,
+        D4TrackReader::open(matches.value_of("second-file").unwrap())?,
+    ];
+    let denominators = [
+        readers[0].header().get_denominator(),
+        readers[1].header().get_denominator(),
+    ];
+    let mut reader = D4MatrixReader::new(readers)?;
+    let chroms: Vec<_> = reader
+        .chrom_regions()
+        .into_iter()
+        .map(|(name, _, size)| (name.to_string(), size))
+        .collect();
+
+    let summary_mode = matches.is_present("summary");
+    let mut summary: HashMap<String, DiffSummary> = HashMap::new();
+
+    let mut output = BufWriter::new(std::io::stdout());
+
+    let regions: Vec<_> = chroms
+        .iter()
+        .flat_map(|(chr, size)| {
+            (0..*size)
+                .step_by(PART_SIZE as usize)
+                .map(move |begin| (chr.as_str(), begin, (begin + PART_SIZE).min(*size)))
+        })
+        .collect();
+    let batch_size = rayon::current_num_threads() * 4;
+
+    for batch in regions.chunks(batch_size) {
+        let tasks: Vec<_> = batch
+            .iter()
+            .map(|&(chr, begin, end)| Diff::new(chr, begin, end, denominators))
+            .collect();
+        let results = Diff::create_task(&mut reader, tasks)?.run();
+        let mut results: HashMap<_, _> = results
+            .into_iter()
+            .map(|part| ((part.chrom, part.begin), part.output))
+            .collect();
+        for &(chr, begin, _) in batch {
+            let intervals = match results.remove(&(chr, begin)) {
+                Some(intervals) => intervals,
+                None => continue,
+            };
+            for interval in intervals {
+                if summary_mode {
+                    summary.entry(chr.to_string()).or_default().add(interval);
+                } else {
+                    let &(left, right, first, second) = interval;
+                    writeln!(
+                        output,
+                        "{}\t{}\t{}\t{}\t{}",
+                        chr, left, right, first, second
+                    )?;
+                }
+            }
+        }
+    }
+
+    if summary_mode {
+        for (chr, _) in chroms.iter() {
+            let chrom_summary = summary.remove(chr).unwrap_or_default();
+            writeln!(
+                output,
+                "{}\t{}\t{}",
+                chr, chrom_summary.diff_bases, chrom_summary.max_abs_diff
+            )?;
+        }
+    }
+
+    output.flush()?;
+    Ok(())
+}
@@ -0,0 +1,512 @@
+use clap::{load_yaml, App, ArgMatches};
+use d4::{
+    find_tracks_in_file,
+    ptab::{BitArrayPartReader, DecodeResult, Decoder, PrimaryTablePartReader},
+    stab::{RangeRecord, SecondaryTablePartReader, SparseArrayPartReader},
+    D4TrackReader, ValueType,
+};
+use d4_bigwig::{BigWigInterval, BigWigWriter};
+use d4tools::{parse_region_file, setup_thread_pool, AppResult, InputType};
+use rayon::prelude::*;
+use std::{
+    fs::File,
+    io::{BufWriter, Write},
+    path::Path,
+};
+use zip::{write::FileOptions, CompressionMethod, ZipWriter};
+
+/// The number of intervals we buffer before handing them to libBigWig
+const BIGWIG_CHUNK_SIZE: usize = 65536;
+
+/// The number of bases we decode at once when writing the NumPy arrays
+const NUMPY_CHUNK_SIZE: u32 = 1 << 24;
+
+/// The size of the partitions we decode in parallel when writing the NumPy arrays
+const NUMPY_PARTITION_SIZE: usize = 1_000_000;
+
+/// The file formats a D4 file can be exported to
+#[derive(Clone, Copy)]
+enum OutputFormat {
+    BigWig,
+    Arrow,
+    Parquet,
+    NumPy,
+}
+
+impl OutputFormat {
+    fn detect(matches: &ArgMatches, output: &Path) -> Option<Self> {
+        if matches.is_present("bigwig") {
+            return Some(Self::BigWig);
+        }
+        if matches.is_present("arrow") {
+            return Some(Self::Arrow);
+        }
+        if matches.is_present("parquet") {
+            return Some(Self::Parquet);
+        }
+        if matches.is_present("npy") {
+            return Some(Self::NumPy);
+        }
+        if matches!(InputType::detect(output), InputType::BiwWig) {
+            return Some(Self::BigWig);
+        }
+        let ext = output
+            .extension()
+            .and_then(|e| e.to_str())
+            .unwrap_or("")
+            .to_lowercase();
+        match ext.as_str() {
+            "arrow" | "feather" | "ipc" => Some(Self::Arrow),
+            "parquet" | "pq" => Some(Self::Parquet),
+            "npy" | "npz" => Some(Self::NumPy),
+            _ => None,
+        }
+    }
+}
+
+/// Collects the non-zero intervals of a track, merges the adjacent intervals with the same value
+/// and writes them to the BigWig file chunk by chunk
+struct BigWigSink {
+    writer: BigWigWriter,
+    value_type: ValueType,
+    denominator: f64,
+    chrom: String,
+    buffer: Vec<BigWigInterval>,
+}
+
+impl BigWigSink {
+    fn flush(&mut self, keep_last: bool) -> AppResult<()> {
+        let last = if keep_last { self.buffer.pop() } else { None };
+        self.writer.add_intervals(&self.chrom, &self.buffer)?;
+        self.buffer.clear();
+        self.buffer.extend(last);
+        Ok(())
+    }
+
+    fn push(&mut self, chrom: &str, begin: u32, end: u32, value: i32) -> AppResult<()> {
+        if value == 0 || begin >= end {
+            return Ok(());
+        }
+        if self.chrom != chrom {
+            self.flush(false)?;
+            self.chrom = chrom.to_string();
+        }
+        let value = (self.value_type.decode(value) / self.denominator) as f32;
+        if let Some(last) = self.buffer.last_mut() {
+            if last.end == begin && last.value == value {
+                last.end = end;
+                return Ok(());
+            }
+        }
+        self.buffer.push(BigWigInterval { begin, end, value });
+        if self.buffer.len() > BIGWIG_CHUNK_SIZE {
+            self.flush(true)?;
+        }
+        Ok(())
+    }
+}
+
+fn export_bigwig(input: &str, output: &Path, zoom_levels: u32) -> AppResult<()> {
+    let mut reader: D4TrackReader = D4TrackReader::open(input)?;
+    let chroms: Vec<_> = reader
+        .header()
+        .chrom_list()
+        .iter()
+        .map(|chrom| (chrom.name.clone(), chrom.size))
+        .collect();
+
+    let mut sink = BigWigSink {
+        writer: BigWigWriter::create(output, &chroms, zoom_levels)?,
+        value_type: reader.header().value_type(),
+        denominator: reader.header().get_denominator(),
+        chrom: String::new(),
+        buffer: Vec::with_capacity(BIGWIG_CHUNK_SIZE + 1),
+    };
+
+    for (mut primary, mut secondary) in reader.split(None)? {
+        let (chrom, begin, end) = primary.region();
+        let chrom = chrom.to_string();
+        if let Some(default_value) = primary.default_value() {
+            let mut last_end = begin;
+            for (left, right, value) in secondary.seek_iter(begin) {
+                let left = left.max(begin);
+                let right = right.min(end).max(left);
+                sink.push(&chrom, last_end, left, default_value)?;
+                sink.push(&chrom, left, right, value)?;
+                last_end = right;
+                if right == end {
+                    break;
+                }
+            }
+            sink.push(&chrom, last_end, end, default_value)?;
+        } else {
+            let mut result = Ok(());
+            let mut decoder = primary.make_decoder();
+            decoder.decode_block(
+                begin as usize,
+                (end - begin) as usize,
+                |pos: usize, value: DecodeResult| {
+                    let value = match value {
+                        DecodeResult::Definitely(value) => value,
+                        DecodeResult::Maybe(value) => secondary.decode(pos as u32).unwrap_or(value),
+                    };
+                    if result.is_ok() {
+                        result = sink.push(&chrom, pos as u32, pos as u32 + 1, value);
+                    }
+                },
+            );
+            result?;
+        }
+    }
+
+    sink.flush(false)?;
+
+    Ok(())
+}
+
+/// Open the selected track of the input, or all the tracks when no track is specified
+fn open_tracks(input: &str) -> AppResult<Vec<(String, D4TrackReader)>> {
+    let (path, track_paths) = if let Some((path, track)) = input.split_once(':') {
+        (path, vec![track.into()])
+    } else {
+        let mut track_paths = vec![];
+        find_tracks_in_file(input, |_| true, &mut track_paths)?;
+        (input, track_paths)
+    };
+    let mut tracks = vec![];
+    for track_path in track_paths {
+        let name = track_path.file_name().map_or_else(
+            || "value".to_string(),
+            |name| name.to_string_lossy().to_string(),
+        );
+        let reader = D4TrackReader::open_track_with_path(path, &track_path)?;
+        tracks.push((name, reader));
+    }
+    if tracks.is_empty() {
+        return Err("No data track found in the input file".into());
+    }
+    Ok(tracks)
+}
+
+#[cfg(feature = "arrow")]
+fn export_arrow(
+    input: &str,
+    output: &Path,
+    format: OutputFormat,
+    skip_zeros: bool,
+) -> AppResult<()> {
+    let mut exporter = d4::arrow::ArrowExporter::new(open_tracks(input)?)?;
+    exporter.skip_zeros(skip_zeros);
+    match format {
+        OutputFormat::Parquet => exporter.create_parquet(output)?,
+        _ => exporter.create_ipc(output)?,
+    }
+    Ok(())
+}
+
+#[cfg(not(feature = "arrow"))]
+fn export_arrow(_: &str, _: &Path, _: OutputFormat, _: bool) -> AppResult<()> {
+    Err(
+        "d4tools is built without the Arrow support, please rebuild it with the arrow feature"
+            .into(),
+    )
+}
+
+/// A track written to the dense NumPy array
+struct DenseTrack {
+    reader: D4TrackReader,
+    /// The partitions of the track, which are split once and shared by all the chunks
+    partitions: Vec<(BitArrayPartReader, SparseArrayPartReader<RangeRecord>)>,
+    value_type: ValueType,
+    denominator: f64,
+}
+
+impl DenseTrack {
+    fn new(mut reader: D4TrackReader) -> AppResult<Self> {
+        let partitions = reader.split(Some(NUMPY_PARTITION_SIZE))?;
+        let header = reader.header();
+        Ok(Self {
+            value_type: header.value_type(),
+            denominator: header.get_denominator(),
+            partitions,
+            reader,
+        })
+    }
+
+    fn is_integer(&self) -> bool {
+        self.value_type == ValueType::Integer && self.denominator == 1.0
+    }
+
+    fn decode(&self, value: i32) -> f64 {
+        self.value_type.decode(value) / self.denominator
+    }
+
+    /// Decode the raw values of the region into the buffer, the partitions overlapping the
+    /// region are decoded in parallel and each of them fills its own part of the buffer
+    fn read_values(&mut self, chrom: &str, begin: u32, end: u32, buf: &mut [i32]) {
+        let mut rest = buf;
+        let mut jobs = vec![];
+        for (primary, secondary) in self.partitions.iter_mut() {
+            let (part_chrom, part_begin, part_end) = primary.region();
+            if part_chrom != chrom || part_end <= begin || part_begin >= end {
+                continue;
+            }
+            let (from, to) = (part_begin.max(begin), part_end.min(end));
+            let (target, tail) = rest.split_at_mut((to - from) as usize);
+            rest = tail;
+            jobs.push((primary, secondary, from, to, target));
+        }
+        jobs.into_par_iter()
+            .for_each(|(primary, secondary, from, to, target)| {
+                if let Some(default_value) = primary.default_value() {
+                    target.fill(default_value);
+                    for (left, right, value) in secondary.seek_iter(from) {
+                        if left >= to {
+                            break;
+                        }
+                        let (left, right) = (left.max(from), right.min(to));
+                        if left < right {
+                            target[(left - from) as usize..(right - from) as usize].fill(value);
+                        }
+                    }
+                } else {
+                    let mut decoder = primary.make_decoder();
+                    decoder.decode_block(
+                        from as usize,
+                        (to - from) as usize,
+                        |pos: usize, value: DecodeResult| {
+                            target[pos - from as usize] = match value {
+                                DecodeResult::Definitely(value) => value,
+                                DecodeResult::Maybe(value) => {
+                                    secondary.decode(pos as u32).unwrap_or(value)
+                                }
+                            };
+                        },
+                    );
+                }
+            });
+    }
+}
+
+/// Write the header of a NumPy array file in format version 1.0
+fn write_npy_header<W: Write>(mut writer: W, descr: &str, shape: &[usize]) -> AppResult<()> {
+    let shape = match shape {
+        [size] => format!("{},", size),
+        _ => shape
+            .iter()
+            .map(|size| size.to_string())
+            .collect::<Vec<_>>()
+            .join(", "),
+    };
+    let mut header = format!(
+        "{{'descr': '{}', 'fortran_order': False, 'shape': ({}), }}",
+        descr, shape
+    );
+    // The magic string, the version and the header length take 10 bytes, the header is padded
+    // with spaces and terminated by a newline so that the data is 64-byte aligned
+    while (10 + header.len() + 1) % 64 != 0 {
+        header.push(' ');
+    }
+    header.push('\n');
+    writer.write_all(b"\x93NUMPY\x01\x00")?;
+    writer.write_all(&(header.len() as u16).to_le_bytes())?;
+    writer.write_all(header.as_bytes())?;
+    Ok(())
+}
+
+/// Writes the values of the tracks in a region as a dense NumPy array
+struct DenseArrayWriter {
+    tracks: Vec<DenseTrack>,
+    bin_size: Option<u32>,
+    buffer: Vec<i32>,
+}
+
+impl DenseArrayWriter {
+    /// The values are written as int32 when they are all integers, otherwise as float64
+    fn is_integer(&self) -> bool {
+        self.bin_size.is_none() && self.tracks.iter().all(DenseTrack::is_integer)
+    }
+
+    /// The shape of the array, which is (length,) for a single track and (tracks, length) for
+    /// multiple tracks
+    fn shape(&self, begin: u32, end: u32) -> Vec<usize> {
+        let size = (end - begin) as usize;
+        let size = self
+            .bin_size
+            .map_or(size, |bin_size| size.div_ceil(bin_size as usize));
+        if self.tracks.len() == 1 {
+            vec![size]
+        } else {
+            vec![self.tracks.len(), size]
+        }
+    }
+
+    fn data_size(&self, begin: u32, end: u32) -> usize {
+        let item_size = if self.is_integer() { 4 } else { 8 };
+        self.shape(begin, end).into_iter().product::<usize>() * item_size
+    }
+
+    fn write<W: Write>(
+        &mut self,
+        mut writer: W,
+        chrom: &str,
+        begin: u32,
+        end: u32,
+    ) -> AppResult<()> {
+        let is_integer = self.is_integer();
+        let descr = if is_integer { "<i4" } else { "<f8" };
+        write_npy_header(&mut writer, descr, &self.shape(begin, end))?;
+        // Each chunk covers whole bins, so that a bin is never split into two chunks
+        let chunk_size = self.bin_size.map_or(NUMPY_CHUNK_SIZE, |bin_size| {
+            (NUMPY_CHUNK_SIZE / bin_size).max(1) * bin_size
+        });
+        let mut bytes = vec![];
+        for track in self.tracks.iter_mut() {
+            for chunk_begin in (begin..end).step_by(chunk_size as usize) {
+                let chunk_end = chunk_begin.saturating_add(chunk_size).min(end);
+                self.buffer.resize((chunk_end - chunk_begin) as usize, 0);
+                track.read_values(chrom, chunk_begin, chunk_end, &mut self.buffer);
+                bytes.clear();
+                if is_integer {
+                    for value in self.buffer.iter() {
+                        bytes.extend_from_slice(&value.to_le_bytes());
+                    }
+                } else if let Some(bin_size) = self.bin_size {
+                    for bin in self.buffer.chunks(bin_size as usize) {
+                        let sum: f64 = bin.iter().map(|&value| track.decode(value)).sum();
+                        bytes.extend_from_slice(&(sum / bin.len() as f64).to_le_bytes());
+                    }
+                } else {
+                    for &value in self.buffer.iter() {
+                        bytes.extend_from_slice(&track.decode(value).to_le_bytes());
+                    }
+                }
+                writer.write_all(&bytes)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Parse the region specified as chr, chr:start-end or chr:start
+fn parse_region_spec(spec: &str, chrom_list: &[d4::Chrom]) -> AppResult<(String, u32, u32)> {
+    if let Some(chrom) = chrom_list.iter().find(|chrom| chrom.name == spec) {
+        return Ok((chrom.name.clone(), 0, chrom.size as u32));
+    }
+    let invalid = || format!("Invalid region spec: {}", spec);
+    let (chrom, range) = spec.rsplit_once(':').ok_or_else(invalid)?;
+    let size = chrom_list
+        .iter()
+        .find(|c| c.name == chrom)
+        .ok_or_else(|| format!("Unknown chromosome: {}", chrom))?
+        .size as u32;
+    let (begin, end) = match range.split_once('-') {
+        Some((begin, end)) => (begin.parse().ok(), end.parse().ok()),
+        None => (range.parse().ok(), Some(size)),
+    };
+    match (begin, end) {
+        (Some(begin), Some(end)) => Ok((chrom.to_string(), begin, end)),
+        _ => Err(invalid().into()),
+    }
+}
+
+fn export_numpy(matches: &ArgMatches, input: &str, output: &Path) -> AppResult<()> {
+    let tracks: Vec<_> = open_tracks(input)?
+        .into_iter()
+        .map(|(_, reader)| DenseTrack::new(reader))
+        .collect::<AppResult<_>>()?;
+    if !d4tools::check_reference_consistency(tracks.iter().map(|t| t.reader.header().chrom_list()))
+    {
+        return Err("Inconsistent reference genome".into());
+    }
+    let chrom_list = tracks[0].reader.header().chrom_list().to_vec();
+
+    let mut regions = vec![];
+    if let Some(region_file) = matches.value_of("region") {
+        regions.extend(parse_region_file(region_file)?);
+    }
+    if let Some(specs) = matches.values_of("regions") {
+        for spec in specs {
+            regions.push(parse_region_spec(spec, &chrom_list)?);
+        }
+    }
+    if matches.value_of("region").is_none() && regions.is_empty() {
+        regions.extend(
+            chrom_list
+                .iter()
+                .map(|chrom| (chrom.name.clone(), 0, chrom.size as u32)),
+        );
+    }
+    for (chrom, begin, end) in regions.iter() {
+        let size = chrom_list
+            .iter()
+            .find(|c| &c.name == chrom)
+            .ok_or_else(|| format!("Unknown chromosome: {}", chrom))?
+            .size as u32;
+        if begin >= end || *end > size {
+            return Err(format!(
+                "Region {}:{}-{} is out of the range of {}, whose size is {}",
+                chrom, begin, end, chrom, size
+            )
+            .into());
+        }
+    }
+
+    let bin_size: Option<u32> = matches.value_of("bin-size").map(str::parse).transpose()?;
+    if bin_size == Some(0) {
+        return Err("The bin size must be positive".into());
+    }
+
+    let mut writer = DenseArrayWriter {
+        tracks,
+        bin_size,
+        buffer: vec![],
+    };
+
+    let is_npz = output
+        .extension()
+        .is_some_and(|ext| ext.eq_ignore_ascii_case("npz"));
+
+    if is_npz {
+        let mut zip = ZipWriter::new(File::create(output)?);
+        for (chrom, begin, end) in regions {
+            let options = FileOptions::default()
+                .compression_method(CompressionMethod::Deflated)
+                .large_file(writer.data_size(begin, end) >= u32::MAX as usize);
+            zip.start_file(format!("{}:{}-{}.npy", chrom, begin, end), options)?;
+            writer.write(&mut zip, &chrom, begin, end)?;
+        }
+        zip.finish()?;
+    } else {
+        if regions.len() != 1 {
+            return Err("A .npy file only holds a single region, use a .npz output instead".into());
+        }
+        let (chrom, begin, end) = &regions[0];
+        let mut file = BufWriter::new(File::create(output)?);
+        writer.write(&mut file, chrom, *begin, *end)?;
+        file.flush()?;
+    }
+    Ok(())
+}
+
+pub fn entry_point(args: Vec<String>) -> AppResult<()> {
+    let yaml = load_yaml!("cli.yml");
+    let matches = App::from_yaml(yaml)
+        .version(d4tools::VERSION)
+        .get_matches_from(args);
+
+    setup_thread_pool(&matches)?;
+
+    let input = matches.value_of("input-file").unwrap();
+    let output = Path::new(matches.value_of("output-file").unwrap());
+
+    match OutputFormat::detect(&matches, output) {
+        Some(OutputFormat::BigWig) => {
+            let zoom_levels = matches.value_of("zoom-levels").map_or(Ok(10), str::parse)?;
+            export_bigwig(input, output, zoom_levels)
+        }
+        Some(OutputFormat::NumPy) => export_numpy(&matches, input, output),
+        Some(format) => export_arrow(input, output, format, matches.is_present("no-missing-data")),
+        None => Err(std::io::Error::other("Unsupported output format").into()),
+    }
+}
@@ -1,11 +1,14 @@
 use clap::{load_yaml, App, ArgMatches};
-use d4::index::{D4IndexCollection, Sum};
+use d4::{
+    find_tracks_in_file,
+    index::{D4IndexCollection, MinMax, Sum, ValueHistogram},
+};
+use std::path::{Path, PathBuf};
 
 use d4tools::AppResult;
 
-fn build_main(args: &ArgMatches) -> AppResult<()> {
-    let input_path = args.value_of("FILE").unwrap();
-    let mut index_collection = D4IndexCollection::open_for_write(input_path)?;
+fn build_track_index(input_path: &str, track: &Path, args: &ArgMatches) -> AppResult<()> {
+    let mut index_collection = D4IndexCollection::open_track_for_write(input_path, Some(track))?;
     if args.is_present("secondary-frame") {
         log::info!("Creating SFI");
         index_collection.create_secondary_frame_index()?;
@@ -17,12 +20,43 @@ fn build_main(args: &ArgMatches) -> AppResult<()> {
         }
         index_collection.create_sum_index()?;
     }
+    if args.is_present("minmax") {
+        if !args.is_present("secondary-frame") {
+            index_collection.create_secondary_frame_index().ok();
+        }
+        index_collection.create_minmax_index()?;
+    }
+    if args.is_present("histogram") {
+        if !args.is_present("secondary-frame") {
+            index_collection.create_secondary_frame_index().ok();
+        }
+        index_collection.create_histogram_index()?;
+    }
+    Ok(())
+}
+
+fn build_main(args: &ArgMatches) -> AppResult<()> {
+    let input_path = args.value_of("FILE").unwrap();
+    let tracks: Vec<PathBuf> = if let Some(tracks) = args.values_of("track") {
+        tracks.map(PathBuf::from).collect()
+    } else {
+        let mut tracks = vec![];
+        find_tracks_in_file(input_path, |_| true, &mut tracks)?;
+        tracks
+    };
+    for track in tracks {
+        log::info!("Building index for track {}", track.display());
+        build_track_index(input_path, &track, args)?;
+    }
     Ok(())
 }
 
 fn show_main(args: &ArgMatches) -> AppResult<()> {
     let input_path = args.value_of("FILE").unwrap();
-    let index_collection = D4IndexCollection::from_reader(std::fs::File::open(input_path)?)?;
+    let index_collection = D4IndexCollection::from_reader_with_track(
+        std::fs::File::open(input_path)?,
+        args.value_of("track"),
+    )?;
     match args.value_of("INDEX_TYPE").unwrap().to_lowercase().as_str() {
         "sfi" => {
             let index = index_collection.load_seconary_frame_index()?;
@@ -32,6 +66,14 @@ fn show_main(args: &ArgMatches) -> AppResult<()> {
             let index = index_collection.load_data_index::<Sum>()?;
             index.print_index();
         }
+        "minmax" => {
+            let index = index_collection.load_data_index::<MinMax>()?;
+            index.print_index();
+        }
+        "histogram" => {
+            let index = index_collection.load_data_index::<ValueHistogram>()?;
+            index.print_index();
+        }
         _ => {
             panic!("Unsupported index type")
         }
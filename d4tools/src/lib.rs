@@ -1,7 +1,10 @@
 use clap::ArgMatches;
-use d4::{Chrom, Dictionary};
+use d4::{
+    stab::SecondaryTablePartWriter, Chrom, D4FileBuilder, D4FileWriter, Dictionary, ValueType,
+};
+use flate2::bufread::MultiGzDecoder;
 use log::warn;
-use rayon::ThreadPoolBuildError;
+use rayon::{prelude::*, ThreadPoolBuildError};
 use std::fs::File;
 use std::io::{BufRead, BufReader};
 use std::path::Path;
@@ -14,20 +17,34 @@ pub enum InputType {
     Alignment,
     BedGraph,
     BiwWig,
+    Wig,
     Unsupported,
 }
 
 impl InputType {
     pub fn detect(path: &Path) -> InputType {
-        let ext = path
-            .extension()
-            .and_then(|e| e.to_str())
-            .unwrap_or("")
-            .to_lowercase();
+        // The standard input is read as text, which can be either bedGraph or WIG
+        if path == Path::new("-") {
+            return Self::BedGraph;
+        }
+        let extension = |path: &Path| {
+            path.extension()
+                .and_then(|e| e.to_str())
+                .unwrap_or("")
+                .to_lowercase()
+        };
+        let mut ext = extension(path);
+        // The compressed text files are detected by the extension before .gz
+        if ext == "gz" || ext == "bgz" {
+            ext = path
+                .file_stem()
+                .map_or_else(String::new, |stem| extension(Path::new(stem)));
+        }
         match ext.as_str() {
             "sam" | "bam" | "cram" => Self::Alignment,
             "bw" | "bigwig" => Self::BiwWig,
-            "txt" | "bedgraph" => Self::BedGraph,
+            "txt" | "bedgraph" | "bdg" | "bed" => Self::BedGraph,
+            "wig" => Self::Wig,
             _ => Self::Unsupported,
         }
     }
@@ -51,13 +68,127 @@ pub fn parse_genome_file<P: AsRef<Path>>(file: P) -> std::io::Result<Vec<Chrom>>
         .map(|(name, size)| Chrom { name, size })
         .collect())
 }
+/// Open a text input, `-` reads the standard input. The gzip and bgzip compressed inputs are
+/// detected by their magic number and decompressed on the fly.
+pub fn open_text_input<P: AsRef<Path>>(path: P) -> std::io::Result<Box<dyn BufRead>> {
+    let mut reader: Box<dyn BufRead> = if path.as_ref() == Path::new("-") {
+        Box::new(BufReader::new(std::io::stdin()))
+    } else {
+        Box::new(BufReader::new(File::open(path)?))
+    };
+    if reader.fill_buf()?.starts_with(&[0x1f, 0x8b]) {
+        reader = Box::new(BufReader::new(MultiGzDecoder::new(reader)));
+    }
+    Ok(reader)
+}
+
+/// The state of a WIG data section
+enum WigSection {
+    FixedStep {
+        chrom: String,
+        next: u32,
+        step: u32,
+        span: u32,
+    },
+    VariableStep {
+        chrom: String,
+        span: u32,
+    },
+}
+
+impl WigSection {
+    /// Parse the declaration line of a section, the positions in WIG files are 1-based
+    fn parse(line: &str) -> Option<Self> {
+        let mut tokens = line.split_whitespace();
+        let kind = tokens.next()?;
+        let mut chrom = None;
+        let mut start = None;
+        let mut step = None;
+        let mut span = 1;
+        for token in tokens {
+            match token.split_once('=')? {
+                ("chrom", value) => chrom = Some(value.to_owned()),
+                ("start", value) => start = Some(value.parse::<u32>().ok()?),
+                ("step", value) => step = Some(value.parse().ok()?),
+                ("span", value) => span = value.parse().ok()?,
+                _ => {}
+            }
+        }
+        match kind {
+            "fixedStep" => Some(Self::FixedStep {
+                chrom: chrom?,
+                next: start?.checked_sub(1)?,
+                step: step?,
+                span,
+            }),
+            "variableStep" => Some(Self::VariableStep {
+                chrom: chrom?,
+                span,
+            }),
+            _ => None,
+        }
+    }
+
+    fn parse_data(&mut self, line: &str) -> Option<(String, u32, u32, f64)> {
+        match self {
+            Self::FixedStep {
+                chrom,
+                next,
+                step,
+                span,
+            } => {
+                let value = line.trim().parse().ok()?;
+                let begin = *next;
+                *next += *step;
+                Some((chrom.clone(), begin, begin + *span, value))
+            }
+            Self::VariableStep { chrom, span } => {
+                let mut tokens = line.split_whitespace();
+                let begin = tokens.next()?.parse::<u32>().ok()?.checked_sub(1)?;
+                let value = tokens.next()?.parse().ok()?;
+                Some((chrom.clone(), begin, begin + *span, value))
+            }
+        }
+    }
+}
+
+/// Parse a bedGraph or WIG file, see [open_text_input] for the supported inputs. The
+/// fixedStep and variableStep sections of WIG files are converted to intervals. An error is
+/// yielded if the input can't be read, e.g. a truncated compressed file.
 pub fn parse_bed_file<P: AsRef<Path>>(
     file: P,
-) -> std::io::Result<impl Iterator<Item = (String, u32, u32, f64)>> {
-    let file = BufReader::new(File::open(file)?);
+) -> std::io::Result<impl Iterator<Item = std::io::Result<(String, u32, u32, f64)>>> {
+    let file = open_text_input(file)?;
     let mut warned = false;
+    let mut wig_section = None;
     Ok(file.lines().filter_map(move |line| {
-        if let Ok(line) = line {
+        let line = match line {
+            Ok(line) => line,
+            Err(err) => return Some(Err(err)),
+        };
+        let parsed = (|| {
+            if line.starts_with("fixedStep") || line.starts_with("variableStep") {
+                wig_section = WigSection::parse(&line);
+                if wig_section.is_none() {
+                    warn!("Invalid WIG declaration line: {}", line.trim_end());
+                }
+                return None;
+            }
+            if line.starts_with('#') {
+                return None;
+            }
+            if line.starts_with("track") || line.starts_with("browser") {
+                wig_section = None;
+                return None;
+            }
+            if let Some(section) = wig_section.as_mut() {
+                let ret = section.parse_data(&line);
+                if ret.is_none() && !warned && !line.trim().is_empty() {
+                    warn!("Invalid input line: {}", line.trim_end());
+                    warned = true;
+                }
+                return ret;
+            }
             let tokenized: Vec<_> = line.split(|c| c == '\t').take(4).collect();
             if tokenized.len() == 3 {
                 if let Ok(pos) = tokenized[1].parse() {
@@ -73,15 +204,129 @@ pub fn parse_bed_file<P: AsRef<Path>>(
                         }
                     }
                 }
-            } else if !warned && !line.starts_with('#') {
+            } else if !warned {
                 warn!("Invalid input line: {}", line.trim_end());
                 warned = true;
             }
+            None
+        })();
+        // The multi-column bedGraph files are imported as multiple tracks, but the standard
+        // input can't be checked before parsing. The extra value columns shouldn't be dropped
+        if parsed.is_some()
+            && line
+                .trim_end()
+                .split('\t')
+                .nth(4)
+                .is_some_and(|value| value.parse::<f64>().is_ok())
+        {
+            return Some(Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidData,
+                format!("Multiple value columns in line: {}", line.trim_end()),
+            )));
         }
-        None
+        parsed.map(Ok)
     }))
 }
 
+/// Parse a bedGraph file with multiple value columns, e.g. the output of `bedtools unionbedg`.
+/// Returns the names of the value columns and the intervals with all their values. The names
+/// are taken from the header line if there's one, otherwise the columns are named track1,
+/// track2, etc. Like [parse_bed_file], an error is yielded if the input can't be read.
+#[allow(clippy::type_complexity)]
+pub fn parse_multi_column_bed_file<P: AsRef<Path>>(
+    file: P,
+) -> std::io::Result<(
+    Vec<String>,
+    impl Iterator<Item = std::io::Result<(String, u32, u32, Vec<f64>)>>,
+)> {
+    let mut lines = open_text_input(file)?.lines().peekable();
+    let mut names = None;
+    while let Some(line) = lines.peek() {
+        let line = match line {
+            Ok(line) => line,
+            Err(_) => return Err(lines.next().unwrap().unwrap_err()),
+        };
+        let tokens: Vec<_> = line.trim_end().split('\t').collect();
+        if line.starts_with("track") || line.starts_with("browser") || tokens.len() < 4 {
+            lines.next();
+            continue;
+        }
+        let is_data = !line.starts_with('#')
+            && tokens[1].parse::<u32>().is_ok()
+            && tokens[2].parse::<u32>().is_ok();
+        if is_data {
+            names.get_or_insert_with(|| {
+                (1..tokens.len() - 2)
+                    .map(|idx| format!("track{}", idx))
+                    .collect()
+            });
+            break;
+        }
+        names = Some(tokens[3..].iter().map(|name| name.to_string()).collect());
+        lines.next();
+    }
+    let names: Vec<String> = names.unwrap_or_default();
+    let num_values = names.len();
+    let mut warned = false;
+    let intervals = lines.filter_map(move |line| {
+        let line = match line {
+            Ok(line) => line,
+            Err(err) => return Some(Err(err)),
+        };
+        let mut tokens = line.trim_end().split('\t');
+        let parsed = (|| {
+            let chr = tokens.next()?.to_owned();
+            let begin = tokens.next()?.parse().ok()?;
+            let end = tokens.next()?.parse().ok()?;
+            let values = tokens
+                .map(|value| value.parse().ok())
+                .collect::<Option<Vec<f64>>>()?;
+            (values.len() == num_values).then_some((chr, begin, end, values))
+        })();
+        if parsed.is_none() && !warned && !line.starts_with('#') && !line.trim().is_empty() {
+            warn!("Invalid input line: {}", line.trim_end());
+            warned = true;
+        }
+        parsed.map(Ok)
+    });
+    Ok((names, intervals))
+}
+
+/// Stop the parsed intervals at the first read error, which is stored in `error`. This is used
+/// to feed the intervals to a consumer that takes infallible items, the error should be checked
+/// once the intervals are consumed.
+pub fn stop_at_error<'a, T: 'a>(
+    items: impl Iterator<Item = std::io::Result<T>> + 'a,
+    error: &'a mut Option<std::io::Error>,
+) -> impl Iterator<Item = T> + 'a {
+    items.map_while(move |item| item.map_err(|err| *error = Some(err)).ok())
+}
+
+/// Parse a BED file that describes a list of regions, only the first 3 columns are used
+pub fn parse_region_file<P: AsRef<Path>>(file: P) -> std::io::Result<Vec<(String, u32, u32)>> {
+    let file = BufReader::new(File::open(file)?);
+    let mut ret = vec![];
+    for line in file.lines() {
+        let line = line?;
+        if line.starts_with('#') || line.starts_with("track") || line.starts_with("browser") {
+            continue;
+        }
+        let mut tokenized = line.split('\t');
+        if let (Some(chr), Some(begin), Some(end)) =
+            (tokenized.next(), tokenized.next(), tokenized.next())
+        {
+            if let (Ok(begin), Ok(end)) = (begin.trim().parse(), end.trim().parse()) {
+                ret.push((chr.to_owned(), begin, end));
+                continue;
+            }
+        }
+        if !line.trim().is_empty() {
+            warn!("Invalid region line: {}", line.trim_end());
+        }
+    }
+    Ok(ret)
+}
+
 pub fn make_dictionary(
     range_spec: Option<&str>,
     file_spec: Option<&str>,
@@ -128,3 +373,68 @@ pub fn check_reference_consistency<'a, I: Iterator<Item = &'a [Chrom]>>(iter: I)
 
     true
 }
+
+/// Split the chromosome into windows of the given size, the last window may be shorter
+pub fn make_windows(chr: &str, size: u32, window_size: u32) -> Vec<(String, u32, u32)> {
+    (0..size)
+        .step_by(window_size as usize)
+        .map(|begin| (chr.to_string(), begin, (begin + window_size).min(size)))
+        .collect()
+}
+
+/// Write a track that has one value for each fixed-size window, `chrom_values` are the actual
+/// values of the windows for each chromosome in `chrom_list`. Each window is a single secondary
+/// table record on top of an empty primary table.
+pub fn write_window_track<P: AsRef<Path>>(
+    path: P,
+    chrom_list: &[Chrom],
+    chrom_values: &[Vec<f64>],
+    window_size: u32,
+    value_type: ValueType,
+    denominator: f64,
+) -> std::io::Result<()> {
+    /// The size of each region we encode at once
+    const PART_SIZE: usize = 1_000_000;
+
+    let mut builder = D4FileBuilder::new(path);
+    builder.append_chrom(chrom_list.iter().cloned());
+    builder.set_dictionary(Dictionary::new_simple_range_dict(0, 1)?);
+    builder.set_value_type(value_type);
+    if value_type == ValueType::Integer && denominator != 1.0 {
+        builder.set_denominator(denominator);
+    }
+    let mut writer: D4FileWriter = builder.create()?;
+    writer.enable_secondary_table_compression(5);
+
+    let mut partitions = writer.parallel_parts(Some(PART_SIZE))?;
+    partitions
+        .par_iter_mut()
+        .try_for_each(|(pt, st)| -> std::io::Result<()> {
+            let (chr, begin, end) = pt.region();
+            let chrom_id = chrom_list.iter().position(|c| c.name == chr).unwrap();
+            let values = &chrom_values[chrom_id];
+            let first_window = (begin / window_size) as usize;
+            for (idx, &value) in values.iter().enumerate().skip(first_window) {
+                let window_begin = idx as u32 * window_size;
+                if window_begin >= end {
+                    break;
+                }
+                let left = window_begin.max(begin);
+                let right = (window_begin + window_size).min(end);
+                let value = match value_type {
+                    ValueType::Integer => (value * denominator).round() as i32,
+                    ValueType::Float => ValueType::Float.encode(value),
+                };
+                if value != 0 {
+                    st.encode_record(left, right, value)?;
+                }
+            }
+            st.flush()
+        })?;
+
+    for (_, mut st) in partitions {
+        st.finish()?;
+    }
+
+    Ok(())
+}
@@ -1,13 +1,22 @@
+mod binning;
+mod callable;
+mod compute;
 mod create;
+mod diff;
+mod export;
 mod framedump;
 mod index;
 mod ls_track;
 mod merge;
+mod normalize;
 mod plot;
+mod qc;
 #[cfg(feature = "d4-server")]
 mod server;
 mod show;
 mod stat;
+mod subset;
+mod update;
 
 fn main() -> Result<(), Box<dyn std::error::Error>> {
     env_logger::init_from_env(
@@ -15,30 +24,48 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
     );
     let args: Vec<_> = std::env::args().skip(1).collect();
     let ret = match args.first().map(AsRef::as_ref) {
+        Some("bin") => binning::entry_point(args),
+        Some("callable") => callable::entry_point(args),
+        Some("compute") => compute::entry_point(args),
         Some("create") => create::entry_point(args),
+        Some("diff") => diff::entry_point(args),
+        Some("export") => export::entry_point(args),
         Some("framedump") => framedump::entry_point(args),
         Some("index") => index::entry_point(args),
         Some("ls-track") => ls_track::entry_point(args),
         Some("merge") => merge::entry_point(args),
+        Some("normalize") => normalize::entry_point(args),
         Some("plot") => plot::entry_point(args),
+        Some("qc") => qc::entry_point(args),
         #[cfg(feature = "d4-server")]
         Some("serve") => server::entry_point(args),
         Some("show") | Some("view") => show::entry_point(args),
         Some("stat") => stat::entry_point(args),
+        Some("subset") => subset::entry_point(args),
+        Some("update") => update::entry_point(args),
         _ => {
             eprintln!("D4 Utilities Program {}(D4 library version: {})", d4tools::VERSION, d4::VERSION);
             eprintln!("Usage: d4tools <subcommand> <args>");
             eprintln!("Possible subcommands are:");
+            eprintln!("\tbin      \tSummarize a track in fixed-size windows");
+            eprintln!("\tcallable \tExtract the regions where the value is within a given range");
+            eprintln!("\tcompute  \tCombine multiple tracks into a new D4 file");
             eprintln!("\tcreate   \tCreate a new D4 depth profile");
+            eprintln!("\tdiff     \tCompare two tracks base by base");
+            eprintln!("\texport   \tExport a D4 track to other file formats");
             eprintln!("\tframedump\tDump The container data");
             eprintln!("\tindex    \tIndex related operations");
             eprintln!("\tls-track \tList all available tracks in the D4 file");
             eprintln!("\tmerge    \tMerge existing D4 file as a multi-track D4 file");
+            eprintln!("\tnormalize\tCorrect the GC bias and mappability of a depth profile");
             eprintln!("\tplot     \tPlot the specified region");
+            eprintln!("\tqc       \tReport the coverage uniformity and sex check metrics");
             #[cfg(feature = "d4-server")]
             eprintln!("\tserve    \tStart a D4 server");
             eprintln!("\tshow     \tPrint the underlying depth profile");
             eprintln!("\tstat     \tRun statistics on the given file");
+            eprintln!("\tsubset   \tCopy selected tracks, chromosomes and regions into a new file");
+            eprintln!("\tupdate   \tOverwrite values or append chromosomes in place");
             eprintln!("\tview     \tSame as show");
             eprintln!();
             eprintln!("Type 'd4tools <subcommand> --help' to learn more about each subcommands.");
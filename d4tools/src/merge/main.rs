@@ -8,7 +8,7 @@ fn main(args: Vec<String>) -> Result<(), Box<dyn std::error::Error>> {
         .get_matches_from(args);
     let inputs: Vec<_> = matches.values_of("input-files").unwrap().collect();
     let output = matches.value_of("output-file").unwrap();
-    let mut merger = D4FileMerger::new(output);
+    let mut merger = D4FileMerger::new(output).append(matches.is_present("append"));
     for input in inputs {
         if let Some(split_pos) = input.find(':') {
             let path = &input[..split_pos];
@@ -0,0 +1,265 @@
+use clap::{load_yaml, App};
+use d4::{
+    task::{Mean, Task},
+    Chrom, D4TrackReader, ValueType,
+};
+use d4tools::{make_windows, setup_thread_pool, write_window_track, AppResult};
+use log::{info, warn};
+use std::{
+    collections::HashMap,
+    fs::File,
+    io::{BufRead, BufReader},
+};
+
+/// The windows with more unknown bases (e.g. N) than this fraction are masked
+const MAX_UNKNOWN_FRACTION: f64 = 0.1;
+
+/// The number of GC percentage bins on each side that are pooled to estimate the expected
+/// depth of a GC percentage
+const GC_BIN_BANDWIDTH: usize = 2;
+
+/// The minimum number of windows required to estimate the expected depth of a GC percentage,
+/// the GC percentages with less support are interpolated from their neighbours
+const MIN_WINDOWS_PER_GC_BIN: usize = 20;
+
+/// The base composition of a window
+#[derive(Clone, Copy, Default)]
+struct BaseCount {
+    gc: u32,
+    known: u32,
+}
+
+impl BaseCount {
+    fn gc_percentage(&self) -> usize {
+        (self.gc as f64 * 100.0 / self.known as f64).round() as usize
+    }
+}
+
+/// Compute the base composition of each window for all the chromosomes in `chrom_list`
+fn count_bases(
+    fasta_path: &str,
+    chrom_list: &[Chrom],
+    window_size: u32,
+) -> AppResult<HashMap<String, Vec<BaseCount>>> {
+    let chrom_sizes: HashMap<_, _> = chrom_list
+        .iter()
+        .map(|c| (c.name.as_str(), c.size))
+        .collect();
+    let mut result = HashMap::new();
+    let mut current: Option<(String, usize, Vec<BaseCount>)> = None;
+    let fasta = BufReader::new(File::open(fasta_path)?);
+    for line in fasta.lines() {
+        let line = line?;
+        if let Some(header) = line.strip_prefix('>') {
+            if let Some((name, _, counts)) = current.take() {
+                result.insert(name, counts);
+            }
+            let name = header.split_whitespace().next().unwrap_or_default();
+            if let Some(&size) = chrom_sizes.get(name) {
+                let n_windows = size.div_ceil(window_size as usize);
+                current = Some((name.to_string(), 0, vec![BaseCount::default(); n_windows]));
+            }
+            continue;
+        }
+        if let Some((_, pos, counts)) = current.as_mut() {
+            for base in line.trim_end().bytes() {
+                if let Some(window) = counts.get_mut(*pos / window_size as usize) {
+                    match base {
+                        b'G' | b'C' | b'g' | b'c' => {
+                            window.gc += 1;
+                            window.known += 1;
+                        }
+                        b'A' | b'T' | b'a' | b't' => window.known += 1,
+                        _ => {}
+                    }
+                }
+                *pos += 1;
+            }
+        }
+    }
+    if let Some((name, _, counts)) = current.take() {
+        result.insert(name, counts);
+    }
+    for chrom in chrom_list {
+        if !result.contains_key(&chrom.name) {
+            return Err(format!("Chromosome {} is missing from the reference", chrom.name).into());
+        }
+    }
+    Ok(result)
+}
+
+/// Compute the mean value of each window of the chromosome, in the same order as the windows
+fn window_means(reader: &mut D4TrackReader, windows: &[(String, u32, u32)]) -> AppResult<Vec<f64>> {
+    let denominator = reader.header().get_denominator();
+    let mut results: Vec<_> = Mean::create_task(reader, windows)?
+        .run()
+        .into_iter()
+        .map(|part| (part.begin, *part.output / denominator))
+        .collect();
+    results.sort_by_key(|&(begin, _)| begin);
+    Ok(results.into_iter().map(|(_, value)| value).collect())
+}
+
+fn median(values: &mut [f64]) -> Option<f64> {
+    if values.is_empty() {
+        return None;
+    }
+    let mid = values.len() / 2;
+    let (_, value, _) = values.select_nth_unstable_by(mid, |a, b| a.total_cmp(b));
+    Some(*value)
+}
+
+/// Fit the expected depth of each GC percentage from the depths of the windows with that GC
+/// percentage. The sparse GC percentages are linearly interpolated from the nearest estimated
+/// ones.
+fn fit_gc_curve(depth_by_gc: &[Vec<f64>]) -> Option<Vec<f64>> {
+    let mut curve: Vec<Option<f64>> = (0..depth_by_gc.len())
+        .map(|gc| {
+            let from = gc.saturating_sub(GC_BIN_BANDWIDTH);
+            let to = (gc + GC_BIN_BANDWIDTH + 1).min(depth_by_gc.len());
+            let mut pooled: Vec<_> = depth_by_gc[from..to].concat();
+            if pooled.len() < MIN_WINDOWS_PER_GC_BIN {
+                return None;
+            }
+            median(&mut pooled)
+        })
+        .collect();
+
+    let known: Vec<_> = curve
+        .iter()
+        .enumerate()
+        .filter_map(|(gc, value)| value.map(|value| (gc, value)))
+        .collect();
+    let (&(first_gc, first_value), &(last_gc, last_value)) = (known.first()?, known.last()?);
+    for (gc, value) in curve.iter_mut().enumerate() {
+        if value.is_some() {
+            continue;
+        }
+        *value = Some(if gc < first_gc {
+            first_value
+        } else if gc > last_gc {
+            last_value
+        } else {
+            let right = known.partition_point(|&(known_gc, _)| known_gc < gc);
+            let (left_gc, left_value) = known[right - 1];
+            let (right_gc, right_value) = known[right];
+            let ratio = (gc - left_gc) as f64 / (right_gc - left_gc) as f64;
+            left_value + (right_value - left_value) * ratio
+        });
+    }
+    Some(curve.into_iter().map(Option::unwrap).collect())
+}
+
+pub fn entry_point(args: Vec<String>) -> AppResult<()> {
+    let yaml = load_yaml!("cli.yml");
+    let matches = App::from_yaml(yaml)
+        .version(d4tools::VERSION)
+        .get_matches_from(args);
+
+    setup_thread_pool(&matches)?;
+
+    let window_size: u32 = matches.value_of("window").unwrap_or("1000").parse()?;
+    if window_size == 0 {
+        return Err("The window size should be positive".into());
+    }
+    let min_mappability: f64 = matches
+        .value_of("min-mappability")
+        .unwrap_or("0.9")
+        .parse()?;
+    let denominator: f64 = matches.value_of("denominator").unwrap_or("100").parse()?;
+
+    let mut reader: D4TrackReader = D4TrackReader::open(matches.value_of("input").unwrap())?;
+    let chrom_list = reader.header().chrom_list().to_owned();
+    let mut mappability_reader: Option<D4TrackReader> = matches
+        .value_of("mappability")
+        .map(D4TrackReader::open)
+        .transpose()?;
+
+    let base_counts = count_bases(matches.value_of("ref").unwrap(), &chrom_list, window_size)?;
+
+    // The depth of each window, adjusted by its mappability, `None` for the masked windows
+    let mut chrom_depths = Vec::with_capacity(chrom_list.len());
+    let mut depth_by_gc = vec![vec![]; 101];
+    for chrom in chrom_list.iter() {
+        let windows = make_windows(&chrom.name, chrom.size as u32, window_size);
+        let depths = window_means(&mut reader, &windows)?;
+        let mappability = match mappability_reader.as_mut() {
+            Some(reader) if reader.header().get_chrom_id(&chrom.name).is_some() => {
+                window_means(reader, &windows)?
+            }
+            Some(_) => {
+                warn!(
+                    "Chromosome {} is missing from the mappability track",
+                    chrom.name
+                );
+                vec![0.0; windows.len()]
+            }
+            None => vec![1.0; windows.len()],
+        };
+        let counts = &base_counts[&chrom.name];
+        let depths: Vec<_> = windows
+            .iter()
+            .zip(depths)
+            .zip(mappability)
+            .zip(counts)
+            .map(|((((_, begin, end), depth), mappability), count)| {
+                let min_known = (end - begin) as f64 * (1.0 - MAX_UNKNOWN_FRACTION);
+                if (count.known as f64) < min_known
+                    || mappability < min_mappability
+                    || mappability <= 0.0
+                {
+                    return None;
+                }
+                let depth = depth / mappability;
+                depth_by_gc[count.gc_percentage()].push(depth);
+                Some(depth)
+            })
+            .collect();
+        chrom_depths.push(depths);
+    }
+
+    let num_windows: usize = depth_by_gc.iter().map(Vec::len).sum();
+    let mut all_depths = depth_by_gc.concat();
+    let global_median =
+        median(&mut all_depths).ok_or("No window is eligible for the GC bias correction")?;
+    let gc_curve = match fit_gc_curve(&depth_by_gc) {
+        Some(curve) => curve,
+        None => {
+            warn!("Too few windows to fit the GC bias, only the mappability is corrected");
+            vec![global_median; depth_by_gc.len()]
+        }
+    };
+    info!(
+        "{} windows are used to fit the GC bias, median depth {}",
+        num_windows, global_median
+    );
+
+    let chrom_values: Vec<Vec<f64>> = chrom_list
+        .iter()
+        .zip(chrom_depths)
+        .map(|(chrom, depths)| {
+            let counts = &base_counts[&chrom.name];
+            depths
+                .into_iter()
+                .zip(counts)
+                .map(|(depth, count)| match depth {
+                    Some(depth) if gc_curve[count.gc_percentage()] > 0.0 => {
+                        depth * global_median / gc_curve[count.gc_percentage()]
+                    }
+                    _ => 0.0,
+                })
+                .collect()
+        })
+        .collect();
+
+    write_window_track(
+        matches.value_of("output").unwrap(),
+        &chrom_list,
+        &chrom_values,
+        window_size,
+        ValueType::Integer,
+        denominator,
+    )?;
+
+    Ok(())
+}
@@ -0,0 +1,426 @@
+use clap::{load_yaml, App};
+use d4::{
+    task::{Histogram, Mean, PercentCov, SimpleTask, Task},
+    D4TrackReader, ValueType,
+};
+use d4tools::{parse_region_file, setup_thread_pool, AppResult};
+use log::warn;
+use serde_derive::Serialize;
+use std::{
+    collections::HashMap,
+    io::{BufWriter, Write},
+};
+
+/// The tasks run on each region
+type QcTask = (Histogram, Mean, PercentCov);
+
+/// The accumulated coverage of a set of regions, the depths are in the raw value unit
+#[derive(Clone)]
+struct Coverage {
+    length: u64,
+    sum: f64,
+    histogram: Vec<u64>,
+    above: u64,
+    covered: Vec<f64>,
+}
+
+impl Coverage {
+    fn new(num_bins: usize, num_thresholds: usize) -> Self {
+        Self {
+            length: 0,
+            sum: 0.0,
+            histogram: vec![0; num_bins],
+            above: 0,
+            covered: vec![0.0; num_thresholds],
+        }
+    }
+
+    fn add_region(
+        &mut self,
+        length: u32,
+        (histogram, mean, percent_cov): &((u32, Vec<u32>, u32), f64, Vec<f32>),
+    ) {
+        self.length += length as u64;
+        self.sum += mean * length as f64;
+        for (count, &value) in self.histogram.iter_mut().zip(histogram.1.iter()) {
+            *count += value as u64;
+        }
+        self.above += histogram.2 as u64;
+        for (count, &fraction) in self.covered.iter_mut().zip(percent_cov.iter()) {
+            *count += (fraction as f64 * length as f64).round();
+        }
+    }
+
+    fn merge(&mut self, other: &Coverage) {
+        self.length += other.length;
+        self.sum += other.sum;
+        for (count, value) in self.histogram.iter_mut().zip(other.histogram.iter()) {
+            *count += value;
+        }
+        self.above += other.above;
+        for (count, value) in self.covered.iter_mut().zip(other.covered.iter()) {
+            *count += value;
+        }
+    }
+
+    fn mean(&self) -> Option<f64> {
+        if self.length == 0 {
+            return None;
+        }
+        Some(self.sum / self.length as f64)
+    }
+
+    /// Get the smallest depth that is greater than or equal to the given fraction of bases,
+    /// `None` if the depth isn't in the range of the histogram
+    fn percentile(&self, fraction: f64) -> Option<f64> {
+        let target = ((self.length as f64 * fraction).round() as u64).max(1);
+        let mut current = 0;
+        for (value, &count) in self.histogram.iter().enumerate() {
+            current += count;
+            if current >= target {
+                return Some(value as f64);
+            }
+        }
+        None
+    }
+
+    /// Get the fraction of bases with the depth in the range, the bases beyond the histogram
+    /// range are counted only if the range is unbounded
+    fn fraction_between(&self, low: f64, high: f64) -> f64 {
+        let mut count: u64 = self
+            .histogram
+            .iter()
+            .enumerate()
+            .filter(|&(value, _)| value as f64 >= low && value as f64 <= high)
+            .map(|(_, &count)| count)
+            .sum();
+        if high >= self.histogram.len() as f64 {
+            count += self.above;
+        }
+        count as f64 / self.length as f64
+    }
+}
+
+/// The QC metrics of a set of regions, all the depths are in the actual value unit
+struct Summary {
+    name: String,
+    length: u64,
+    mean: Option<f64>,
+    median: Option<f64>,
+    fold_80_penalty: Option<f64>,
+    pct_within_20pct_of_mean: Option<f64>,
+    pct_dropout: Option<f64>,
+    pct_coverage: Vec<Option<f64>>,
+    autosome_ratio: Option<f64>,
+}
+
+impl Summary {
+    fn new(name: &str, coverage: &Coverage, scale: f64, autosome_mean: Option<f64>) -> Self {
+        let mean = coverage.mean();
+        let percentage = |count: f64| mean.map(|_| count * 100.0 / coverage.length as f64);
+        if let Some(mean) = mean {
+            if mean * 1.2 >= coverage.histogram.len() as f64 {
+                warn!(
+                    "The mean depth of {} is close to the histogram range, try a larger --max-bin",
+                    name
+                );
+            }
+        }
+        let p20 = coverage.percentile(0.2);
+        Summary {
+            name: name.to_string(),
+            length: coverage.length,
+            mean: mean.map(|mean| mean / scale),
+            median: coverage.percentile(0.5).map(|value| value / scale),
+            fold_80_penalty: mean.zip(p20).and_then(|(mean, p20)| {
+                if p20 > 0.0 {
+                    Some(mean / p20)
+                } else {
+                    None
+                }
+            }),
+            pct_within_20pct_of_mean: mean
+                .map(|mean| coverage.fraction_between(mean * 0.8, mean * 1.2) * 100.0),
+            pct_dropout: percentage(coverage.length as f64 - coverage.covered[0]),
+            pct_coverage: coverage.covered[1..]
+                .iter()
+                .map(|&count| percentage(count))
+                .collect(),
+            autosome_ratio: mean.zip(autosome_mean).and_then(|(mean, autosome_mean)| {
+                if autosome_mean > 0.0 {
+                    Some(mean / autosome_mean)
+                } else {
+                    None
+                }
+            }),
+        }
+    }
+}
+
+fn is_autosome(name: &str) -> bool {
+    let name = name.strip_prefix("chr").unwrap_or(name);
+    !name.is_empty() && name.bytes().all(|b| b.is_ascii_digit())
+}
+
+fn is_sex_chrom(name: &str, sex_chrom: &str) -> bool {
+    name.strip_prefix("chr").unwrap_or(name) == sex_chrom
+}
+
+fn format_value(value: Option<f64>, null: &str) -> String {
+    match value {
+        Some(value) if value.is_finite() => format!("{}", value),
+        _ => null.to_string(),
+    }
+}
+
+/// The depth coverage percentages keyed by the thresholds
+struct PctCoverage<'a>(&'a [u32], &'a [Option<f64>]);
+
+impl serde::Serialize for PctCoverage<'_> {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_map(self.0.iter().zip(self.1.iter()))
+    }
+}
+
+/// The JSON fields of the metrics in the summary, the non-finite values are written as null
+#[derive(Serialize)]
+struct JsonMetrics<'a> {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    name: Option<&'a str>,
+    length: u64,
+    mean: Option<f64>,
+    median: Option<f64>,
+    fold_80_penalty: Option<f64>,
+    pct_within_20pct_of_mean: Option<f64>,
+    pct_dropout: Option<f64>,
+    pct_coverage: PctCoverage<'a>,
+    autosome_ratio: Option<f64>,
+}
+
+impl<'a> JsonMetrics<'a> {
+    fn new(summary: &'a Summary, name: Option<&'a str>, thresholds: &'a [u32]) -> Self {
+        Self {
+            name,
+            length: summary.length,
+            mean: summary.mean,
+            median: summary.median,
+            fold_80_penalty: summary.fold_80_penalty,
+            pct_within_20pct_of_mean: summary.pct_within_20pct_of_mean,
+            pct_dropout: summary.pct_dropout,
+            pct_coverage: PctCoverage(thresholds, &summary.pct_coverage),
+            autosome_ratio: summary.autosome_ratio,
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct SexCheck {
+    x_autosome_ratio: Option<f64>,
+    y_autosome_ratio: Option<f64>,
+    x_y_ratio: Option<f64>,
+}
+
+#[derive(Serialize)]
+struct JsonReport<'a> {
+    input: &'a str,
+    #[serde(flatten)]
+    total: JsonMetrics<'a>,
+    sex_check: SexCheck,
+    chromosomes: Vec<JsonMetrics<'a>>,
+}
+
+fn write_json<W: Write>(
+    output: &mut W,
+    input: &str,
+    total: &Summary,
+    chroms: &[Summary],
+    thresholds: &[u32],
+    sex_check: [Option<f64>; 3],
+) -> AppResult<()> {
+    let report = JsonReport {
+        input,
+        total: JsonMetrics::new(total, None, thresholds),
+        sex_check: SexCheck {
+            x_autosome_ratio: sex_check[0],
+            y_autosome_ratio: sex_check[1],
+            x_y_ratio: sex_check[2],
+        },
+        chromosomes: chroms
+            .iter()
+            .map(|summary| JsonMetrics::new(summary, Some(&summary.name), thresholds))
+            .collect(),
+    };
+    serde_json::to_writer_pretty(&mut *output, &report)?;
+    writeln!(output)?;
+    Ok(())
+}
+
+fn write_tsv<W: Write>(
+    output: &mut W,
+    total: &Summary,
+    chroms: &[Summary],
+    thresholds: &[u32],
+    sex_check: [Option<f64>; 3],
+) -> AppResult<()> {
+    write!(
+        output,
+        "#name\tlength\tmean\tmedian\tfold_80_penalty\tpct_within_20pct_of_mean\tpct_dropout"
+    )?;
+    for threshold in thresholds {
+        write!(output, "\tpct_ge_{}", threshold)?;
+    }
+    writeln!(output, "\tautosome_ratio")?;
+    for summary in chroms.iter().chain(std::iter::once(total)) {
+        let value = |value| format_value(value, "NA");
+        write!(
+            output,
+            "{}\t{}\t{}\t{}\t{}\t{}\t{}",
+            summary.name,
+            summary.length,
+            value(summary.mean),
+            value(summary.median),
+            value(summary.fold_80_penalty),
+            value(summary.pct_within_20pct_of_mean),
+            value(summary.pct_dropout)
+        )?;
+        for &pct in summary.pct_coverage.iter() {
+            write!(output, "\t{}", value(pct))?;
+        }
+        writeln!(output, "\t{}", value(summary.autosome_ratio))?;
+    }
+    // The sex check metrics follow the table as comment lines
+    let names = ["x_autosome_ratio", "y_autosome_ratio", "x_y_ratio"];
+    for (name, ratio) in names.iter().zip(sex_check) {
+        writeln!(output, "#{}\t{}", name, format_value(ratio, "NA"))?;
+    }
+    Ok(())
+}
+
+pub fn entry_point(args: Vec<String>) -> AppResult<()> {
+    let yaml = load_yaml!("cli.yml");
+    let matches = App::from_yaml(yaml)
+        .version(d4tools::VERSION)
+        .get_matches_from(args);
+
+    setup_thread_pool(&matches)?;
+
+    let json = match matches.value_of("format").unwrap_or("json") {
+        "json" => true,
+        "tsv" => false,
+        other => return Err(format!("Unsupported report format: {}", other).into()),
+    };
+    let thresholds = matches
+        .value_of("thresholds")
+        .unwrap_or("1,10,20,30")
+        .split(',')
+        .map(|threshold| threshold.trim().parse())
+        .collect::<Result<Vec<u32>, _>>()?;
+    let min_depth: u32 = matches.value_of("min-depth").unwrap_or("1").parse()?;
+    let max_bin: f64 = matches.value_of("max-bin").unwrap_or("1000").parse()?;
+
+    let input = matches.value_of("input").unwrap();
+    let mut reader: D4TrackReader = D4TrackReader::open(input)?;
+    let chrom_list = reader.header().chrom_list().to_owned();
+    // The histogram and the depth thresholds are in the raw value unit, which is scaled by the
+    // denominator for the integer values
+    let scale = match reader.header().value_type() {
+        ValueType::Integer => reader.header().get_denominator(),
+        ValueType::Float => 1.0,
+    };
+    let num_bins = (max_bin * scale).ceil() as i32;
+    let raw_thresholds: Vec<u32> = std::iter::once(min_depth)
+        .chain(thresholds.iter().copied())
+        .map(|threshold| (threshold as f64 * scale).ceil() as u32)
+        .collect();
+
+    let mut chrom_regions: HashMap<String, Vec<(u32, u32)>> = HashMap::new();
+    if let Some(region_file) = matches.value_of("region") {
+        let chrom_sizes: HashMap<_, _> = chrom_list
+            .iter()
+            .map(|c| (c.name.as_str(), c.size as u32))
+            .collect();
+        for (chr, begin, end) in parse_region_file(region_file)? {
+            let size = match chrom_sizes.get(chr.as_str()) {
+                Some(&size) => size,
+                None => {
+                    warn!("Chromosome {} is missing from the input, skipped", chr);
+                    continue;
+                }
+            };
+            let end = end.min(size);
+            if begin < end {
+                chrom_regions.entry(chr).or_default().push((begin, end));
+            }
+        }
+    } else {
+        for chrom in chrom_list.iter() {
+            chrom_regions.insert(chrom.name.clone(), vec![(0, chrom.size as u32)]);
+        }
+    }
+
+    let mut chrom_coverage = Vec::new();
+    for chrom in chrom_list.iter() {
+        let regions = match chrom_regions.get(&chrom.name) {
+            Some(regions) => regions,
+            None => continue,
+        };
+        // The histogram, mean and coverage of each region are computed in one pass
+        let tasks: Vec<QcTask> = regions
+            .iter()
+            .map(|&(begin, end)| {
+                (
+                    Histogram::with_bin_range(&chrom.name, begin, end, 0..num_bins),
+                    Mean::new(&chrom.name, begin, end),
+                    PercentCov::new(&chrom.name, begin, end, raw_thresholds.clone()),
+                )
+            })
+            .collect();
+        let mut coverage = Coverage::new(num_bins as usize, raw_thresholds.len());
+        for part in QcTask::create_task(&mut reader, tasks)?.run().into_iter() {
+            coverage.add_region(part.end - part.begin, part.output);
+        }
+        chrom_coverage.push((chrom.name.as_str(), coverage));
+    }
+
+    let mut total = Coverage::new(num_bins as usize, raw_thresholds.len());
+    let mut autosomes = Coverage::new(0, 0);
+    for (name, coverage) in chrom_coverage.iter() {
+        total.merge(coverage);
+        if is_autosome(name) {
+            autosomes.merge(coverage);
+        }
+    }
+    let autosome_mean = autosomes.mean();
+    let sex_chrom_mean = |sex_chrom| {
+        chrom_coverage
+            .iter()
+            .find(|(name, _)| is_sex_chrom(name, sex_chrom))
+            .and_then(|(_, coverage)| coverage.mean())
+    };
+    let (x_mean, y_mean) = (sex_chrom_mean("X"), sex_chrom_mean("Y"));
+    let ratio = |a: Option<f64>, b: Option<f64>| {
+        a.zip(b)
+            .and_then(|(a, b)| if b > 0.0 { Some(a / b) } else { None })
+    };
+    let sex_check = [
+        ratio(x_mean, autosome_mean),
+        ratio(y_mean, autosome_mean),
+        ratio(x_mean, y_mean),
+    ];
+
+    let chroms: Vec<_> = chrom_coverage
+        .iter()
+        .map(|(name, coverage)| Summary::new(name, coverage, scale, autosome_mean))
+        .collect();
+    let total = Summary::new("total", &total, scale, autosome_mean);
+
+    let mut output = BufWriter::new(std::io::stdout());
+    if json {
+        write_json(&mut output, input, &total, &chroms, &thresholds, sex_check)?;
+    } else {
+        write_tsv(&mut output, &total, &chroms, &thresholds, sex_check)?;
+    }
+    output.flush()?;
+
+    Ok(())
+}
@@ -1,8 +1,8 @@
 use clap::{load_yaml, App};
 use d4::{
     find_tracks,
-    ssio::{http::HttpReader, D4TrackReader},
-    Chrom,
+    ssio::{http::HttpReader, D4MatrixReader, D4TrackReader},
+    Chrom, ValueType,
 };
 use d4_framefile::{Directory, OpenResult};
 use d4tools::AppResult;
@@ -14,25 +14,50 @@ use std::{
     io::{BufRead, BufReader, Error, ErrorKind, Read, Result as IOResult, Seek, Write},
     path::Path,
 };
+/// How the values of a track are printed
+#[derive(Clone, Copy)]
+enum ValueFormat {
+    Integer,
+    FixedPoint(f64),
+    Float(f64),
+}
+
+impl<R: Read + Seek> From<&D4TrackReader<R>> for ValueFormat {
+    fn from(reader: &D4TrackReader<R>) -> Self {
+        let header = reader.get_header();
+        match (header.value_type(), reader.get_denominator()) {
+            (ValueType::Float, _) => Self::Float(header.get_denominator()),
+            (ValueType::Integer, Some(denominator)) => Self::FixedPoint(denominator),
+            (ValueType::Integer, None) => Self::Integer,
+        }
+    }
+}
+
 fn write_bed_record_fast<W: Write>(
     mut writer: W,
     chr: &str,
     left: u32,
     right: u32,
     values: &[i32],
-    denominators: &[Option<f64>],
+    formats: &[ValueFormat],
 ) -> IOResult<()> {
     writer.write_all(chr.as_bytes())?;
     writer.write_all(b"\t")?;
     writer.write_all(left.to_string().as_bytes())?;
     writer.write_all(b"\t")?;
     writer.write_all(right.to_string().as_bytes())?;
-    for (value, denominator) in values.iter().zip(denominators.iter()) {
+    for (value, format) in values.iter().zip(formats.iter()) {
         writer.write_all(b"\t")?;
-        if let Some(denominator) = denominator {
-            writer.write_all((*value as f64 / denominator).to_string().as_bytes())?;
-        } else {
-            writer.write_all(value.to_string().as_bytes())?;
+        match format {
+            ValueFormat::Integer => writer.write_all(value.to_string().as_bytes())?,
+            ValueFormat::FixedPoint(denominator) => {
+                writer.write_all((*value as f64 / denominator).to_string().as_bytes())?
+            }
+            ValueFormat::Float(denominator) => {
+                // Print the shortest representation of the f32 value rather than the widened one
+                let value = (ValueType::Float.decode(*value) / denominator) as f32;
+                writer.write_all(value.to_string().as_bytes())?
+            }
         }
     }
     writer.write_all(b"\n")?;
@@ -97,77 +122,41 @@ fn flush_value<W: Write>(
     left: u32,
     right: u32,
     values: &[i32],
-    denominators: &[Option<f64>],
+    formats: &[ValueFormat],
     print_zeros: bool,
 ) -> IOResult<()> {
     if (print_zeros || values.iter().any(|&x| x != 0)) && left < right {
-        write_bed_record_fast(target, chr, left, right, values, denominators)?;
+        write_bed_record_fast(target, chr, left, right, values, formats)?;
     }
     Ok(())
 }
 
 fn show_region<R: Read + Seek>(
-    inputs: &mut [D4TrackReader<R>],
+    input: &mut D4MatrixReader<R>,
+    formats: &[ValueFormat],
     regions: &[(usize, u32, u32)],
     print_all_zero: bool,
 ) -> Result<(), Box<dyn std::error::Error>> {
-    if inputs.is_empty() {
+    if input.track_count() == 0 {
         return Ok(());
     }
 
     let mut stdout = std::io::stdout();
-    let denominators: Vec<_> = inputs.iter().map(|x| x.get_denominator()).collect();
 
     for &(cid, begin, end) in regions {
-        let chrom_list = inputs[0].chrom_list();
-        let chrom = chrom_list[cid].name.as_str().to_string();
-
-        let mut values = vec![0; inputs.len()];
-        let mut prev_values = vec![0; inputs.len()];
-
-        let mut views: Vec<_> = inputs
-            .iter_mut()
-            .map(|x| x.get_view(&chrom, begin, end).unwrap())
-            .collect();
-
-        let mut value_changed = false;
-        let mut last_pos = begin;
-
-        for pos in begin..end {
-            for (input_id, input) in views.iter_mut().enumerate() {
-                let (reported_pos, value) = input.next().unwrap()?;
-                assert_eq!(reported_pos, pos);
-                if values[input_id] != value {
-                    if !value_changed {
-                        prev_values.clone_from(&values);
-                        value_changed = true;
-                    }
-                    values[input_id] = value;
-                }
-            }
-            if value_changed {
-                flush_value(
-                    &mut stdout,
-                    &chrom,
-                    last_pos,
-                    pos,
-                    prev_values.as_slice(),
-                    denominators.as_slice(),
-                    print_all_zero,
-                )?;
-                last_pos = pos;
-                value_changed = false;
-            }
-        }
+        let chrom = input.chrom_list()[cid].name.clone();
+
+        let mut view = input.get_matrix_view(&chrom, begin, end)?;
 
-        if last_pos != end {
+        while let Some(row) = view.next_row() {
+            let (chrom, left, right, values) = row?;
             flush_value(
                 &mut stdout,
-                &chrom,
-                last_pos,
-                end,
-                values.as_slice(),
-                denominators.as_slice(),
+                chrom,
+                left,
+                right,
+                values,
+                formats,
                 print_all_zero,
             )?;
         }
@@ -274,7 +263,10 @@ fn show_impl<R: Read + Seek, I: Iterator<Item = String>>(
 
     let regions = parse_region_spec(regions, readers[0].chrom_list())?;
 
-    show_region(&mut readers, &regions, print_all_zero)
+    let formats: Vec<ValueFormat> = readers.iter().map(ValueFormat::from).collect();
+    let mut matrix = D4MatrixReader::new(readers)?;
+
+    show_region(&mut matrix, &formats, &regions, print_all_zero)
 }
 
 pub fn entry_point(args: Vec<String>) -> Result<(), Box<dyn std::error::Error>> {
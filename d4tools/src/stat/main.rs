@@ -2,11 +2,12 @@ use clap::{load_yaml, App, ArgMatches};
 
 use d4::{
     find_tracks,
-    index::{D4IndexCollection, Sum},
-    ssio::http::HttpReader,
-    task::{Histogram, Mean, PercentCov, SimpleTask, Task, TaskOutput},
+    index::{histogram_percentile, D4IndexCollection, DataSummary, MinMax, Sum, ValueHistogram},
+    ssio::{http::HttpReader, D4MatrixReader},
+    task::{Histogram, Mean, PercentCov, SimpleTask, Task, TaskOutput, ValueRange},
     Chrom, D4TrackReader,
 };
+use log::warn;
 
 use std::{
     borrow::{Borrow, Cow},
@@ -175,15 +176,20 @@ fn percentile_stat(
         }
         print!("{}\t{}\t{}", chr, begin, end);
         for ((below, hist, above), &denominator) in results.into_iter().zip(denominators.iter()) {
-            let count: u32 = below + hist.iter().sum::<u32>() + above;
-            let below_count = (count as f64 * percentile.clamp(0.0, 1.0)).round() as u32;
-            let mut current = below;
-            let mut idx = 0;
-            while current < below_count && idx < hist.len() {
-                current += hist[idx];
-                idx += 1;
-            }
-            print!("\t{}", idx as f64 / denominator.unwrap_or(1.0));
+            // Use the same rule as the histogram index, so the result doesn't depend on whether
+            // the index is available. The values out of the histogram range are reported as the
+            // closest bound of the range.
+            let value = histogram_percentile(below as u64, &hist, above as u64, percentile)
+                .unwrap_or_else(|| {
+                    let count = below + hist.iter().sum::<u32>() + above;
+                    let target = (count as f64 * percentile.clamp(0.0, 1.0)).round().max(1.0);
+                    if below as f64 >= target {
+                        0
+                    } else {
+                        hist.len() as i32
+                    }
+                });
+            print!("\t{}", value as f64 / denominator.unwrap_or(1.0));
         }
         println!();
     }
@@ -277,12 +283,12 @@ fn perc_cov_stat(
     Ok(())
 }
 
-fn mean_stat_index<R: Read + Seek>(
+fn stat_with_index<R: Read + Seek, S: DataSummary, F: Fn(&S, u32) -> Option<f64>>(
     mut reader: R,
     track: Option<&str>,
     print_header: bool,
     region_file: Option<&str>,
-    sum_only: bool,
+    summarize: F,
 ) -> Result<bool, Box<dyn std::error::Error>> {
     let mut tracks = Vec::new();
 
@@ -305,23 +311,54 @@ fn mean_stat_index<R: Read + Seek>(
         })
         .collect();
 
-    let mut index: Vec<_> = Vec::new();
-    for idx_obj in root_dir.iter().map(|root| {
-        let index = D4IndexCollection::from_root_container(root)?;
-        index.load_data_index::<Sum>()
+    // The index is only used if every track has one
+    if !root_dir.iter().all(|root| {
+        D4IndexCollection::from_root_container(root)
+            .is_ok_and(|collection| collection.has_data_index::<S>())
     }) {
-        if idx_obj.is_err() {
-            return Ok(false);
-        }
-        index.push(idx_obj.unwrap());
+        return Ok(false);
     }
 
-    let mut ssio_reader: Vec<_> = root_dir
+    let readers = root_dir
+        .iter()
+        .map(|root| d4::ssio::D4TrackReader::from_track_root(root.clone()))
+        .collect::<Result<Vec<_>, _>>()?;
+    let denominators: Vec<_> = readers
         .iter()
-        .map(|root| d4::ssio::D4TrackReader::from_track_root(root.clone()).unwrap())
+        .map(|reader| reader.get_denominator().unwrap_or(1.0))
         .collect();
+    let mut matrix = D4MatrixReader::new(readers)?;
+    let index = match matrix.load_data_index::<S>() {
+        Ok(index) => index,
+        Err(err) => {
+            warn!(
+                "Unable to load the {}, falling back to a full scan: {}",
+                S::INDEX_NAME,
+                err
+            );
+            return Ok(false);
+        }
+    };
 
-    let regions = parse_region_spec(region_file, ssio_reader[0].chrom_list())?;
+    let regions = parse_region_spec(region_file, matrix.chrom_list())?;
+
+    let mut results = Vec::with_capacity(regions.len());
+    for (chr, begin, end) in regions.iter() {
+        let summaries = matrix.query_data_index(&index, chr, *begin, *end)?;
+        let size = index[0]
+            .query(chr, *begin, *end)
+            .map_or(0, |res| res.query_size());
+        let mut values = Vec::with_capacity(summaries.len());
+        for (summary, denominator) in summaries.iter().zip(denominators.iter()) {
+            match summarize(summary, size) {
+                Some(value) => values.push(value / denominator),
+                // The result falls out of what the index tracks, e.g. a percentile in the
+                // overflow bins of the histogram index, so the file is scanned instead
+                None => return Ok(false),
+            }
+        }
+        results.push(values);
+    }
 
     if print_header {
         print!("#Chr\tBegin\tEnd");
@@ -335,17 +372,10 @@ fn mean_stat_index<R: Read + Seek>(
         println!();
     }
 
-    for (chr, begin, end) in regions {
+    for ((chr, begin, end), values) in regions.into_iter().zip(results) {
         print!("{}\t{}\t{}", chr, begin, end);
-        for (sum_index, ssio_reader) in index.iter().zip(ssio_reader.iter_mut()) {
-            let index_res = sum_index.query(chr.as_str(), begin, end).unwrap();
-            let sum_res = index_res.get_result(ssio_reader)?;
-            let value = if sum_only {
-                sum_res.sum()
-            } else {
-                sum_res.mean(index_res.query_size())
-            };
-            print!("\t{}", value / ssio_reader.get_denominator().unwrap_or(1.0));
+        for value in values {
+            print!("\t{}", value);
         }
         println!();
     }
@@ -353,6 +383,50 @@ fn mean_stat_index<R: Read + Seek>(
     Ok(true)
 }
 
+fn stat_index_dispatch<R: Read + Seek>(
+    reader: R,
+    track: Option<&str>,
+    print_header: bool,
+    region_file: Option<&str>,
+    stat: Option<&str>,
+) -> Result<bool, Box<dyn std::error::Error>> {
+    match stat {
+        Some("sum") => stat_with_index(reader, track, print_header, region_file, |s: &Sum, _| {
+            Some(s.sum())
+        }),
+        Some("min") => {
+            stat_with_index(reader, track, print_header, region_file, |r: &MinMax, _| {
+                Some(r.min() as f64)
+            })
+        }
+        Some("max") => {
+            stat_with_index(reader, track, print_header, region_file, |r: &MinMax, _| {
+                Some(r.max() as f64)
+            })
+        }
+        Some("median") => stat_with_index(
+            reader,
+            track,
+            print_header,
+            region_file,
+            |h: &ValueHistogram, _| h.percentile(0.5).map(|v| v as f64),
+        ),
+        Some(whatever) if whatever.starts_with("percentile=") => {
+            let percentile: f64 = whatever["percentile=".len()..].parse()?;
+            stat_with_index(
+                reader,
+                track,
+                print_header,
+                region_file,
+                |h: &ValueHistogram, _| h.percentile(percentile / 100.0).map(|v| v as f64),
+            )
+        }
+        _ => stat_with_index(reader, track, print_header, region_file, |s: &Sum, size| {
+            Some(s.mean(size))
+        }),
+    }
+}
+
 pub fn entry_point(args: Vec<String>) -> Result<(), Box<dyn std::error::Error>> {
     let yaml = load_yaml!("cli.yml");
     let matches = App::from_yaml(yaml)
@@ -398,12 +472,17 @@ pub fn entry_point(args: Vec<String>) -> Result<(), Box<dyn std::error::Error>>
             || matches.value_of("stat") == Some("avg")
             || matches.value_of("stat") == Some("sum")
             || matches.value_of("stat") == Some("count")
+            || matches.value_of("stat") == Some("min")
+            || matches.value_of("stat") == Some("max")
+            || matches.value_of("stat") == Some("median")
+            || matches
+                .value_of("stat")
+                .is_some_and(|s| s.starts_with("percentile="))
             || !matches.is_present("stat"))
         && matches.values_of("input").unwrap().len() == 1
     {
         let path = matches.value_of("input").unwrap();
         let region_file = matches.value_of("region");
-        let sum_only = matches.value_of("stat") == Some("sum");
         if path.starts_with("http://") || path.starts_with("https://") {
             let (url, track) = if let Some(pos) = path.rfind('#') {
                 (&path[..pos], Some(&path[pos + 1..]))
@@ -411,12 +490,12 @@ pub fn entry_point(args: Vec<String>) -> Result<(), Box<dyn std::error::Error>>
                 (path, None)
             };
             let reader = HttpReader::new(url)?;
-            if mean_stat_index(
+            if stat_index_dispatch(
                 reader,
                 track,
                 matches.is_present("header"),
                 region_file,
-                sum_only,
+                matches.value_of("stat"),
             )? {
                 return Ok(());
             }
@@ -427,12 +506,12 @@ pub fn entry_point(args: Vec<String>) -> Result<(), Box<dyn std::error::Error>>
                 (path, None)
             };
             let reader = File::open(path)?;
-            if mean_stat_index(
+            if stat_index_dispatch(
                 reader,
                 track,
                 matches.is_present("header"),
                 region_file,
-                sum_only,
+                matches.value_of("stat"),
             )? {
                 return Ok(());
             }
@@ -478,7 +557,7 @@ pub fn entry_point(args: Vec<String>) -> Result<(), Box<dyn std::error::Error>>
             }
             let mut tags = Vec::new();
             let mut denoms = Vec::new();
-            for result in run_task::<d4::task::Sum>(matches, &mut tags, &mut denoms)? {
+            for result in run_task::<d4::task::FloatSum>(matches, &mut tags, &mut denoms)? {
                 if !header_printed {
                     for tag in tags.iter() {
                         print!("\t{}", tag);
@@ -488,7 +567,30 @@ pub fn entry_point(args: Vec<String>) -> Result<(), Box<dyn std::error::Error>>
                 }
                 print!("{}\t{}\t{}", result.chrom, result.begin, result.end);
                 for (value, denom) in result.output.into_iter().zip(denoms.iter()) {
-                    print!("\t{}", value as f64 / denom.unwrap_or(1.0))
+                    print!("\t{}", value / denom.unwrap_or(1.0))
+                }
+                println!();
+            }
+        }
+        Some(what @ "min") | Some(what @ "max") => {
+            let use_min = what == "min";
+            if !header_printed {
+                print!("#Chr\tBegin\tEnd");
+            }
+            let mut tags = Vec::new();
+            let mut denoms = Vec::new();
+            for result in run_task::<ValueRange>(matches, &mut tags, &mut denoms)? {
+                if !header_printed {
+                    for tag in tags.iter() {
+                        print!("\t{}", tag);
+                    }
+                    println!();
+                    header_printed = true;
+                }
+                print!("{}\t{}\t{}", result.chrom, result.begin, result.end);
+                for ((min, max), denom) in result.output.into_iter().zip(denoms.iter()) {
+                    let value = if use_min { min } else { max };
+                    print!("\t{}", value / denom.unwrap_or(1.0))
                 }
                 println!();
             }
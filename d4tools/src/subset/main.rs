@@ -0,0 +1,39 @@
+use clap::{load_yaml, App};
+use d4::D4FileSubset;
+use d4tools::{parse_region_file, AppResult};
+
+pub fn entry_point(args: Vec<String>) -> AppResult<()> {
+    let yaml = load_yaml!("cli.yml");
+    let matches = App::from_yaml(yaml)
+        .version(d4tools::VERSION)
+        .get_matches_from(args);
+
+    let input = matches.value_of("input-file").unwrap();
+    let output = matches.value_of("output-file").unwrap();
+
+    let mut subset = D4FileSubset::new(input, output).zero_fill(matches.is_present("zero-fill"));
+
+    if let Some(tracks) = matches.values_of("track") {
+        for track in tracks {
+            subset = subset.add_track(track);
+        }
+    }
+
+    if let Some(chroms) = matches.values_of("chrom") {
+        subset = subset.select_chroms(chroms);
+    }
+
+    if let Some(region_file) = matches.value_of("region") {
+        let regions = parse_region_file(region_file)?;
+        if regions.is_empty() {
+            return Err("No valid region found in the region file".into());
+        }
+        for (chrom, begin, end) in regions {
+            subset = subset.add_region(&chrom, begin, end);
+        }
+    }
+
+    subset.subset()?;
+
+    Ok(())
+}
@@ -0,0 +1,37 @@
+use clap::{load_yaml, App};
+use d4::D4FileUpdater;
+use d4tools::{parse_bed_file, parse_genome_file, AppResult};
+
+pub fn entry_point(args: Vec<String>) -> AppResult<()> {
+    let yaml = load_yaml!("cli.yml");
+    let matches = App::from_yaml(yaml)
+        .version(d4tools::VERSION)
+        .get_matches_from(args);
+
+    if !matches.is_present("values") && !matches.is_present("append-chroms") {
+        return Err("Nothing to update, either --values or --append-chroms is required".into());
+    }
+
+    let mut updater = D4FileUpdater::new(matches.value_of("input-file").unwrap());
+
+    if let Some(tracks) = matches.values_of("track") {
+        for track in tracks {
+            updater = updater.add_track(track);
+        }
+    }
+
+    if let Some(genome_file) = matches.value_of("append-chroms") {
+        updater = updater.append_chrom(parse_genome_file(genome_file)?.into_iter());
+    }
+
+    if let Some(values_file) = matches.value_of("values") {
+        for record in parse_bed_file(values_file)? {
+            let (chrom, begin, end, value) = record?;
+            updater = updater.set_value(&chrom, begin, end, value);
+        }
+    }
+
+    updater.update()?;
+
+    Ok(())
+}
@@ -191,11 +191,20 @@ class D4Builder(D4BuilderImpl):
         self.output_path = output_path
         self.index = ""
 
-    def enable_secondary_table_compression(self, level=5):
+    def enable_secondary_table_compression(self, level=5, method="deflate"):
         """
-        Enable the secondary table compression for the d4 file to created
+        Enable the secondary table compression for the d4 file to created.
+        The method can be "deflate", "zstd" or "lz4".
         """
-        self.set_compression(level)
+        self.set_compression(level, method)
+        return self
+
+    def enable_primary_table_compression(self, level=5, method="deflate"):
+        """
+        Store the primary table of the d4 file to created as independently compressed blocks.
+        The method can be "deflate", "zstd" or "lz4".
+        """
+        self.set_primary_table_compression(level, method)
         return self
 
     def set_dict_bits(self, n):
@@ -206,6 +215,15 @@ class D4Builder(D4BuilderImpl):
         self.dict_range(0, 1 << n)
         return self
 
+    def profile_dict(self, intervals):
+        """
+        Choose the dictionary by sampling the (chr, begin, end, value) intervals to encode,
+        the bases that are not covered by any interval are 0.
+        Note: the sequences should be added before profiling.
+        """
+        self.dict_from_intervals(list(intervals))
+        return self
+
     def add_sequence(self, chr, size):
         """
         Add a new sequence/chromosome to the given file
@@ -602,6 +620,8 @@ class D4File(D4FileImpl):
             buf_ptr = buf.ctypes.data_as(ctypes.POINTER(ctypes.c_uint32))
             buf_addr = ctypes.cast(buf_ptr, ctypes.c_void_p).value
             self.load_values_to_buffer(name, begin, end, buf_addr)
+            if self.is_float():
+                buf = buf.view(numpy.float32)
             denom = self.get_denominator()
             if denom == 1.0:
                 return buf
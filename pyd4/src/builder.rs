@@ -1,25 +1,48 @@
 use d4::{
-    index::D4IndexCollection, ptab::PTablePartitionWriter, stab::SecondaryTablePartWriter, Chrom,
-    D4FileBuilder, D4FileMerger, D4FileWriter, D4FileWriterExt, Dictionary,
+    index::D4IndexCollection,
+    ptab::{
+        BitArrayWriter, CompressedBitArrayWriter, Encoder, PTablePartitionWriter,
+        PrimaryTableWriter,
+    },
+    stab::{CompressionMethod, SecondaryTablePartWriter},
+    Chrom, D4FileBuilder, D4FileMerger, D4FileWriter, D4FileWriterExt, Dictionary,
+};
+use pyo3::{
+    exceptions::{PyKeyError, PyValueError},
+    prelude::*,
 };
-use pyo3::{exceptions::PyKeyError, prelude::*};
 use rayon::prelude::*;
 
 #[pyclass(subclass)]
 pub struct D4Builder {
     genome_size: Vec<(String, usize)>,
     dictionary: Dictionary,
-    compression: Option<u32>,
+    compression: Option<CompressionMethod>,
+    primary_compression: Option<CompressionMethod>,
 }
 
-struct WriterPartHandle {
+fn parse_compression_method(level: i32, method: Option<&str>) -> PyResult<CompressionMethod> {
+    Ok(match method.unwrap_or("deflate") {
+        "deflate" => CompressionMethod::Deflate(level as u32),
+        "zstd" => CompressionMethod::Zstd(level),
+        "lz4" => CompressionMethod::Lz4,
+        other => {
+            return Err(PyValueError::new_err(format!(
+                "Unknown compression method {}",
+                other
+            )))
+        }
+    })
+}
+
+struct WriterPartHandle<PT: PrimaryTableWriter> {
     chrom: String,
     end: u32,
     frontier: u32,
-    inner: <D4FileWriter as D4FileWriterExt>::Partition,
+    inner: <D4FileWriter<PT> as D4FileWriterExt>::Partition,
 }
 
-impl WriterPartHandle {
+impl<PT: PrimaryTableWriter> WriterPartHandle<PT> {
     fn flush(&mut self) -> PyResult<()> {
         let mut p_enc = self.inner.0.make_encoder();
         for pos in self.frontier..self.end {
@@ -28,6 +51,7 @@ impl WriterPartHandle {
             }
         }
         self.inner.1.finish()?;
+        self.inner.0.finish()?;
         Ok(())
     }
     fn encode(&mut self, begin: u32, data: &[i32]) -> PyResult<()> {
@@ -110,10 +134,68 @@ impl D4Merger {
     }
 }
 
+/// The writer and its partitions, the primary table of the file may be block compressed
+enum WriterImpl {
+    BitArray(D4FileWriter, Vec<WriterPartHandle<BitArrayWriter>>),
+    Compressed(
+        D4FileWriter<CompressedBitArrayWriter>,
+        Vec<WriterPartHandle<CompressedBitArrayWriter>>,
+    ),
+}
+
+fn split_writer<PT: PrimaryTableWriter>(
+    writer: &mut D4FileWriter<PT>,
+) -> PyResult<Vec<WriterPartHandle<PT>>> {
+    Ok(writer
+        .parallel_parts(Some(100_0000))?
+        .into_iter()
+        .map(|(p, s)| {
+            let (chr, begin, end) = p.region();
+            let frontier = begin;
+            WriterPartHandle {
+                chrom: chr.to_string(),
+                end,
+                frontier,
+                inner: (p, s),
+            }
+        })
+        .collect())
+}
+
+fn finish_writer<PT: PrimaryTableWriter>(
+    writer: D4FileWriter<PT>,
+    parts: Vec<WriterPartHandle<PT>>,
+) -> PyResult<()> {
+    parts
+        .into_par_iter()
+        .try_for_each(|mut part| part.flush())?;
+    writer.finish()?;
+    Ok(())
+}
+
+fn write_parts<PT: PrimaryTableWriter>(
+    parts: &mut [WriterPartHandle<PT>],
+    chr: &str,
+    start_pos: u32,
+    data_addr: i64,
+    count: usize,
+) -> PyResult<()> {
+    let active_parts: Vec<_> = parts
+        .iter_mut()
+        .filter_map(|part| unsafe {
+            part.get_data_view(chr, start_pos, data_addr as *const i32, count)
+                .map(|view| (part, view))
+        })
+        .collect();
+    active_parts.into_par_iter().try_for_each(|(part, view)| {
+        let data = unsafe { std::slice::from_raw_parts(view.1 as *const i32, view.2) };
+        part.encode(view.0, data)
+    })
+}
+
 #[pyclass(subclass)]
 pub struct D4Writer {
-    writer_obj: Option<D4FileWriter>,
-    parts: Vec<WriterPartHandle>,
+    inner: Option<WriterImpl>,
     index_option: IndexFlavor,
 }
 
@@ -127,11 +209,11 @@ impl D4Writer {
     /// The output may be incompleted until this method gets called.
     /// If you want to make sure the output is completed, you can explicitly call this function.
     fn close(&mut self) -> PyResult<()> {
-        let parts = std::mem::replace(&mut self.parts, Vec::new());
-        parts
-            .into_par_iter()
-            .for_each(|mut part| part.flush().unwrap());
-        self.writer_obj.take();
+        match self.inner.take() {
+            Some(WriterImpl::BitArray(writer, parts)) => finish_writer(writer, parts)?,
+            Some(WriterImpl::Compressed(writer, parts)) => finish_writer(writer, parts)?,
+            None => (),
+        }
         match &self.index_option {
             IndexFlavor::Sum(path) => {
                 let mut ic = D4IndexCollection::open_for_write(path)?;
@@ -144,19 +226,15 @@ impl D4Writer {
     }
 
     fn write(&mut self, chr: &str, start_pos: u32, data_addr: i64, count: usize) -> PyResult<()> {
-        let active_parts: Vec<_> = self
-            .parts
-            .iter_mut()
-            .filter_map(|part| unsafe {
-                part.get_data_view(chr, start_pos, data_addr as *const i32, count)
-                    .and_then(|view| Some((part, view)))
-            })
-            .collect();
-        active_parts.into_par_iter().for_each(|(part, view)| {
-            let data = unsafe { std::slice::from_raw_parts(view.1 as *const i32, view.2) };
-            part.encode(view.0, data).unwrap();
-        });
-        Ok(())
+        match self.inner.as_mut() {
+            Some(WriterImpl::BitArray(_, parts)) => {
+                write_parts(parts, chr, start_pos, data_addr, count)
+            }
+            Some(WriterImpl::Compressed(_, parts)) => {
+                write_parts(parts, chr, start_pos, data_addr, count)
+            }
+            None => Err(PyValueError::new_err("Unable to write a closed D4 file")),
+        }
     }
 }
 
@@ -173,6 +251,7 @@ impl D4Builder {
             genome_size: Vec::new(),
             dictionary,
             compression: None,
+            primary_compression: None,
         })
     }
     /// dict_range(low, high)
@@ -184,6 +263,15 @@ impl D4Builder {
         self.dictionary = Dictionary::new_simple_range_dict(low, high)?;
         Ok(())
     }
+    /// dict_from_intervals(intervals)
+    /// --
+    ///
+    /// Determine the primary table dictionary by sampling a list of (chrom, begin, end, value)
+    /// intervals, the uncovered bases are 0. Only the sequences that have been added are profiled.
+    fn dict_from_intervals(&mut self, intervals: Vec<(String, u32, u32, i32)>) -> PyResult<()> {
+        self.dictionary = Dictionary::from_sample_intervals(&self.genome_size, intervals)?;
+        Ok(())
+    }
     /// add_seq(name, size)
     /// --
     ///
@@ -218,16 +306,30 @@ impl D4Builder {
             .collect();
         Ok(())
     }
-    /// set_compression(level)
+    /// set_compression(level, method)
     /// --
     ///
-    /// Set the compression level of the secondary table
-    fn set_compression(&mut self, level: i32) -> PyResult<()> {
+    /// Set the compression level of the secondary table, the method can be "deflate" (default),
+    /// "zstd" or "lz4"
+    fn set_compression(&mut self, level: i32, method: Option<&str>) -> PyResult<()> {
         if level < 0 {
             self.compression = None;
-        } else {
-            self.compression = Some(level as u32);
+            return Ok(());
         }
+        self.compression = Some(parse_compression_method(level, method)?);
+        Ok(())
+    }
+    /// set_primary_table_compression(level, method)
+    /// --
+    ///
+    /// Store the primary table as independently compressed blocks, the method can be "deflate"
+    /// (default), "zstd" or "lz4". A negative level disables the primary table compression.
+    fn set_primary_table_compression(&mut self, level: i32, method: Option<&str>) -> PyResult<()> {
+        self.primary_compression = if level < 0 {
+            None
+        } else {
+            Some(parse_compression_method(level, method)?)
+        };
         Ok(())
     }
     /// into_writer(path)
@@ -235,35 +337,35 @@ impl D4Builder {
     ///
     /// Build the D4 file from the writer class.
     fn into_writer(&mut self, path: &str, flavor: &str) -> PyResult<D4Writer> {
-        let mut writer: D4FileWriter = D4FileBuilder::new(path)
+        let mut builder = D4FileBuilder::new(path);
+        builder
             .set_dictionary(self.dictionary.clone())
             .append_chrom(self.genome_size.iter().map(|(name, size)| Chrom {
                 name: name.to_string(),
                 size: *size,
-            }))
-            .create()?;
-
-        if let Some(level) = self.compression {
-            writer.enable_secondary_table_compression(level);
-        }
+            }));
 
-        let parts = writer
-            .parallel_parts(Some(100_0000))?
-            .into_iter()
-            .map(|(p, s)| {
-                let (chr, begin, end) = p.region();
-                let frontier = begin;
-                WriterPartHandle {
-                    chrom: chr.to_string(),
-                    end,
-                    frontier,
-                    inner: (p, s),
+        let inner = match self.primary_compression {
+            None => {
+                let mut writer: D4FileWriter = builder.create()?;
+                if let Some(method) = self.compression {
+                    writer.set_secondary_table_compression(method);
                 }
-            })
-            .collect();
+                let parts = split_writer(&mut writer)?;
+                WriterImpl::BitArray(writer, parts)
+            }
+            Some(primary_method) => {
+                let mut writer: D4FileWriter<CompressedBitArrayWriter> = builder.create()?;
+                if let Some(method) = self.compression {
+                    writer.set_secondary_table_compression(method);
+                }
+                writer.set_primary_table_compression(primary_method)?;
+                let parts = split_writer(&mut writer)?;
+                WriterImpl::Compressed(writer, parts)
+            }
+        };
         Ok(D4Writer {
-            writer_obj: Some(writer),
-            parts,
+            inner: Some(inner),
             index_option: if flavor == "sum" {
                 IndexFlavor::Sum(path.to_string())
             } else {
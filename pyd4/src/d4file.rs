@@ -2,7 +2,7 @@ use crate::ReaderWrapper;
 use d4::ptab::{DecodeResult, Decoder};
 use d4::stab::SecondaryTablePartReader;
 use d4::task::{Histogram, Mean, Task, TaskContext};
-use d4::Chrom;
+use d4::{Chrom, ValueType};
 use pyo3::prelude::*;
 use pyo3::types::{PyInt, PyList, PyString, PyTuple};
 use rayon::prelude::*;
@@ -86,6 +86,19 @@ impl D4File {
         }
     }
 
+    /// is_float()
+    /// --
+    ///
+    /// Check if the values in this track are 32-bit floats
+    pub fn is_float(&self) -> PyResult<bool> {
+        let value_type = if self.is_remote_file()? {
+            self.open()?.into_remote_reader()?.get_header().value_type()
+        } else {
+            self.open()?.into_local_reader()?.header().value_type()
+        };
+        Ok(value_type == ValueType::Float)
+    }
+
     /// list_tracks()
     /// --
     ///
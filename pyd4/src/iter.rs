@@ -1,7 +1,7 @@
 use d4::ptab::DecodeResult;
 use d4::ssio::{http::HttpReader, D4TrackReader as RemoteReader};
 use d4::stab::SecondaryTablePartReader;
-use d4::D4TrackReader;
+use d4::{D4TrackReader, ValueType};
 use pyo3::iter::IterNextOutput;
 use pyo3::prelude::*;
 use std::io::Result;
@@ -11,6 +11,7 @@ use std::io::Result;
 pub struct D4Iter {
     _inner: Option<D4TrackReader>,
     iter: Box<dyn Iterator<Item = i32> + Send + 'static>,
+    value_type: ValueType,
 }
 
 impl D4Iter {
@@ -22,6 +23,7 @@ impl D4Iter {
     ) -> PyResult<Self> {
         Ok(Self {
             _inner: None,
+            value_type: inner.get_header().value_type(),
             iter: Box::new(inner.get_view(chr, left, right)?.map(|res| {
                 if let Ok((_, value)) = res {
                     value
@@ -37,6 +39,7 @@ impl D4Iter {
         left: u32,
         right: u32,
     ) -> PyResult<Self> {
+        let value_type = inner.header().value_type();
         let partition = inner.split(None)?;
 
         let chr = chr.to_string();
@@ -67,16 +70,20 @@ impl D4Iter {
         Ok(D4Iter {
             _inner: Some(inner),
             iter: Box::new(iter),
+            value_type,
         })
     }
     fn __iter__(slf: PyRefMut<Self>) -> Result<PyRefMut<Self>> {
         Ok(slf)
     }
-    fn __next__(mut slf: PyRefMut<Self>) -> IterNextOutput<i32, &'static str> {
-        if let Some(next) = slf.iter.next() {
-            IterNextOutput::Yield(next)
-        } else {
-            IterNextOutput::Return("Ended")
+    fn __next__(mut slf: PyRefMut<Self>) -> IterNextOutput<PyObject, &'static str> {
+        let py = slf.py();
+        match slf.iter.next() {
+            Some(next) if slf.value_type == ValueType::Float => {
+                IterNextOutput::Yield(slf.value_type.decode(next).into_py(py))
+            }
+            Some(next) => IterNextOutput::Yield(next.into_py(py)),
+            None => IterNextOutput::Return("Ended"),
         }
     }
 }
\ No newline at end of file